@@ -31,3 +31,13 @@ pub fn clear() {
         }
     }
 }
+
+/// Raise the machine software interrupt of `hart_idx`
+pub fn send_soft(hart_idx: usize) {
+    unsafe { (*CLINT.load(Ordering::Relaxed)).set_msip(hart_idx) };
+}
+
+/// Clear the machine software interrupt of the current hart
+pub fn clear_soft() {
+    unsafe { (*CLINT.load(Ordering::Relaxed)).clear_msip(hart_id()) };
+}
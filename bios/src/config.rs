@@ -14,3 +14,5 @@ pub const SUPERVISOR_ENTRY: usize = 0x8020_0000;
 
 pub const LEN_STACK_PER_HART: usize = 16 * 1024;
 pub const NUM_HART_MAX: usize = 8;
+/// The hart that enters the supervisor on boot, which starts the others
+pub const BOOT_HART_ID: usize = 0;
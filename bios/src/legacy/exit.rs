@@ -15,10 +15,15 @@ pub(crate) mod exit_values {
 }
 
 pub(crate) fn sbi_shutdown() -> ! {
+    finish(QEMU_EXIT_SUCC)
+}
+
+/// Write `value` to the test device of QEMU, which exits or resets the machine
+pub(crate) fn finish(value: u32) -> ! {
     unsafe {
         asm!(
             "sw {0}, 0({1})",
-            in(reg) QEMU_EXIT_SUCC,
+            in(reg) value,
             in(reg) QEMU_EXIT_ADDR
         );
     }
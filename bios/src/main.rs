@@ -5,16 +5,22 @@
 mod clint;
 mod config;
 mod legacy;
+mod sbi;
 mod stack;
 mod trap;
 mod utils;
 
+use crate::config::BOOT_HART_ID;
 use crate::utils::hart_id;
 use crate::utils::riscv_spec::{mepc, mie, mstatus};
 use utils::set_pmp;
 use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
 use trap::{trap_return, trap_vec};
 
+/// Set by the boot hart once `.bss` is cleared, before which the other harts must not touch any static
+static BSS_READY: AtomicBool = AtomicBool::new(false);
+
 #[naked]
 #[no_mangle]
 #[link_section = ".text.entry"]
@@ -31,9 +37,20 @@ unsafe extern "C" fn _start() -> ! {
     )
 }
 
+/// Initialize the current hart
+///
+/// Every hart enters the firmware at the same time. The boot hart goes on into the supervisor,
+/// while the others stay stopped until the supervisor starts them through the HSM extension.
 extern "C" fn rust_main() {
-    utils::init_bss();
-    utils::init_uart();
+    if hart_id() == BOOT_HART_ID {
+        utils::init_bss();
+        utils::init_uart();
+        BSS_READY.store(true, Ordering::Release);
+    } else {
+        while !BSS_READY.load(Ordering::Acquire) {
+            core::hint::spin_loop();
+        }
+    }
     set_pmp();
     stack::trap_stack_init();
     clint::clear();
@@ -51,4 +68,9 @@ extern "C" fn rust_main() {
         medeleg::clear_machine_env_call();
         mtvec::write(trap_vec as _, mtvec::TrapMode::Vectored);
     }
+    if hart_id() == BOOT_HART_ID {
+        sbi::hsm::boot_hart_started();
+    } else {
+        sbi::hsm::wait_for_start();
+    }
 }
\ No newline at end of file
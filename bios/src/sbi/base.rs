@@ -0,0 +1,29 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use riscv::register::{marchid, mimpid, mvendorid};
+use sbi_spec::base::*;
+
+use super::{is_supported, SbiRet};
+
+/// SBI specification version 1.0, with the major version in bits 24..31
+const SBI_SPEC_VERSION: usize = 1 << 24;
+/// Implementation id of RustSBI
+const SBI_IMPL_ID: usize = 4;
+/// Version 0.2 of this firmware
+const SBI_IMPL_VERSION: usize = 0x0002;
+
+pub fn handle(fid: usize, param: usize) -> SbiRet {
+    match fid {
+        GET_SBI_SPEC_VERSION => SbiRet::success(SBI_SPEC_VERSION),
+        GET_SBI_IMPL_ID => SbiRet::success(SBI_IMPL_ID),
+        GET_SBI_IMPL_VERSION => SbiRet::success(SBI_IMPL_VERSION),
+        PROBE_EXTENSION => SbiRet::success(is_supported(param) as usize),
+        GET_MVENDORID => SbiRet::success(mvendorid::read().map_or(0, |r| r.bits())),
+        GET_MARCHID => SbiRet::success(marchid::read().map_or(0, |r| r.bits())),
+        GET_MIMPID => SbiRet::success(mimpid::read().map_or(0, |r| r.bits())),
+        _ => SbiRet::not_supported(),
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use riscv::register::mip;
+use sbi_spec::binary::RET_ERR_ALREADY_AVAILABLE;
+use spin::Mutex;
+
+use super::{ipi, SbiRet};
+use crate::config::NUM_HART_MAX;
+use crate::trap::trap_return;
+use crate::utils::hart_id;
+use crate::utils::riscv_spec::mstatus;
+use crate::{clint, stack::trap_context};
+
+/// Hart states reported by `hart_get_status`
+const HART_STATE_STARTED: usize = 0;
+const HART_STATE_STOPPED: usize = 1;
+const HART_STATE_START_PENDING: usize = 2;
+
+#[derive(Clone, Copy)]
+enum HartState {
+    /// The hart does not exist, or has not entered the firmware yet
+    Unavailable,
+    Started,
+    Stopped,
+    /// Another hart has asked the hart to enter the supervisor at `start_addr`
+    StartPending { start_addr: usize, opaque: usize },
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const HART_UNAVAILABLE: Mutex<HartState> = Mutex::new(HartState::Unavailable);
+
+static HART_STATES: [Mutex<HartState>; NUM_HART_MAX] = [HART_UNAVAILABLE; NUM_HART_MAX];
+
+/// Mark the boot hart, which enters the supervisor without being started, as started
+pub fn boot_hart_started() {
+    *HART_STATES[hart_id()].lock() = HartState::Started;
+}
+
+pub fn is_started(hartid: usize) -> bool {
+    HART_STATES
+        .get(hartid)
+        .is_some_and(|state| matches!(*state.lock(), HartState::Started))
+}
+
+pub fn hart_start(hartid: usize, start_addr: usize, opaque: usize) -> SbiRet {
+    let Some(state) = HART_STATES.get(hartid) else {
+        return SbiRet::invalid_param();
+    };
+    let mut state = state.lock();
+    match *state {
+        HartState::Stopped => {
+            *state = HartState::StartPending { start_addr, opaque };
+            drop(state);
+            clint::send_soft(hartid);
+            SbiRet::success(0)
+        }
+        HartState::Unavailable => SbiRet::invalid_param(),
+        _ => SbiRet::error(RET_ERR_ALREADY_AVAILABLE),
+    }
+}
+
+/// Stop the calling hart, which returns to the supervisor only after being started again
+pub fn hart_stop() -> ! {
+    clint::clear();
+    wait_for_start();
+    trap_return()
+}
+
+pub fn hart_get_status(hartid: usize) -> SbiRet {
+    match HART_STATES.get(hartid).map(|state| *state.lock()) {
+        Some(HartState::Started) => SbiRet::success(HART_STATE_STARTED),
+        Some(HartState::Stopped) => SbiRet::success(HART_STATE_STOPPED),
+        Some(HartState::StartPending { .. }) => SbiRet::success(HART_STATE_START_PENDING),
+        _ => SbiRet::invalid_param(),
+    }
+}
+
+/// Park the current hart in the stopped state until another hart calls `hart_start` on it
+///
+/// The hart sleeps with `wfi` and is woken by the machine software interrupt sent by
+/// `hart_start`. On return the trap context enters the supervisor at the requested address,
+/// with the hart id in `a0`, the opaque value in `a1`, paging off and interrupts disabled.
+pub fn wait_for_start() {
+    let hartid = hart_id();
+    *HART_STATES[hartid].lock() = HartState::Stopped;
+    let (start_addr, opaque) = loop {
+        unsafe { riscv::asm::wfi() };
+        clint::clear_soft();
+        // Requests sent before the hart stopped need no more handling than an acknowledgement
+        ipi::handle_requests();
+        let mut state = HART_STATES[hartid].lock();
+        if let HartState::StartPending { start_addr, opaque } = *state {
+            *state = HartState::Started;
+            break (start_addr, opaque);
+        }
+    };
+    unsafe {
+        mip::clear_ssoft();
+        mip::clear_stimer();
+    }
+    mstatus::update(|bits| *bits &= !mstatus::SIE);
+    let ctx = trap_context();
+    ctx.pc = start_addr;
+    ctx.satp = 0;
+    ctx.regs[10] = hartid;
+    ctx.regs[11] = opaque;
+}
@@ -0,0 +1,116 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Requests between harts, delivered through the machine software interrupt
+//!
+//! The sender posts its request in the mailbox of the target hart and raises the `msip` of the
+//! target, whose `msoft` handler performs the request. A supervisor IPI is forwarded by setting
+//! `sip.SSIP`; remote fences are waited for until every target has done them.
+
+use bitflags::bitflags;
+use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use riscv::register::mip;
+
+use super::{hsm::is_started, SbiRet};
+use crate::clint;
+use crate::config::NUM_HART_MAX;
+use crate::utils::hart_id;
+
+bitflags! {
+    struct Request: usize {
+        const IPI = 1 << 0;
+        const FENCE_I = 1 << 1;
+        const SFENCE_VMA = 1 << 2;
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_MAILBOX: AtomicUsize = AtomicUsize::new(0);
+
+static MAILBOXES: [AtomicUsize; NUM_HART_MAX] = [EMPTY_MAILBOX; NUM_HART_MAX];
+
+/// Collect the harts selected by `hart_mask` and `hart_mask_base` into a bit set of hart ids
+///
+/// A base of `usize::MAX` selects every started hart. Selecting a hart that is not started is an
+/// invalid parameter.
+fn target_harts(hart_mask: usize, hart_mask_base: usize) -> Option<usize> {
+    if hart_mask_base == usize::MAX {
+        return Some(
+            (0..NUM_HART_MAX)
+                .filter(|&hart| is_started(hart))
+                .fold(0, |set, hart| set | 1 << hart),
+        );
+    }
+    let mut targets = 0;
+    for bit in (0..usize::BITS as usize).filter(|bit| hart_mask & 1 << bit != 0) {
+        let hart = hart_mask_base.checked_add(bit)?;
+        if !is_started(hart) {
+            return None;
+        }
+        targets |= 1 << hart;
+    }
+    Some(targets)
+}
+
+/// Post `request` to every hart in `targets`, and wait for the fences to be done
+fn send(hart_mask: usize, hart_mask_base: usize, request: Request) -> SbiRet {
+    let Some(targets) = target_harts(hart_mask, hart_mask_base) else {
+        return SbiRet::invalid_param();
+    };
+    let is_target = |hart: &usize| targets & 1 << hart != 0;
+    for hart in (0..NUM_HART_MAX).filter(is_target) {
+        MAILBOXES[hart].fetch_or(request.bits(), Ordering::AcqRel);
+        clint::send_soft(hart);
+    }
+    if request != Request::IPI {
+        // Keep serving the requests to this hart, since the target may be waiting for it as well
+        while (0..NUM_HART_MAX)
+            .filter(is_target)
+            .any(|hart| MAILBOXES[hart].load(Ordering::Acquire) & request.bits() != 0)
+        {
+            handle_requests();
+            core::hint::spin_loop();
+        }
+    }
+    SbiRet::success(0)
+}
+
+pub fn send_ipi(hart_mask: usize, hart_mask_base: usize) -> SbiRet {
+    send(hart_mask, hart_mask_base, Request::IPI)
+}
+
+pub fn remote_fence_i(hart_mask: usize, hart_mask_base: usize) -> SbiRet {
+    send(hart_mask, hart_mask_base, Request::FENCE_I)
+}
+
+pub fn remote_sfence_vma(hart_mask: usize, hart_mask_base: usize) -> SbiRet {
+    send(hart_mask, hart_mask_base, Request::SFENCE_VMA)
+}
+
+/// Perform the requests posted to the current hart
+///
+/// A request is cleared from the mailbox only after it is done, so that a sender waiting for a
+/// fence does not return early.
+pub fn handle_requests() {
+    let mailbox = &MAILBOXES[hart_id()];
+    let request = Request::from_bits_truncate(mailbox.load(Ordering::Acquire));
+    if request.contains(Request::IPI) {
+        unsafe { mip::set_ssoft() };
+    }
+    if request.contains(Request::FENCE_I) {
+        unsafe { asm!("fence.i") };
+    }
+    if request.contains(Request::SFENCE_VMA) {
+        unsafe { asm!("sfence.vma") };
+    }
+    mailbox.fetch_and(!request.bits(), Ordering::AcqRel);
+}
+
+/// Handler of the machine software interrupt, called by `msoft` on the machine stack
+pub(crate) extern "C" fn msoft_handler() {
+    clint::clear_soft();
+    handle_requests();
+}
@@ -0,0 +1,99 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! SBI extensions of version 0.2 and later, which return an error code in `a0` and a value in `a1`
+
+use sbi_spec as spec;
+use spec::binary::{RET_ERR_INVALID_PARAM, RET_ERR_NOT_SUPPORTED, RET_SUCCESS};
+
+use crate::clint::set_timer;
+
+/// The `base` module contains implementation of the Base extension (EID 0x10)
+pub mod base;
+
+/// The `hsm` module contains implementation of the Hart State Management extension (EID "HSM")
+pub mod hsm;
+
+/// The `ipi` module contains implementation of
+/// - the IPI extension (EID "sPI")
+/// - the RFENCE extension (EID "RFNC")
+pub mod ipi;
+
+/// The `srst` module contains implementation of the System Reset extension (EID "SRST")
+pub mod srst;
+
+pub struct SbiRet {
+    pub error: usize,
+    pub value: usize,
+}
+
+impl SbiRet {
+    pub fn success(value: usize) -> Self {
+        Self { error: RET_SUCCESS, value }
+    }
+
+    pub fn error(error: usize) -> Self {
+        Self { error, value: 0 }
+    }
+
+    pub fn not_supported() -> Self {
+        Self::error(RET_ERR_NOT_SUPPORTED)
+    }
+
+    pub fn invalid_param() -> Self {
+        Self::error(RET_ERR_INVALID_PARAM)
+    }
+}
+
+/// Handle an SBI call with extension id `eid`, function id `fid` and arguments `a0` to `a5`
+pub fn handle_ecall(eid: usize, fid: usize, args: [usize; 6]) -> SbiRet {
+    match eid {
+        spec::base::EID_BASE => base::handle(fid, args[0]),
+        spec::time::EID_TIME => {
+            set_timer(args[0] as u64);
+            SbiRet::success(0)
+        }
+        spec::hsm::EID_HSM => match fid {
+            spec::hsm::HART_START => hsm::hart_start(args[0], args[1], args[2]),
+            spec::hsm::HART_STOP => hsm::hart_stop(),
+            spec::hsm::HART_GET_STATUS => hsm::hart_get_status(args[0]),
+            _ => SbiRet::not_supported(),
+        },
+        spec::spi::EID_SPI => match fid {
+            spec::spi::SEND_IPI => ipi::send_ipi(args[0], args[1]),
+            _ => SbiRet::not_supported(),
+        },
+        spec::rfnc::EID_RFNC => match fid {
+            spec::rfnc::REMOTE_FENCE_I => ipi::remote_fence_i(args[0], args[1]),
+            // The whole TLB is flushed, which covers any address range and ASID
+            spec::rfnc::REMOTE_SFENCE_VMA | spec::rfnc::REMOTE_SFENCE_VMA_ASID => {
+                ipi::remote_sfence_vma(args[0], args[1])
+            }
+            _ => SbiRet::not_supported(),
+        },
+        spec::srst::EID_SRST => match fid {
+            spec::srst::SYSTEM_RESET => srst::system_reset(args[0], args[1]),
+            _ => SbiRet::not_supported(),
+        },
+        _ => SbiRet::not_supported(),
+    }
+}
+
+/// Whether the extension `eid` is implemented, as reported by `probe_extension`
+pub fn is_supported(eid: usize) -> bool {
+    use spec::legacy::{LEGACY_CONSOLE_GETCHAR, LEGACY_CONSOLE_PUTCHAR, LEGACY_SHUTDOWN};
+    matches!(
+        eid,
+        LEGACY_CONSOLE_PUTCHAR
+            | LEGACY_CONSOLE_GETCHAR
+            | LEGACY_SHUTDOWN
+            | spec::base::EID_BASE
+            | spec::time::EID_TIME
+            | spec::hsm::EID_HSM
+            | spec::spi::EID_SPI
+            | spec::rfnc::EID_RFNC
+            | spec::srst::EID_SRST
+    )
+}
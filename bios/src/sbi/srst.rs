@@ -0,0 +1,33 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use sbi_spec::srst::{
+    RESET_REASON_NO_REASON, RESET_REASON_SYSTEM_FAILURE, RESET_TYPE_COLD_REBOOT,
+    RESET_TYPE_SHUTDOWN, RESET_TYPE_WARM_REBOOT,
+};
+
+use super::SbiRet;
+use crate::legacy::exit::{exit_values::*, finish};
+
+pub fn system_reset(reset_type: usize, reset_reason: usize) -> SbiRet {
+    let reason_is = |reason| reset_reason == reason as usize;
+    if !reason_is(RESET_REASON_NO_REASON) && !reason_is(RESET_REASON_SYSTEM_FAILURE) {
+        return SbiRet::invalid_param();
+    }
+    if reset_type == RESET_TYPE_SHUTDOWN as usize {
+        if reason_is(RESET_REASON_SYSTEM_FAILURE) {
+            // QEMU exits with the code in the upper 16 bits, which must be non-zero for a failure
+            finish(QEMU_EXIT_FAIL | 1 << 16)
+        } else {
+            finish(QEMU_EXIT_SUCC)
+        }
+    } else if reset_type == RESET_TYPE_COLD_REBOOT as usize
+        || reset_type == RESET_TYPE_WARM_REBOOT as usize
+    {
+        finish(QEMU_EXIT_RESET)
+    } else {
+        SbiRet::invalid_param()
+    }
+}
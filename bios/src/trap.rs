@@ -8,9 +8,10 @@ use core::arch::{asm, global_asm};
 use riscv::register::mcause::{Exception, Trap};
 use riscv::register::{mcause, mtval};
 
-use crate::clint::{set_timer, CLINT};
+use crate::clint::CLINT;
 use crate::legacy::console::sbi_console_getchar;
 use crate::legacy::exit::sbi_shutdown;
+use crate::sbi::{handle_ecall, ipi::msoft_handler};
 use crate::utils::hart_id;
 use crate::{print, println};
 use crate::{config::SUPERVISOR_ENTRY, stack::trap_context};
@@ -21,6 +22,8 @@ extern "C" {
     fn __alltraps();
     fn __restore();
 }
+
+#[repr(C)]
 pub struct TrapContext {
     pub regs: [usize; 32],
     pub pc: usize,
@@ -31,6 +34,8 @@ pub struct TrapContext {
 
 impl TrapContext {
     pub fn init(&mut self, machine_sp: usize) {
+        // The supervisor is entered with its hart id in a0, as required by the SBI
+        self.regs[10] = hart_id();
         self.pc = SUPERVISOR_ENTRY;
         self.machine_sp = machine_sp;
         self.trap_handler = trap_handler as usize;
@@ -81,12 +86,12 @@ unsafe extern "C" fn mtimer() {
         // sp      : M sp
         // mscratch: S sp
         "   csrrw sp, mscratch, sp",
-        // 保护
-        "   addi  sp, sp, -4*8
-            sd    ra, 0*8(sp)
-            sd    a0, 1*8(sp)
-            sd    a1, 2*8(sp)
-            sd    a2, 3*8(sp)
+        // 保护：trap context 在 SBI 调用之外不被使用，寄存器存入其中，
+        // 而不是压到栈底之下的其他 hart 的栈上
+        "   sd    ra,  1*8(sp)
+            sd    a0, 10*8(sp)
+            sd    a1, 11*8(sp)
+            sd    a2, 12*8(sp)
         ",
         // 清除 mtimecmp
         "   la    a0, {clint_ptr}
@@ -100,11 +105,10 @@ unsafe extern "C" fn mtimer() {
             csrrs zero, mip, a0
         ",
         // 恢复
-        "   ld    ra, 0*8(sp)
-            ld    a0, 1*8(sp)
-            ld    a1, 2*8(sp)
-            ld    a2, 3*8(sp)
-            addi  sp, sp,  4*8
+        "   ld    ra,  1*8(sp)
+            ld    a0, 10*8(sp)
+            ld    a1, 11*8(sp)
+            ld    a2, 12*8(sp)
         ",
         // 换栈：
         // sp      : S sp
@@ -122,6 +126,8 @@ unsafe extern "C" fn mtimer() {
 
 /// machine soft 中断代理
 ///
+/// 处理其他 hart 投递的请求：转发 IPI 为 ssip，或执行远程 fence。
+///
 /// # Safety
 ///
 /// 裸函数。
@@ -130,37 +136,60 @@ unsafe extern "C" fn mtimer() {
 unsafe extern "C" fn msoft() {
     asm!(
         // 换栈：
-        // sp      : M sp
+        // sp      : trap context
         // mscratch: S sp
         "   csrrw sp, mscratch, sp",
-        // 保护
-        "   addi sp, sp, -3*8
-            sd   ra, 0*8(sp)
-            sd   a0, 1*8(sp)
-            sd   a1, 2*8(sp)
+        // 保护调用者保存的寄存器和 s0
+        "   sd   ra,  1*8(sp)
+            sd   t0,  5*8(sp)
+            sd   t1,  6*8(sp)
+            sd   t2,  7*8(sp)
+            sd   s0,  8*8(sp)
+            sd   a0, 10*8(sp)
+            sd   a1, 11*8(sp)
+            sd   a2, 12*8(sp)
+            sd   a3, 13*8(sp)
+            sd   a4, 14*8(sp)
+            sd   a5, 15*8(sp)
+            sd   a6, 16*8(sp)
+            sd   a7, 17*8(sp)
+            sd   t3, 28*8(sp)
+            sd   t4, 29*8(sp)
+            sd   t5, 30*8(sp)
+            sd   t6, 31*8(sp)
         ",
-        // 清除 msip 设置 ssip
-        "   la   a0, {clint_ptr}
-            ld   a0, (a0)
-            csrr a1, mhartid
-            call {clear_msip}
-            csrrsi zero, mip, 1 << 1
+        // 在 M 栈上处理请求
+        "   mv   s0, sp
+            ld   sp, 34*8(sp)
+            call {handler}
+            mv   sp, s0
         ",
         // 恢复
-        "   ld   ra, 0*8(sp)
-            ld   a0, 1*8(sp)
-            ld   a1, 2*8(sp)
-            addi sp, sp,  3*8
+        "   ld   ra,  1*8(sp)
+            ld   t0,  5*8(sp)
+            ld   t1,  6*8(sp)
+            ld   t2,  7*8(sp)
+            ld   s0,  8*8(sp)
+            ld   a0, 10*8(sp)
+            ld   a1, 11*8(sp)
+            ld   a2, 12*8(sp)
+            ld   a3, 13*8(sp)
+            ld   a4, 14*8(sp)
+            ld   a5, 15*8(sp)
+            ld   a6, 16*8(sp)
+            ld   a7, 17*8(sp)
+            ld   t3, 28*8(sp)
+            ld   t4, 29*8(sp)
+            ld   t5, 30*8(sp)
+            ld   t6, 31*8(sp)
         ",
         // 换栈：
         // sp      : S sp
-        // mscratch: M sp
+        // mscratch: trap context
         "   csrrw sp, mscratch, sp",
         // 返回
         "   mret",
-        clint_ptr  = sym CLINT,
-        //               Clint::clear_msip_naked(&self, hart_idx)
-        clear_msip = sym Clint::clear_msip_naked,
+        handler = sym msoft_handler,
         options(noreturn)
     )
 }
@@ -172,24 +201,25 @@ fn trap_handler() -> ! {
     match mcause::read().cause() {
         // SBI call
         Trap::Exception(Exception::SupervisorEnvCall) => {
-            use sbi_spec::{legacy, time};
+            use sbi_spec::legacy;
             ctx.pc += 4;
-            ctx.regs[10] = match ctx.regs[17] {
+            match ctx.regs[17] {
                 legacy::LEGACY_CONSOLE_PUTCHAR => {
                     print!("{}", ctx.regs[10] as u8 as char);
-                    0
+                    ctx.regs[10] = 0;
                 }
                 legacy::LEGACY_CONSOLE_GETCHAR => {
-                    sbi_console_getchar() as usize
-                }
-                time::EID_TIME => {
-                    set_timer(ctx.regs[10] as u64);
-                    0
+                    ctx.regs[10] = sbi_console_getchar() as usize;
                 }
-                _ => {
-                    panic!("Unsupported SBI call with a7 = {:x}", ctx.regs[17]);
+                legacy::LEGACY_SHUTDOWN => sbi_shutdown(),
+                eid => {
+                    let mut args = [0; 6];
+                    args.copy_from_slice(&ctx.regs[10..16]);
+                    let ret = handle_ecall(eid, ctx.regs[16], args);
+                    ctx.regs[10] = ret.error;
+                    ctx.regs[11] = ret.value;
                 }
-            };
+            }
         }
         trap => {
             println!(
@@ -0,0 +1,23 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+/// Number of block orders of the kernel's buddy allocator
+pub const BUDDY_LEVELS: usize = 32;
+
+/// Usage of a buddy allocator
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BuddyStats {
+    /// Bytes requested by the users
+    pub user: usize,
+    /// Bytes in the allocated blocks, including what is lost to rounding up
+    pub allocated: usize,
+    /// Bytes managed by the allocator
+    pub total: usize,
+    /// Number of free blocks of each order, where a block of order `i` has `1 << i` bytes
+    pub free_blocks: [usize; BUDDY_LEVELS],
+    /// Size of the largest free block in bytes
+    pub largest_free: usize,
+}
@@ -0,0 +1,12 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Types passed between the kernel and the user programs through the syscalls
+
+#![no_std]
+
+pub mod heap;
+pub mod sched;
+pub mod time;
@@ -0,0 +1,27 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+/// Scheduling classes of `SchedAttr::policy`
+pub const SCHED_NORMAL: u32 = 0;
+pub const SCHED_DEADLINE: u32 = 6;
+
+/// Scheduling attributes in the layout of `struct sched_attr`, with times in nanoseconds
+///
+/// `sched_util_min` and `sched_util_max` are not supported. Their place holds the number of
+/// overruns of a real-time thread instead, which are jobs that used up their runtime before
+/// yielding.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SchedAttr {
+    pub size: u32,
+    pub policy: u32,
+    pub flags: u64,
+    pub nice: i32,
+    pub priority: u32,
+    pub runtime: u64,
+    pub deadline: u64,
+    pub period: u64,
+    pub overruns: u64,
+}
@@ -0,0 +1,71 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use core::time::Duration;
+
+const NSEC_PER_SEC: usize = 1_000_000_000;
+
+/// Clock ticks per second in `Tms`, as `sysconf(_SC_CLK_TCK)` on Linux
+pub const CLK_TCK: usize = 100;
+
+/// Time in the layout of `struct timespec`
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
+impl TimeSpec {
+    pub fn from_nanos(nanos: usize) -> Self {
+        Self {
+            sec: nanos / NSEC_PER_SEC,
+            nsec: nanos % NSEC_PER_SEC,
+        }
+    }
+}
+
+impl From<TimeSpec> for Duration {
+    fn from(ts: TimeSpec) -> Self {
+        Duration::new(ts.sec as u64, ts.nsec as u32)
+    }
+}
+
+/// Time in the layout of `struct timeval`
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeVal {
+    pub sec: usize,
+    pub usec: usize,
+}
+
+impl From<TimeVal> for Duration {
+    fn from(tv: TimeVal) -> Self {
+        Duration::new(tv.sec as u64, tv.usec as u32 * 1000)
+    }
+}
+
+/// Process times in the layout of `struct tms`, in clock ticks of `CLK_TCK`
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Tms {
+    /// User time of the process
+    pub utime: usize,
+    /// System time of the process
+    pub stime: usize,
+    /// User time of the reaped children, including their reaped children
+    pub cutime: usize,
+    /// System time of the reaped children, including their reaped children
+    pub cstime: usize,
+}
+
+/// Resource usage in the layout of `struct rusage`, where only the CPU times are filled
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RUsage {
+    pub utime: TimeVal,
+    pub stime: TimeVal,
+    pub rest: [usize; 14],
+}
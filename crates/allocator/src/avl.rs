@@ -120,6 +120,14 @@ impl AVLTree {
         }
     }
 
+    /// Count the nodes in the current subtree
+    fn count_in_node(node: *mut AVLTreeNode) -> usize {
+        if node.is_null() {
+            return 0;
+        }
+        unsafe { 1 + AVLTree::count_in_node((*node).left) + AVLTree::count_in_node((*node).right) }
+    }
+
     /// Find a node in the current subtree
     fn find_in_node(node: *mut AVLTreeNode, data: usize) -> bool {
         if node.is_null() {
@@ -177,129 +185,78 @@ impl AVLTree {
         node
     }
 
-    /// Adjust the height after removing a node
-    fn adjust(node: *mut AVLTreeNode, sub_tree: bool) -> (bool, *mut AVLTreeNode) {
-        let left_height = Self::get_height(unsafe { (*node).left });
-        let right_height = Self::get_height(unsafe { (*node).right });
-        if sub_tree {
-            // Delete on right sub tree to make it shorter
-            if left_height - right_height == 1 {
-                return (true, node);
-            }
-            if left_height == right_height {
-                unsafe {
-                    (*node).height -= 1;
-                    return (false, node);
+    /// Update the height of the node and rotate it if its subtrees differ in height by 2
+    ///
+    /// Return the new root of the subtree.
+    fn rebalance(node: *mut AVLTreeNode) -> *mut AVLTreeNode {
+        unsafe {
+            let left_height = Self::get_height((*node).left);
+            let right_height = Self::get_height((*node).right);
+            if left_height - right_height == 2 {
+                let left = (*node).left;
+                if Self::get_height((*left).right) > Self::get_height((*left).left) {
+                    return Self::lr_rotate(node);
                 }
+                return Self::ll_rotate(node);
             }
-            if Self::get_height(unsafe { (*(*node).left).right })
-                > Self::get_height(unsafe { (*(*node).left).left })
-            {
-                (false, Self::lr_rotate(node))
-            } else {
-                (
-                    Self::get_height(unsafe { (*node).right })
-                        == Self::get_height(unsafe { (*node).left }),
-                    Self::ll_rotate(node),
-                )
-            }
-        } else {
-            // Delete on left sub tree to make it shorter
-            if right_height - left_height == 1 {
-                return (true, node);
-            }
-            if left_height == right_height {
-                unsafe {
-                    (*node).height -= 1;
-                    return (false, node);
+            if right_height - left_height == 2 {
+                let right = (*node).right;
+                if Self::get_height((*right).left) > Self::get_height((*right).right) {
+                    return Self::rl_rotate(node);
                 }
+                return Self::rr_rotate(node);
             }
-            if Self::get_height(unsafe { (*(*node).right).left })
-                > Self::get_height(unsafe { (*(*node).right).right })
-            {
-                (false, Self::rl_rotate(node))
-            } else {
-                (
-                    Self::get_height(unsafe { (*node).right })
-                        == Self::get_height(unsafe { (*node).left }),
-                    Self::rr_rotate(node),
-                )
-            }
+            (*node).height = max(left_height, right_height) + 1;
+            node
         }
     }
 
-    fn delete_in_node(mut node: *mut AVLTreeNode, data: usize) -> (bool, *mut AVLTreeNode, bool) {
+    /// Delete a node in the current subtree, return the new root and whether it was found
+    ///
+    /// The nodes live in the memory they stand for, so a node with two children is replaced by
+    /// its successor node itself rather than by a copy of its data.
+    fn delete_in_node(node: *mut AVLTreeNode, data: usize) -> (*mut AVLTreeNode, bool) {
         if node.is_null() {
-            return (true, node, false);
+            return (node, false);
         }
-        if data == unsafe { (*node).data } {
-            // Delete current node
-            if !unsafe { (*node).left }.is_null() && !unsafe { (*node).right }.is_null() {
-                let mut tmp = unsafe { (*node).right };
-                while !unsafe { (*tmp).left }.is_null() {
-                    tmp = unsafe { (*tmp).left };
-                }
-                unsafe {
-                    (*tmp).left = (*node).left;
-                    (*tmp).right = (*node).right;
-                    (*tmp).height = (*node).height;
-                }
-                let (mut flag, son, status) =
-                    Self::delete_in_node(unsafe { (*node).right }, unsafe { (*tmp).data });
-                unsafe {
-                    (*node).right = son;
-                }
-                if !flag {
-                    (flag, node) = Self::adjust(node, false);
+        unsafe {
+            let found = match data.cmp(&(*node).data) {
+                Ordering::Less => {
+                    let (left, found) = Self::delete_in_node((*node).left, data);
+                    (*node).left = left;
+                    found
                 }
-                (flag, node, status)
-            } else {
-                let tmp = node;
-                if unsafe { (*node).left }.is_null() {
-                    node = unsafe { (*node).right };
-                } else {
-                    node = unsafe { (*node).left };
+                Ordering::Greater => {
+                    let (right, found) = Self::delete_in_node((*node).right, data);
+                    (*node).right = right;
+                    found
                 }
-                unsafe {
-                    core::ptr::drop_in_place(tmp);
+                Ordering::Equal => {
+                    if (*node).left.is_null() {
+                        return ((*node).right, true);
+                    }
+                    if (*node).right.is_null() {
+                        return ((*node).left, true);
+                    }
+                    let (right, successor) = Self::delete_min_in_node((*node).right);
+                    (*successor).left = (*node).left;
+                    (*successor).right = right;
+                    return (Self::rebalance(successor), true);
                 }
-                (false, node, true)
-            }
-        } else {
-            let (mut flag, son, status) = if data < unsafe { (*node).data } {
-                Self::delete_in_node(unsafe { (*node).left }, data)
-            } else {
-                Self::delete_in_node(unsafe { (*node).right }, data)
             };
-            unsafe {
-                if data < (*node).data {
-                    (*node).left = son;
-                } else {
-                    (*node).right = son;
-                }
-            }
-            if !flag {
-                (flag, node) = Self::adjust(node, data > unsafe { (*node).data });
-            }
-            (flag, node, status)
+            (Self::rebalance(node), found)
         }
     }
 
-    fn delete_min_in_node(mut node: *mut AVLTreeNode) -> (bool, *mut AVLTreeNode, usize) {
-        if node.is_null() {
-            return (false, node, usize::default());
-        }
+    /// Delete the minimum node in the current subtree, return the new root and the deleted node
+    fn delete_min_in_node(node: *mut AVLTreeNode) -> (*mut AVLTreeNode, *mut AVLTreeNode) {
         unsafe {
             if (*node).left.is_null() {
-                (false, (*node).right, (*node).data)
-            } else {
-                let (mut flag, left, data) = Self::delete_min_in_node((*node).left);
-                (*node).left = left;
-                if flag {
-                    (flag, node) = Self::adjust(node, false);
-                }
-                (flag, node, data)
+                return ((*node).right, node);
             }
+            let (left, min) = Self::delete_min_in_node((*node).left);
+            (*node).left = left;
+            (Self::rebalance(node), min)
         }
     }
 }
@@ -326,21 +283,25 @@ impl AVLTree {
     }
 
     pub fn delete(&mut self, data: usize) -> bool {
-        let (flag, node, _) = AVLTree::delete_in_node(self.0 as *mut AVLTreeNode, data);
+        let (node, found) = AVLTree::delete_in_node(self.0 as *mut AVLTreeNode, data);
         self.0 = node as usize;
-        flag
+        found
     }
 
     pub fn pop_min(&mut self) -> Option<usize> {
         if self.is_empty() {
             return None;
         }
-        let (_, node, data) = AVLTree::delete_min_in_node(self.0 as *mut AVLTreeNode);
+        let (node, min) = AVLTree::delete_min_in_node(self.0 as *mut AVLTreeNode);
         self.0 = node as usize;
-        Some(data)
+        Some(unsafe { (*min).data })
     }
 
     pub fn is_empty(&self) -> bool {
         self.0 == 0
     }
+
+    pub fn len(&self) -> usize {
+        AVLTree::count_in_node(self.0 as *mut AVLTreeNode)
+    }
 }
@@ -3,11 +3,15 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
-use core::{alloc::Layout, clone, cmp::{max, min}, mem::size_of};
+use core::{alloc::Layout, cmp::{max, min}, ptr::null_mut};
+
+pub use abi::heap::BuddyStats;
 
 use super::avl::AVLTree;
 
-const BUDDY_ALLOCATOR_LEVEL: usize = 32;
+const BUDDY_ALLOCATOR_LEVEL: usize = abi::heap::BUDDY_LEVELS;
+/// Order of the smallest block, which holds a node of the free lists
+const MIN_LEVEL: usize = 5;
 
 pub struct BuddyAllocator {
     // Unallocated memory
@@ -19,6 +23,12 @@ pub struct BuddyAllocator {
     total: usize,
 }
 
+/// Order of the block serving the layout
+fn block_level(layout: &Layout) -> usize {
+    let size = max(layout.size().next_power_of_two(), layout.align());
+    max(size.trailing_zeros() as usize, MIN_LEVEL)
+}
+
 impl BuddyAllocator {
     /// Helper function for splitting into lower layers
     fn split(&mut self, from: usize, to: usize) {
@@ -34,19 +44,23 @@ impl BuddyAllocator {
     }
 
     /// Helper function for merging into higher layers
+    ///
+    /// The block is merged with its buddy as long as the buddy is free, and the merged block is
+    /// put into the free list of its layer.
     fn merge(&mut self, from: usize, ptr: usize) {
         let mut layer = from;
         let mut key = ptr;
-        while layer < self.free_list.len() {
-            let buddy = key ^ (1 << layer);
-            if self.free_list[layer].delete(buddy) {
-                key = min(key, buddy);
-                layer += 1;
-            } else {
-                self.free_list[layer].insert(key);
-                break;
-            }
+        while layer + 1 < self.free_list.len() && self.free_list[layer].delete(key ^ (1 << layer)) {
+            key &= !(1 << layer);
+            layer += 1;
         }
+        self.free_list[layer].insert(key);
+    }
+}
+
+impl Default for BuddyAllocator {
+    fn default() -> Self {
+        Self::empty()
     }
 }
 
@@ -60,48 +74,150 @@ impl BuddyAllocator {
         }
     }
 
+    /// Add the memory in `[start, end)` to the allocator
+    ///
+    /// The range is cut into blocks aligned to their own size, so that each of them has its
+    /// buddy at the address with the bit of its order flipped.
+    ///
+    /// # Safety
+    /// The memory range must be valid, writable and not used by anything else.
     pub unsafe fn add_segment(&mut self, mut start: usize, mut end: usize) {
-        let align = 32;
+        let align = 1 << MIN_LEVEL;
         start = (start + align - 1) & !(align - 1);
         end &= !(align - 1);
+        if start >= end {
+            return;
+        }
         self.total += end - start;
 
         while start < end {
-            let level = (end - start).trailing_zeros() as usize;
+            let size_level = (usize::BITS - 1 - (end - start).leading_zeros()) as usize;
+            let level = min(
+                min(start.trailing_zeros() as usize, size_level),
+                BUDDY_ALLOCATOR_LEVEL - 1,
+            );
             self.free_list[level].insert(start);
             start += 1 << level;
         }
     }
 
+    /// Allocate a block for the layout, or return null if there is no free block large enough
     pub fn alloc(&mut self, layout: Layout) -> *mut u8 {
-        let size = max(
-            layout.size().next_power_of_two(),
-            max(layout.align(), 32),
-        );
-        let level = size.trailing_zeros() as usize;
-        for i in level..self.free_list.len() {
-            // Find the first non-empty layer
-            if !self.free_list[i].is_empty() {
-                self.split(i, level);
-                let result = self.free_list[level].pop_min().expect("[allocator] Expect non-empty free list.");
-
-                // Maintain statistics
-                // println!("[allocator] allocated: {:#x}, user: {:#x}", self.allocated, self.user);
-                self.user += size;
-                self.allocated += size;
-                return result as *mut u8;
+        let level = block_level(&layout);
+        let Some(from) = (level..self.free_list.len()).find(|&i| !self.free_list[i].is_empty())
+        else {
+            return null_mut();
+        };
+        self.split(from, level);
+        let result = self.free_list[level].pop_min().expect("[allocator] Expect non-empty free list.");
+
+        // Maintain statistics
+        // println!("[allocator] allocated: {:#x}, user: {:#x}", self.allocated, self.user);
+        self.user += layout.size();
+        self.allocated += 1 << level;
+        result as *mut u8
+    }
+
+    /// Free a block allocated with the same layout, merging it with its free buddies
+    ///
+    /// # Safety
+    /// The block must have been allocated by this allocator with the same layout.
+    pub unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let level = block_level(&layout);
+        self.merge(level, ptr as usize);
+        self.user -= layout.size();
+        self.allocated -= 1 << level;
+    }
+
+    /// Get the usage of the allocator, which walks all the free lists
+    pub fn stats(&self) -> BuddyStats {
+        let mut stats = BuddyStats {
+            user: self.user,
+            allocated: self.allocated,
+            total: self.total,
+            ..BuddyStats::default()
+        };
+        for (level, free_list) in self.free_list.iter().enumerate() {
+            stats.free_blocks[level] = free_list.len();
+            if !free_list.is_empty() {
+                stats.largest_free = 1 << level;
             }
         }
-        panic!("[allocator] out of memory")
+        stats
     }
+}
 
-    pub unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
-        let size = max(
-            layout.size().next_power_of_two(),
-            max(layout.align(), 32),
-        );
-        let level = size.trailing_zeros() as usize;
-        self.free_list[level].insert(ptr as usize);
-        self.user -= size;
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use super::BuddyAllocator;
+
+    const ARENA_SIZE: usize = 1 << 16;
+
+    /// A buddy allocator managing a fresh arena, aligned to its size so that it is one block
+    fn arena_allocator() -> BuddyAllocator {
+        let layout = Layout::from_size_align(ARENA_SIZE, ARENA_SIZE).unwrap();
+        let start = unsafe { std::alloc::alloc(layout) } as usize;
+        assert_ne!(start, 0);
+        let mut buddy = BuddyAllocator::empty();
+        unsafe { buddy.add_segment(start, start + ARENA_SIZE) };
+        buddy
+    }
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn free_coalesces_into_one_block() {
+        let mut buddy = arena_allocator();
+        let layouts = [
+            layout(32, 8),
+            layout(100, 8),
+            layout(4096, 4096),
+            layout(1000, 16),
+            layout(8, 8),
+        ];
+        let ptrs: std::vec::Vec<_> = layouts.iter().map(|&layout| buddy.alloc(layout)).collect();
+        assert!(ptrs.iter().all(|ptr| !ptr.is_null()));
+        // Free in another order than the allocation, so that buddies come back in any order
+        for i in [3, 0, 4, 2, 1] {
+            unsafe { buddy.dealloc(ptrs[i], layouts[i]) };
+        }
+        let stats = buddy.stats();
+        assert_eq!(stats.free_blocks.iter().sum::<usize>(), 1);
+        assert_eq!(stats.free_blocks[ARENA_SIZE.trailing_zeros() as usize], 1);
+        assert_eq!(stats.largest_free, ARENA_SIZE);
+    }
+
+    #[test]
+    fn exhaustion_returns_null() {
+        let mut buddy = arena_allocator();
+        assert!(buddy.alloc(layout(2 * ARENA_SIZE, 8)).is_null());
+        let whole = buddy.alloc(layout(ARENA_SIZE, 8));
+        assert!(!whole.is_null());
+        assert!(buddy.alloc(layout(8, 8)).is_null());
+        unsafe { buddy.dealloc(whole, layout(ARENA_SIZE, 8)) };
+        assert!(!buddy.alloc(layout(8, 8)).is_null());
+    }
+
+    #[test]
+    fn stats_count_requested_and_allocated_bytes() {
+        let mut buddy = arena_allocator();
+        let ptr = buddy.alloc(layout(100, 8));
+        let stats = buddy.stats();
+        assert_eq!(stats.user, 100);
+        assert_eq!(stats.allocated, 128);
+        assert_eq!(stats.total, ARENA_SIZE);
+        assert_eq!(stats.largest_free, ARENA_SIZE / 2);
+        // Splitting the arena down to 128 bytes leaves one free block of each order in between
+        for level in 7..16 {
+            assert_eq!(stats.free_blocks[level], 1);
+        }
+        assert_eq!(stats.free_blocks.iter().sum::<usize>(), 9);
+        unsafe { buddy.dealloc(ptr, layout(100, 8)) };
+        let stats = buddy.stats();
+        assert_eq!((stats.user, stats.allocated), (0, 0));
     }
 }
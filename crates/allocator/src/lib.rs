@@ -5,15 +5,20 @@
 
 #![no_std]
 
+#[cfg(test)]
+extern crate std;
+
 use core::{alloc::{GlobalAlloc, Layout}, borrow::BorrowMut};
 
 use spin::Mutex;
 pub use stack::StackAllocator;
 
-use self::buddy::BuddyAllocator;
+use self::slab::SlabAllocator;
+pub use self::buddy::{BuddyAllocator, BuddyStats};
 
 mod buddy;
 mod avl;
+mod slab;
 mod stack;
 
 pub struct LockedBuddyAllocator(Mutex<BuddyAllocator>);
@@ -37,6 +42,11 @@ impl LockedBuddyAllocator {
             self.0.lock().borrow_mut().add_segment(start, end);
         }
     }
+
+    /// Get the usage of the allocator
+    pub fn stats(&self) -> BuddyStats {
+        self.0.lock().stats()
+    }
 }
 
 unsafe impl GlobalAlloc for LockedBuddyAllocator {
@@ -48,3 +58,47 @@ unsafe impl GlobalAlloc for LockedBuddyAllocator {
         self.0.lock().borrow_mut().dealloc(ptr, layout);
     }
 }
+
+/// Slab allocator behind a lock, serving small objects from per-size caches
+pub struct LockedSlabAllocator(Mutex<SlabAllocator>);
+
+impl LockedSlabAllocator {
+    pub const fn empty() -> Self {
+        Self(Mutex::new(SlabAllocator::empty()))
+    }
+
+    /// Initializes the locked slab allocator with the memory in `[start, end)`.
+    ///
+    /// # Safety
+    /// The memory range must be valid and not used by anything else.
+    pub unsafe fn init(&self, start: usize, end: usize) {
+        unsafe {
+            self.0.lock().add_segment(start, end);
+        }
+    }
+
+    /// Add more memory in `[start, end)` to the allocator
+    ///
+    /// # Safety
+    /// The memory range must be valid and not used by anything else.
+    pub unsafe fn add_segment(&self, start: usize, end: usize) {
+        unsafe {
+            self.0.lock().add_segment(start, end);
+        }
+    }
+
+    /// Get the usage of the allocator
+    pub fn stats(&self) -> BuddyStats {
+        self.0.lock().stats()
+    }
+}
+
+unsafe impl GlobalAlloc for LockedSlabAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0.lock().alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().dealloc(ptr, layout);
+    }
+}
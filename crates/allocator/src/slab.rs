@@ -0,0 +1,258 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use core::{alloc::Layout, ptr::null_mut};
+
+use super::buddy::{BuddyAllocator, BuddyStats};
+
+/// Sizes of the objects served by the caches, larger objects go to the buddy allocator directly
+///
+/// An object of a size which is not a power of two is aligned to the largest power of two
+/// dividing its size, since the slabs are aligned to their own size.
+const SIZE_CLASSES: [usize; 13] = [8, 16, 32, 48, 64, 96, 128, 192, 256, 384, 512, 1024, 2048];
+/// Smallest slab, one page
+const MIN_SLAB_SIZE: usize = 4096;
+/// Least number of objects in a slab, which decides the slab size of the large classes
+const MIN_OBJECTS: usize = 8;
+
+/// Header at the start of each slab
+///
+/// The slabs with free objects are linked into the partial list of their cache, and the free
+/// objects of a slab are linked through their first word. Addresses are kept as `usize`, with 0
+/// for none.
+#[repr(C)]
+struct SlabHeader {
+    prev: usize,
+    next: usize,
+    free: usize,
+    used: usize,
+}
+
+/// Slabs of objects of one size class
+#[derive(Clone, Copy)]
+struct SlabCache {
+    object_size: usize,
+    slab_size: usize,
+    /// Offset of the first object in a slab, right after the header
+    offset: usize,
+    /// Head of the list of slabs with free objects
+    partial: usize,
+}
+
+impl SlabCache {
+    const fn new(object_size: usize) -> Self {
+        let align = 1 << object_size.trailing_zeros();
+        let slab_size = (object_size * MIN_OBJECTS).next_power_of_two();
+        let slab_size = if slab_size < MIN_SLAB_SIZE { MIN_SLAB_SIZE } else { slab_size };
+        let header = core::mem::size_of::<SlabHeader>();
+        Self {
+            object_size,
+            slab_size,
+            offset: (header + align - 1) & !(align - 1),
+            partial: 0,
+        }
+    }
+
+    fn slab_layout(&self) -> Layout {
+        Layout::from_size_align(self.slab_size, self.slab_size).unwrap()
+    }
+
+    unsafe fn header<'a>(slab: usize) -> &'a mut SlabHeader {
+        &mut *(slab as *mut SlabHeader)
+    }
+
+    /// Put the slab at the head of the partial list
+    unsafe fn push_partial(&mut self, slab: usize) {
+        let header = Self::header(slab);
+        header.prev = 0;
+        header.next = self.partial;
+        if self.partial != 0 {
+            Self::header(self.partial).prev = slab;
+        }
+        self.partial = slab;
+    }
+
+    /// Take the slab out of the partial list
+    unsafe fn unlink_partial(&mut self, slab: usize) {
+        let header = Self::header(slab);
+        if header.prev != 0 {
+            Self::header(header.prev).next = header.next;
+        } else {
+            self.partial = header.next;
+        }
+        if header.next != 0 {
+            Self::header(header.next).prev = header.prev;
+        }
+    }
+
+    /// Carve a new slab from the buddy allocator and link all its objects into its free list
+    unsafe fn grow(&mut self, buddy: &mut BuddyAllocator) -> bool {
+        let slab = buddy.alloc(self.slab_layout()) as usize;
+        if slab == 0 {
+            return false;
+        }
+        let mut free = 0;
+        let mut object = slab + self.slab_size - self.object_size;
+        while object >= slab + self.offset {
+            *(object as *mut usize) = free;
+            free = object;
+            object -= self.object_size;
+        }
+        let header = Self::header(slab);
+        header.free = free;
+        header.used = 0;
+        self.push_partial(slab);
+        true
+    }
+
+    unsafe fn alloc(&mut self, buddy: &mut BuddyAllocator) -> *mut u8 {
+        if self.partial == 0 && !self.grow(buddy) {
+            return null_mut();
+        }
+        let slab = self.partial;
+        let header = Self::header(slab);
+        let object = header.free;
+        header.free = *(object as *const usize);
+        header.used += 1;
+        if header.free == 0 {
+            self.unlink_partial(slab);
+        }
+        object as *mut u8
+    }
+
+    /// Return the object to its slab, and the slab to the buddy allocator once it is empty,
+    /// unless it is the only slab with free objects
+    unsafe fn dealloc(&mut self, buddy: &mut BuddyAllocator, ptr: *mut u8) {
+        let object = ptr as usize;
+        let slab = object & !(self.slab_size - 1);
+        let header = Self::header(slab);
+        let was_full = header.free == 0;
+        *(object as *mut usize) = header.free;
+        header.free = object;
+        header.used -= 1;
+        if was_full {
+            self.push_partial(slab);
+        }
+        if header.used == 0 && (header.prev != 0 || header.next != 0) {
+            self.unlink_partial(slab);
+            buddy.dealloc(slab as *mut u8, self.slab_layout());
+        }
+    }
+}
+
+/// Size-class allocator for the small objects, on top of a buddy allocator for the slabs and
+/// the large objects
+pub struct SlabAllocator {
+    caches: [SlabCache; SIZE_CLASSES.len()],
+    buddy: BuddyAllocator,
+}
+
+impl SlabAllocator {
+    pub const fn empty() -> Self {
+        let mut caches = [SlabCache::new(SIZE_CLASSES[0]); SIZE_CLASSES.len()];
+        let mut i = 0;
+        while i < SIZE_CLASSES.len() {
+            caches[i] = SlabCache::new(SIZE_CLASSES[i]);
+            i += 1;
+        }
+        Self { caches, buddy: BuddyAllocator::empty() }
+    }
+
+    /// Add the memory in `[start, end)` to the allocator
+    pub unsafe fn add_segment(&mut self, start: usize, end: usize) {
+        self.buddy.add_segment(start, end);
+    }
+
+    /// Index of the cache serving the layout, if it is small enough
+    fn cache_index(layout: &Layout) -> Option<usize> {
+        SIZE_CLASSES.iter().position(|&size| {
+            size >= layout.size() && 1 << size.trailing_zeros() >= layout.align()
+        })
+    }
+
+    /// Allocate an object for the layout, or return null if there is no memory left
+    pub fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        match Self::cache_index(&layout) {
+            Some(idx) => unsafe { self.caches[idx].alloc(&mut self.buddy) },
+            None => self.buddy.alloc(layout),
+        }
+    }
+
+    /// Free an object allocated with the same layout
+    pub unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        match Self::cache_index(&layout) {
+            Some(idx) => self.caches[idx].dealloc(&mut self.buddy, ptr),
+            None => self.buddy.dealloc(ptr, layout),
+        }
+    }
+
+    /// Get the usage of the underlying buddy allocator, where a slab counts as allocated as a
+    /// whole
+    pub fn stats(&self) -> BuddyStats {
+        self.buddy.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use super::{SlabAllocator, MIN_SLAB_SIZE};
+
+    const ARENA_SIZE: usize = 1 << 16;
+
+    /// A slab allocator managing a fresh arena, aligned to its size
+    fn arena_allocator() -> SlabAllocator {
+        let layout = Layout::from_size_align(ARENA_SIZE, ARENA_SIZE).unwrap();
+        let start = unsafe { std::alloc::alloc(layout) } as usize;
+        assert_ne!(start, 0);
+        let mut slab = SlabAllocator::empty();
+        unsafe { slab.add_segment(start, start + ARENA_SIZE) };
+        slab
+    }
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn freed_object_is_reused_across_sizes_of_its_class() {
+        let mut slab = arena_allocator();
+        let first = slab.alloc(layout(20, 4));
+        assert!(!first.is_null());
+        unsafe { slab.dealloc(first, layout(20, 4)) };
+        // 20 and 30 bytes are both served by the class of 32 bytes
+        let second = slab.alloc(layout(30, 8));
+        assert_eq!(first, second);
+        // Another class takes its objects from its own slab
+        let other = slab.alloc(layout(64, 8));
+        assert_ne!(other as usize / MIN_SLAB_SIZE, second as usize / MIN_SLAB_SIZE);
+    }
+
+    #[test]
+    fn empty_slabs_go_back_to_the_buddy_allocator() {
+        let mut slab = arena_allocator();
+        let ptrs: std::vec::Vec<_> = (0..300).map(|_| slab.alloc(layout(32, 8))).collect();
+        assert!(ptrs.iter().all(|ptr| !ptr.is_null()));
+        assert_eq!(slab.stats().allocated, 3 * MIN_SLAB_SIZE);
+        for &ptr in &ptrs {
+            unsafe { slab.dealloc(ptr, layout(32, 8)) };
+        }
+        // The last slab with free objects is kept for the next allocation
+        assert_eq!(slab.stats().allocated, MIN_SLAB_SIZE);
+    }
+
+    #[test]
+    fn large_objects_come_from_the_buddy_allocator() {
+        let mut slab = arena_allocator();
+        let ptr = slab.alloc(layout(3000, 8));
+        assert!(!ptr.is_null());
+        let stats = slab.stats();
+        assert_eq!((stats.user, stats.allocated), (3000, 4096));
+        unsafe { slab.dealloc(ptr, layout(3000, 8)) };
+        assert_eq!(slab.stats().allocated, 0);
+        assert!(slab.alloc(layout(2 * ARENA_SIZE, 8)).is_null());
+    }
+}
@@ -17,9 +17,12 @@ pub struct StackAllocator {
 
 impl StackAllocator {
     /// Initialize the stack frame allocator with a range of frames
+    ///
+    /// Room for recycling all of them is reserved up front, so that deallocating never allocates.
     pub fn init(&mut self, l: usize, r: usize) {
         self.current = l;
         self.end = r;
+        self.recycled.reserve_exact(r - l);
     }
     
     /// Create a new stack allocator
@@ -46,6 +49,20 @@ impl StackAllocator {
         }
     }
 
+    /// Allocate `count` consecutive frames which have never been allocated, the first of which
+    /// is aligned to `align` frames, a power of two, and return the first one
+    ///
+    /// The frames skipped for the alignment are recycled.
+    pub fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<usize> {
+        let start = (self.current + align - 1) & !(align - 1);
+        if start > self.end || self.end - start < count {
+            return None;
+        }
+        self.recycled.extend(self.current..start);
+        self.current = start + count;
+        Some(start)
+    }
+
     /// Deallocate a frame
     pub fn dealloc(&mut self, ppn: usize) {
         if ppn >= self.current || self.recycled.iter().any(|v| *v == ppn) {
@@ -0,0 +1,16 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::time::Duration;
+
+use user_lib::time::{clock_gettime, ClockId, DateTime};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let uptime: Duration = clock_gettime(ClockId::Monotonic).into();
+    println!("{} UTC, up {} ms", DateTime::now(), uptime.as_millis());
+    0
+}
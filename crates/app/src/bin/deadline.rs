@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::time::Duration;
+
+use user_lib::{
+    exit, fork,
+    sched::{sched_getattr, set_deadline, SCHED_DEADLINE},
+    time::Instant,
+    waitpid, yield_,
+};
+
+const JOBS: usize = 10;
+const RUNTIME: Duration = Duration::from_millis(20);
+const PERIOD: Duration = Duration::from_millis(100);
+
+/// Keep the CPU busy for `time`
+fn spin(time: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < time {}
+}
+
+fn periodic() -> ! {
+    assert_eq!(set_deadline(0, RUNTIME, PERIOD, PERIOD), 0);
+    let start = Instant::now();
+    for job in 0..JOBS {
+        spin(RUNTIME / 4);
+        println!("job {} done at {:?}", job, start.elapsed());
+        yield_();
+    }
+    let attr = sched_getattr(0).unwrap();
+    println!("overruns: {}", attr.overruns);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        periodic();
+    }
+    // Wait for the child to get its reservation first
+    while sched_getattr(pid as usize).is_some_and(|attr| attr.policy != SCHED_DEADLINE) {
+        yield_();
+    }
+    // Together with the child this takes more than a hart
+    assert!(set_deadline(0, Duration::from_millis(90), PERIOD, PERIOD) < 0);
+    let mut exit_code: i32 = 0;
+    assert!(waitpid(pid as usize, &mut exit_code) == pid && exit_code == 0);
+    println!("deadline passed.");
+    0
+}
@@ -4,7 +4,13 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{exit, fork, getpid, sleep, yield_};
+use core::time::Duration;
+
+use user_lib::{
+    exit, fork, getpid, sleep,
+    time::{getrusage, RUsageWho},
+    yield_,
+};
 
 const DEPTH: usize = 4;
 
@@ -33,5 +39,11 @@ fn fork_tree(cur: &str) {
 pub fn main() -> i32 {
     fork_tree("");
     sleep(3000);
+    let usage = getrusage(RUsageWho::Self_);
+    println!(
+        "cpu time: user {:?}, system {:?}",
+        Duration::from(usage.utime),
+        Duration::from(usage.stime)
+    );
     0
 }
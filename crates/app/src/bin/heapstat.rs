@@ -0,0 +1,23 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::debug::heap_stats;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let stats = heap_stats();
+    println!(
+        "kernel heap: {} bytes requested, {} of {} bytes allocated",
+        stats.user, stats.allocated, stats.total
+    );
+    for (order, &count) in stats.free_blocks.iter().enumerate() {
+        if count != 0 {
+            println!("  {} free blocks of {} bytes", count, 1usize << order);
+        }
+    }
+    println!("largest free block: {} bytes", stats.largest_free);
+    0
+}
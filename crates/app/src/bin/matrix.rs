@@ -5,7 +5,11 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{exit, fork, get_time, getpid, wait, yield_};
+use user_lib::{
+    exit, fork, get_time, getpid,
+    time::{times, Tms, CLK_TCK},
+    wait, yield_,
+};
 
 static NUM: usize = 30;
 const N: usize = 10;
@@ -65,6 +69,13 @@ pub fn main() -> i32 {
         }
     }
     assert!(wait(&mut exit_code) < 0);
+    let mut tms = Tms::default();
+    times(&mut tms);
+    println!(
+        "children cpu time: user {} ms, system {} ms.",
+        tms.cutime * 1000 / CLK_TCK,
+        tms.cstime * 1000 / CLK_TCK
+    );
     println!("matrix passed.");
     0
 }
@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::shm::{shm_map, shm_unmap, ShmProt};
+use user_lib::{fork, wait};
+
+const LEN: usize = 4096 * 2;
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut shm = shm_map("shm_test", LEN, ShmProt::READ | ShmProt::WRITE).unwrap();
+    unsafe { shm.as_mut_slice() }.fill(0);
+    let pid = fork();
+    if pid == 0 {
+        // The child writes through the mapping inherited from fork, while the parent waits
+        for (i, byte) in unsafe { shm.as_mut_slice() }.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        return 0;
+    }
+    let mut exit_code = 0;
+    assert_eq!(wait(&mut exit_code), pid);
+    assert!(unsafe { shm.as_slice() }.iter().enumerate().all(|(i, &byte)| byte == i as u8));
+    // A process mapping the name again sees the same memory
+    let other = shm_map("shm_test", LEN, ShmProt::READ).unwrap();
+    assert_eq!(unsafe { other.as_slice() }[LEN - 1], (LEN - 1) as u8);
+    assert_eq!(shm_unmap(other), 0);
+    assert_eq!(shm_unmap(shm), 0);
+    println!("shm_test passed!");
+    0
+}
@@ -0,0 +1,16 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use crate::syscall::sys_heap_stats;
+
+/// Usage of the kernel heap, as reported by the kernel's buddy allocator
+pub use abi::heap::BuddyStats as HeapStats;
+
+/// Get the usage of the kernel heap
+pub fn heap_stats() -> HeapStats {
+    let mut stats = HeapStats::default();
+    sys_heap_stats(&mut stats as *mut _);
+    stats
+}
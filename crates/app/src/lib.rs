@@ -3,11 +3,20 @@
 #![feature(panic_info_message)]
 #![feature(alloc_error_handler)]
 
+extern crate alloc;
+
 #[macro_use]
 pub mod console;
+pub mod debug;
 mod lang_items;
+pub mod sched;
+pub mod shm;
+pub mod sync;
 mod syscall;
+pub mod time;
 
+use alloc::{borrow::Cow, string::String};
+use bitflags::bitflags;
 use buddy_system_allocator::LockedHeap;
 use syscall::*;
 
@@ -40,6 +49,33 @@ fn main() -> i32 {
     panic!("Cannot find main!");
 }
 
+bitflags! {
+    pub struct OpenFlags: u32 {
+        const RDONLY = 0;
+        const WRONLY = 1 << 0;
+        const RDWR = 1 << 1;
+        const CREATE = 1 << 9;
+        const TRUNC = 1 << 10;
+    }
+}
+
+/// The string with a terminating `\0` for the kernel, appended if it is missing
+fn terminated(s: &str) -> Cow<'_, str> {
+    if s.ends_with('\0') {
+        return Cow::Borrowed(s);
+    }
+    let mut terminated = String::from(s);
+    terminated.push('\0');
+    Cow::Owned(terminated)
+}
+
+/// Open the file at `path`, which is passed to the kernel with a terminating `\0`
+pub fn open(path: &str, flags: OpenFlags) -> isize {
+    sys_open(&terminated(path), flags.bits)
+}
+pub fn close(fd: usize) -> isize {
+    sys_close(fd)
+}
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
 }
@@ -52,6 +88,26 @@ pub fn exit(exit_code: i32) -> ! {
 pub fn yield_() -> isize {
     sys_yield()
 }
+const PRIO_PROCESS: usize = 0;
+
+/// Set the nice value of the process `pid`, or the calling process if `pid` is 0
+///
+/// Only the process itself and its parent may change it, and only init may lower it.
+pub fn setpriority(pid: usize, nice: i32) -> isize {
+    sys_setpriority(PRIO_PROCESS, pid, nice)
+}
+/// Get the nice value of the process `pid`, or the calling process if `pid` is 0
+pub fn getpriority(pid: usize) -> Option<i32> {
+    match sys_getpriority(PRIO_PROCESS, pid) {
+        -1 => None,
+        prio => Some(20 - prio as i32),
+    }
+}
+/// Add `inc` to the nice value of the calling process, return the new value
+pub fn nice(inc: i32) -> Option<i32> {
+    let nice = getpriority(0)? + inc;
+    (setpriority(0, nice) == 0).then(|| getpriority(0)).flatten()
+}
 pub fn get_time() -> isize {
     sys_get_time()
 }
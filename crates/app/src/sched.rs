@@ -0,0 +1,41 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use core::time::Duration;
+
+use crate::syscall::{sys_sched_getattr, sys_sched_setattr};
+
+pub use abi::sched::{SchedAttr, SCHED_DEADLINE, SCHED_NORMAL};
+
+/// Set the scheduling class of the process `pid`, or the calling process if `pid` is 0
+pub fn sched_setattr(pid: usize, attr: &SchedAttr) -> isize {
+    sys_sched_setattr(pid, attr as *const _, 0)
+}
+
+/// Get the scheduling class of the process `pid`, or the calling process if `pid` is 0
+pub fn sched_getattr(pid: usize) -> Option<SchedAttr> {
+    let mut attr = SchedAttr::default();
+    let size = core::mem::size_of::<SchedAttr>();
+    (sys_sched_getattr(pid, &mut attr as *mut _, size) == 0).then_some(attr)
+}
+
+/// Make the process `pid` a real-time thread, running for `runtime` in each `period` and done by
+/// `deadline` from the start of the period
+///
+/// Each job ends with `yield_`, after which the thread waits for the next period. Fails if the
+/// real-time threads would reserve more than the harts can serve.
+pub fn set_deadline(pid: usize, runtime: Duration, deadline: Duration, period: Duration) -> isize {
+    sched_setattr(
+        pid,
+        &SchedAttr {
+            size: core::mem::size_of::<SchedAttr>() as u32,
+            policy: SCHED_DEADLINE,
+            runtime: runtime.as_nanos() as u64,
+            deadline: deadline.as_nanos() as u64,
+            period: period.as_nanos() as u64,
+            ..SchedAttr::default()
+        },
+    )
+}
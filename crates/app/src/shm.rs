@@ -0,0 +1,78 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use bitflags::bitflags;
+
+use crate::syscall::{sys_shm_map, sys_shm_unmap};
+use crate::terminated;
+
+/// Size of the pages of the kernel, which shared memory objects are made of
+const PAGE_SIZE: usize = 4096;
+
+bitflags! {
+    /// Protection of a shared memory mapping, as `PROT_*` of `mmap`
+    pub struct ShmProt: usize {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+/// A mapping of a shared memory object, which covers whole pages
+///
+/// The memory is shared with every other mapping of the object, in this process or in others,
+/// so it is only handed out as a raw pointer or through `unsafe` slices.
+pub struct SharedMemory {
+    addr: *mut u8,
+    len: usize,
+}
+
+impl SharedMemory {
+    /// Start of the mapping
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.addr
+    }
+
+    /// Size of the mapping in bytes
+    pub fn size(&self) -> usize {
+        self.len
+    }
+
+    /// View the mapping as a slice
+    ///
+    /// # Safety
+    /// The memory must not be written through another mapping while the slice lives.
+    pub unsafe fn as_slice(&self) -> &[u8] {
+        core::slice::from_raw_parts(self.addr, self.len)
+    }
+
+    /// View the mapping as a mutable slice
+    ///
+    /// # Safety
+    /// The memory must not be accessed through another mapping while the slice lives.
+    pub unsafe fn as_mut_slice(&mut self) -> &mut [u8] {
+        core::slice::from_raw_parts_mut(self.addr, self.len)
+    }
+}
+
+/// Map the shared memory object named `name`, creating it with `len` bytes if there is none
+///
+/// Every process mapping the same name sees the same memory, which lives until the last
+/// mapping of it is gone. The mapping covers `len` rounded up to whole pages.
+pub fn shm_map(name: &str, len: usize, prot: ShmProt) -> Option<SharedMemory> {
+    let addr = sys_shm_map(&terminated(name), len, prot.bits);
+    if addr < 0 {
+        return None;
+    }
+    Some(SharedMemory {
+        addr: addr as *mut u8,
+        len: len.div_ceil(PAGE_SIZE) * PAGE_SIZE,
+    })
+}
+
+/// Unmap the mapping of a shared memory object
+pub fn shm_unmap(shm: SharedMemory) -> isize {
+    sys_shm_unmap(shm.addr as usize)
+}
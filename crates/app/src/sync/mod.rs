@@ -0,0 +1,34 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+//! Blocking synchronization primitives built on futexes
+//!
+//! The futexes are not private, so the primitives synchronize the threads of a process as well
+//! as the processes mapping them in shared memory.
+
+use core::sync::atomic::AtomicU32;
+
+use crate::syscall::sys_futex;
+
+mod condvar;
+mod mutex;
+mod semaphore;
+
+pub use condvar::Condvar;
+pub use mutex::{Mutex, MutexGuard};
+pub use semaphore::Semaphore;
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+
+/// Block while `word` holds `val`, return false at once if it does not
+pub fn futex_wait(word: &AtomicU32, val: u32) -> bool {
+    sys_futex(word.as_ptr(), FUTEX_WAIT, val as usize) == 0
+}
+
+/// Wake up at most `count` threads blocked on `word`, return the number woken up
+pub fn futex_wake(word: &AtomicU32, count: usize) -> usize {
+    sys_futex(word.as_ptr(), FUTEX_WAKE, count).max(0) as usize
+}
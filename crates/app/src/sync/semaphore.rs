@@ -0,0 +1,48 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use super::{futex_wait, futex_wake};
+
+/// A counting semaphore
+pub struct Semaphore {
+    count: AtomicU32,
+}
+
+impl Semaphore {
+    pub const fn new(count: u32) -> Self {
+        Self { count: AtomicU32::new(count) }
+    }
+
+    /// Take one unit, blocking while there is none
+    pub fn acquire(&self) {
+        loop {
+            let count = self.count.load(Ordering::Relaxed);
+            if count == 0 {
+                futex_wait(&self.count, 0);
+            } else if self
+                .count
+                .compare_exchange_weak(count, count - 1, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    /// Take one unit if there is any, without blocking
+    pub fn try_acquire(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |count| count.checked_sub(1))
+            .is_ok()
+    }
+
+    /// Give back one unit, and wake up a waiter
+    pub fn release(&self) {
+        self.count.fetch_add(1, Ordering::Release);
+        futex_wake(&self.count, 1);
+    }
+}
@@ -1,14 +1,31 @@
 use core::arch::asm;
 
+use crate::debug::HeapStats;
+use crate::sched::SchedAttr;
+use crate::time::{RUsage, TimeSpec, Tms};
+
+const SYSCALL_OPEN: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_FUTEX: usize = 98;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_SETPRIORITY: usize = 140;
+const SYSCALL_GETPRIORITY: usize = 141;
+const SYSCALL_TIMES: usize = 153;
+const SYSCALL_GETRUSAGE: usize = 165;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_SCHED_SETATTR: usize = 274;
+const SYSCALL_SCHED_GETATTR: usize = 275;
+const SYSCALL_HEAP_STATS: usize = 2000;
+const SYSCALL_SHM_MAP: usize = 3000;
+const SYSCALL_SHM_UNMAP: usize = 3001;
 
 fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -24,6 +41,14 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+pub fn sys_open(path: &str, flags: u32) -> isize {
+    syscall(SYSCALL_OPEN, [path.as_ptr() as usize, flags as usize, 0])
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    syscall(SYSCALL_CLOSE, [fd, 0, 0])
+}
+
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
     syscall(
         SYSCALL_READ,
@@ -40,14 +65,38 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("sys_exit never returns!");
 }
 
+pub fn sys_futex(uaddr: *const u32, op: usize, val: usize) -> isize {
+    syscall(SYSCALL_FUTEX, [uaddr as usize, op, val])
+}
+
 pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0])
 }
 
+pub fn sys_setpriority(which: usize, who: usize, nice: i32) -> isize {
+    syscall(SYSCALL_SETPRIORITY, [which, who, nice as usize])
+}
+
+pub fn sys_getpriority(which: usize, who: usize) -> isize {
+    syscall(SYSCALL_GETPRIORITY, [which, who, 0])
+}
+
+pub fn sys_times(tms: *mut Tms) -> isize {
+    syscall(SYSCALL_TIMES, [tms as usize, 0, 0])
+}
+
+pub fn sys_getrusage(who: isize, usage: *mut RUsage) -> isize {
+    syscall(SYSCALL_GETRUSAGE, [who as usize, usage as usize, 0])
+}
+
 pub fn sys_get_time() -> isize {
     syscall(SYSCALL_GET_TIME, [0, 0, 0])
 }
 
+pub fn sys_clock_gettime(clock_id: usize, ts: *mut TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETTIME, [clock_id, ts as usize, 0])
+}
+
 pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
@@ -63,3 +112,23 @@ pub fn sys_exec(path: &str) -> isize {
 pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
     syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0])
 }
+
+pub fn sys_sched_setattr(pid: usize, attr: *const SchedAttr, flags: usize) -> isize {
+    syscall(SYSCALL_SCHED_SETATTR, [pid, attr as usize, flags])
+}
+
+pub fn sys_sched_getattr(pid: usize, attr: *mut SchedAttr, size: usize) -> isize {
+    syscall(SYSCALL_SCHED_GETATTR, [pid, attr as usize, size])
+}
+
+pub fn sys_heap_stats(stats: *mut HeapStats) -> isize {
+    syscall(SYSCALL_HEAP_STATS, [stats as usize, 0, 0])
+}
+
+pub fn sys_shm_map(name: &str, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_SHM_MAP, [name.as_ptr() as usize, len, prot])
+}
+
+pub fn sys_shm_unmap(addr: usize) -> isize {
+    syscall(SYSCALL_SHM_UNMAP, [addr, 0, 0])
+}
@@ -0,0 +1,141 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use core::{fmt, time::Duration};
+
+use crate::syscall::{sys_clock_gettime, sys_getrusage, sys_times};
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+const SEC_PER_DAY: u64 = 86400;
+
+pub use abi::time::{RUsage, TimeSpec, TimeVal, Tms, CLK_TCK};
+
+/// Whose resource usage `getrusage` reports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RUsageWho {
+    /// The calling process
+    Self_ = 0,
+    /// The reaped children of the calling process
+    Children = -1,
+}
+
+/// Get the CPU times of the process and its reaped children, and return the clock ticks since
+/// boot
+pub fn times(tms: &mut Tms) -> isize {
+    sys_times(tms as *mut _)
+}
+
+pub fn getrusage(who: RUsageWho) -> RUsage {
+    let mut usage = RUsage::default();
+    sys_getrusage(who as isize, &mut usage as *mut _);
+    usage
+}
+
+/// Clocks supported by `clock_gettime`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockId {
+    /// Wall-clock time since the Unix epoch
+    Realtime = 0,
+    /// Time since boot, which never goes backwards
+    Monotonic = 1,
+}
+
+pub fn clock_gettime(clock_id: ClockId) -> TimeSpec {
+    let mut ts = TimeSpec::default();
+    sys_clock_gettime(clock_id as usize, &mut ts as *mut _);
+    ts
+}
+
+/// A point of the monotonic clock, used to measure elapsed time
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(Duration);
+
+impl Instant {
+    pub fn now() -> Self {
+        Self(clock_gettime(ClockId::Monotonic).into())
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+}
+
+/// A point of the wall clock
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SystemTime(Duration);
+
+impl SystemTime {
+    pub fn now() -> Self {
+        Self(clock_gettime(ClockId::Realtime).into())
+    }
+
+    pub fn since_epoch(&self) -> Duration {
+        self.0
+    }
+}
+
+/// Calendar date and time in UTC, printed like `2024-01-31 08:00:00.123`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub nanosecond: u32,
+}
+
+impl From<SystemTime> for DateTime {
+    fn from(time: SystemTime) -> Self {
+        let secs = time.0.as_secs();
+        let (days, secs_of_day) = (secs / SEC_PER_DAY, secs % SEC_PER_DAY);
+        // Convert days since the epoch to the civil date, in eras of 400 years starting at 0000-03-01
+        let z = days as i64 + 719468;
+        let era = z / 146097;
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + (month <= 2) as i64;
+        Self {
+            year,
+            month,
+            day,
+            hour: (secs_of_day / 3600) as u32,
+            minute: (secs_of_day / 60 % 60) as u32,
+            second: (secs_of_day % 60) as u32,
+            nanosecond: (time.0.as_nanos() % NSEC_PER_SEC as u128) as u32,
+        }
+    }
+}
+
+impl DateTime {
+    pub fn now() -> Self {
+        SystemTime::now().into()
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.nanosecond / 1_000_000
+        )
+    }
+}
@@ -0,0 +1,47 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+pub mod virtio_blk;
+
+use alloc::{sync::Arc, vec::Vec};
+
+use virtio_blk::VirtIOBlock;
+
+use crate::dma::DmaBuffer;
+
+/// Size of a block in bytes, which is the sector size of virtio-blk
+pub const BLOCK_SIZE: usize = 512;
+
+/// Base addresses of the virtio-mmio slots in the QEMU virt machine
+pub const VIRTIO_MMIO: [usize; 8] = [
+    0x1000_1000, 0x1000_2000, 0x1000_3000, 0x1000_4000,
+    0x1000_5000, 0x1000_6000, 0x1000_7000, 0x1000_8000,
+];
+
+/// Error of a block request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    /// The device completed the request with the given non-OK status
+    Device(u8),
+}
+
+/// Interface of a device accessed in fixed-size blocks
+pub trait BlockDevice: Send + Sync {
+    /// Number of blocks of the device
+    fn num_blocks(&self) -> usize;
+    /// Read the block `block_id` into `buf`, whose length must be `BLOCK_SIZE`
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), BlockError>;
+    /// Write `buf`, whose length must be `BLOCK_SIZE`, into the block `block_id`
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError>;
+}
+
+/// Find all the virtio block devices on the virtio-mmio bus, in the order of their slots
+pub fn probe_block_devices<D: DmaBuffer + 'static>() -> Vec<Arc<dyn BlockDevice>> {
+    VIRTIO_MMIO
+        .iter()
+        .filter_map(|&base| unsafe { VirtIOBlock::<D>::new(base) })
+        .map(|device| Arc::new(device) as Arc<dyn BlockDevice>)
+        .collect()
+}
@@ -0,0 +1,289 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+/// Driver of the virtio block device through the legacy virtio-mmio interface (version 1)
+///
+/// Only one request is in flight at a time and completion is detected by polling the used ring,
+/// so a single virtqueue with three descriptors is enough. All the memory shared with the device
+/// is in DMA buffers from the kernel, which are identity mapped, so virtual addresses are passed
+/// to the device as physical addresses.
+
+use core::{
+    mem::size_of,
+    ptr::{addr_of, addr_of_mut},
+    sync::atomic::{fence, Ordering},
+};
+
+use bitflags::bitflags;
+use spin::Mutex;
+
+use super::{BlockDevice, BlockError, BLOCK_SIZE};
+use crate::dma::DmaBuffer;
+
+const VIRTIO_MAGIC: u32 = 0x7472_6976; // "virt" in little endian
+const VIRTIO_LEGACY_VERSION: u32 = 1;
+const VIRTIO_DEVICE_BLOCK: u32 = 2;
+
+const PAGE_SIZE: usize = 4096;
+const QUEUE_SIZE: usize = 8;
+
+/// Offsets of the legacy virtio-mmio registers
+mod reg {
+    pub const MAGIC: usize = 0x000;
+    pub const VERSION: usize = 0x004;
+    pub const DEVICE_ID: usize = 0x008;
+    pub const GUEST_FEATURES: usize = 0x020;
+    pub const GUEST_PAGE_SIZE: usize = 0x028;
+    pub const QUEUE_SEL: usize = 0x030;
+    pub const QUEUE_NUM_MAX: usize = 0x034;
+    pub const QUEUE_NUM: usize = 0x038;
+    pub const QUEUE_ALIGN: usize = 0x03c;
+    pub const QUEUE_PFN: usize = 0x040;
+    pub const QUEUE_NOTIFY: usize = 0x050;
+    pub const INTERRUPT_STATUS: usize = 0x060;
+    pub const INTERRUPT_ACK: usize = 0x064;
+    pub const STATUS: usize = 0x070;
+    pub const CONFIG: usize = 0x100;
+}
+
+bitflags! {
+    /// Device status flags
+    struct DeviceStatus: u32 {
+        const ACKNOWLEDGE = 1;
+        const DRIVER = 1 << 1;
+        const DRIVER_OK = 1 << 2;
+        const FAILED = 1 << 7;
+    }
+}
+
+bitflags! {
+    /// Virtqueue descriptor flags
+    struct DescFlags: u16 {
+        const NEXT = 1;
+        const WRITE = 1 << 1;
+    }
+}
+
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct AvailRing {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[repr(C)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+/// The used ring, which starts at the next page boundary in the legacy layout
+#[repr(C, align(4096))]
+struct UsedRing {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+/// Memory of a virtqueue in the legacy layout
+#[repr(C, align(4096))]
+struct VirtQueue {
+    desc: [Descriptor; QUEUE_SIZE],
+    avail: AvailRing,
+    used: UsedRing,
+}
+
+/// A block request, together with the bounce buffer of the data
+#[repr(C)]
+struct Request {
+    req_type: u32,
+    reserved: u32,
+    sector: u64,
+    data: [u8; BLOCK_SIZE],
+    status: u8,
+}
+
+struct VirtIOBlockInner {
+    base: usize,
+    queue: &'static mut VirtQueue,
+    request: &'static mut Request,
+    last_used: u16,
+}
+
+/// A virtio block device, whose memory shared with the device is allocated as `D`
+pub struct VirtIOBlock<D: DmaBuffer> {
+    inner: Mutex<VirtIOBlockInner>,
+    capacity: usize,
+    /// Buffers of the queue and the request, kept for as long as the device is used
+    _dma: [D; 2],
+}
+
+unsafe fn read_reg(base: usize, offset: usize) -> u32 {
+    ((base + offset) as *const u32).read_volatile()
+}
+
+unsafe fn write_reg(base: usize, offset: usize, value: u32) {
+    ((base + offset) as *mut u32).write_volatile(value)
+}
+
+/// Allocate a zeroed DMA buffer for a `T`, or `None` if there is no memory left
+///
+/// The reference is valid as long as the buffer is alive.
+unsafe fn alloc_dma<T, D: DmaBuffer>() -> Option<(D, &'static mut T)> {
+    let buffer = D::alloc(size_of::<T>().div_ceil(PAGE_SIZE))?;
+    let value = &mut *(buffer.paddr() as *mut T);
+    Some((buffer, value))
+}
+
+impl<D: DmaBuffer> VirtIOBlock<D> {
+    /// Initialize the device at the given virtio-mmio slot
+    ///
+    /// Return `None` if there is no legacy virtio block device in the slot.
+    ///
+    /// # Safety
+    /// The slot must be mapped into the current address space.
+    pub unsafe fn new(base: usize) -> Option<Self> {
+        if read_reg(base, reg::MAGIC) != VIRTIO_MAGIC
+            || read_reg(base, reg::VERSION) != VIRTIO_LEGACY_VERSION
+            || read_reg(base, reg::DEVICE_ID) != VIRTIO_DEVICE_BLOCK
+        {
+            return None;
+        }
+
+        // Reset and acknowledge the device, we do not need any optional features
+        let mut status = DeviceStatus::ACKNOWLEDGE | DeviceStatus::DRIVER;
+        write_reg(base, reg::STATUS, 0);
+        write_reg(base, reg::STATUS, status.bits());
+        write_reg(base, reg::GUEST_FEATURES, 0);
+        write_reg(base, reg::GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+
+        // Set up the request queue
+        write_reg(base, reg::QUEUE_SEL, 0);
+        if (read_reg(base, reg::QUEUE_NUM_MAX) as usize) < QUEUE_SIZE {
+            write_reg(base, reg::STATUS, DeviceStatus::FAILED.bits());
+            return None;
+        }
+        let (Some((queue_dma, queue)), Some((request_dma, request))) =
+            (alloc_dma::<VirtQueue, D>(), alloc_dma::<Request, D>())
+        else {
+            write_reg(base, reg::STATUS, DeviceStatus::FAILED.bits());
+            return None;
+        };
+        write_reg(base, reg::QUEUE_NUM, QUEUE_SIZE as u32);
+        write_reg(base, reg::QUEUE_ALIGN, PAGE_SIZE as u32);
+        write_reg(base, reg::QUEUE_PFN, (queue as *mut VirtQueue as usize / PAGE_SIZE) as u32);
+
+        status |= DeviceStatus::DRIVER_OK;
+        write_reg(base, reg::STATUS, status.bits());
+
+        // The capacity in 512-byte sectors is the first field of the configuration space
+        let capacity = read_reg(base, reg::CONFIG) as usize
+            | (read_reg(base, reg::CONFIG + 4) as usize) << 32;
+        Some(Self {
+            inner: Mutex::new(VirtIOBlockInner {
+                base,
+                queue,
+                request,
+                last_used: 0,
+            }),
+            capacity,
+            _dma: [queue_dma, request_dma],
+        })
+    }
+}
+
+impl VirtIOBlockInner {
+    /// Submit the request in the bounce buffer and wait for its completion
+    fn submit(&mut self, req_type: u32, sector: usize) -> Result<(), BlockError> {
+        self.request.req_type = req_type;
+        self.request.sector = sector as u64;
+        self.request.status = 0xff;
+
+        // The header and the status are separated from the data, as required by the legacy interface
+        let data_flags = if req_type == VIRTIO_BLK_T_IN {
+            DescFlags::NEXT | DescFlags::WRITE
+        } else {
+            DescFlags::NEXT
+        };
+        self.queue.desc[0] = Descriptor {
+            addr: addr_of!(self.request.req_type) as u64,
+            len: 16,
+            flags: DescFlags::NEXT.bits(),
+            next: 1,
+        };
+        self.queue.desc[1] = Descriptor {
+            addr: addr_of!(self.request.data) as u64,
+            len: BLOCK_SIZE as u32,
+            flags: data_flags.bits(),
+            next: 2,
+        };
+        self.queue.desc[2] = Descriptor {
+            addr: addr_of!(self.request.status) as u64,
+            len: 1,
+            flags: DescFlags::WRITE.bits(),
+            next: 0,
+        };
+
+        let avail = &mut self.queue.avail;
+        avail.ring[avail.idx as usize % QUEUE_SIZE] = 0;
+        fence(Ordering::SeqCst);
+        avail.idx = avail.idx.wrapping_add(1);
+        fence(Ordering::SeqCst);
+        unsafe {
+            write_reg(self.base, reg::QUEUE_NOTIFY, 0);
+        }
+
+        let used_idx = addr_of_mut!(self.queue.used.idx);
+        while unsafe { used_idx.read_volatile() } == self.last_used {
+            core::hint::spin_loop();
+        }
+        fence(Ordering::SeqCst);
+        self.last_used = self.last_used.wrapping_add(1);
+        unsafe {
+            let interrupt = read_reg(self.base, reg::INTERRUPT_STATUS);
+            write_reg(self.base, reg::INTERRUPT_ACK, interrupt);
+        }
+
+        let status = unsafe { addr_of!(self.request.status).read_volatile() };
+        if status != VIRTIO_BLK_S_OK {
+            return Err(BlockError::Device(status));
+        }
+        Ok(())
+    }
+}
+
+impl<D: DmaBuffer> BlockDevice for VirtIOBlock<D> {
+    fn num_blocks(&self) -> usize {
+        self.capacity
+    }
+
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        let mut inner = self.inner.lock();
+        inner.submit(VIRTIO_BLK_T_IN, block_id)?;
+        buf.copy_from_slice(&inner.request.data);
+        Ok(())
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) -> Result<(), BlockError> {
+        let mut inner = self.inner.lock();
+        inner.request.data.copy_from_slice(buf);
+        inner.submit(VIRTIO_BLK_T_OUT, block_id)
+    }
+}
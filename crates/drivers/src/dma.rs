@@ -0,0 +1,15 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+/// Physically contiguous memory shared with a device, freed when dropped
+///
+/// The drivers do not manage the physical memory, so the kernel provides the buffers.
+pub trait DmaBuffer: Send + Sync + Sized {
+    /// Allocate `pages` zeroed pages, starting at a page boundary
+    fn alloc(pages: usize) -> Option<Self>;
+
+    /// Physical address of the buffer, which is also its address in the identity-mapped kernel
+    fn paddr(&self) -> usize;
+}
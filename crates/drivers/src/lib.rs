@@ -1,6 +1,12 @@
 #![no_std]
 
+extern crate alloc;
+
+pub mod block;
 pub mod console;
+pub mod dma;
+pub mod plic;
+pub mod rtc;
 mod uart;
 
 pub fn init_device() {
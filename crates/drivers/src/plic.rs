@@ -0,0 +1,80 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+/// Driver of the platform-level interrupt controller in the QEMU virt machine
+///
+/// Each hart has two contexts, the one for M mode at `2 * hart` and the one for S mode at
+/// `2 * hart + 1`.
+
+use core::ptr::{read_volatile, write_volatile};
+
+/// Base address of the PLIC in the QEMU virt machine
+pub const PLIC_BASE: usize = 0x0c00_0000;
+
+/// Interrupt source of the UART in the QEMU virt machine
+pub const UART_IRQ: usize = 10;
+
+const PRIORITY: usize = 0x00_0000;
+const ENABLE: usize = 0x00_2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const THRESHOLD: usize = 0x0;
+const CLAIM: usize = 0x4;
+
+/// Context of the S mode of a hart
+pub const fn supervisor_context(hart: usize) -> usize {
+    2 * hart + 1
+}
+
+pub struct Plic {
+    base: usize,
+}
+
+impl Plic {
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    fn reg(&self, offset: usize) -> *mut u32 {
+        (self.base + offset) as *mut u32
+    }
+
+    /// Set the priority of an interrupt source, where 0 disables the source
+    pub fn set_priority(&self, irq: usize, priority: u32) {
+        unsafe { write_volatile(self.reg(PRIORITY + irq * 4), priority) }
+    }
+
+    /// Enable an interrupt source for the context
+    pub fn enable(&self, context: usize, irq: usize) {
+        let reg = self.reg(ENABLE + context * ENABLE_STRIDE + irq / 32 * 4);
+        unsafe { write_volatile(reg, read_volatile(reg) | 1 << (irq % 32)) }
+    }
+
+    /// Disable an interrupt source for the context
+    pub fn disable(&self, context: usize, irq: usize) {
+        let reg = self.reg(ENABLE + context * ENABLE_STRIDE + irq / 32 * 4);
+        unsafe { write_volatile(reg, read_volatile(reg) & !(1 << (irq % 32))) }
+    }
+
+    /// Only interrupts with a priority higher than `threshold` are delivered to the context
+    pub fn set_threshold(&self, context: usize, threshold: u32) {
+        unsafe { write_volatile(self.reg(CONTEXT + context * CONTEXT_STRIDE + THRESHOLD), threshold) }
+    }
+
+    /// Claim the pending interrupt with the highest priority for the context
+    pub fn claim(&self, context: usize) -> Option<usize> {
+        let irq = unsafe { read_volatile(self.reg(CONTEXT + context * CONTEXT_STRIDE + CLAIM)) };
+        (irq != 0).then_some(irq as usize)
+    }
+
+    /// Notify the PLIC that the interrupt claimed by the context has been handled
+    pub fn complete(&self, context: usize, irq: usize) {
+        unsafe { write_volatile(self.reg(CONTEXT + context * CONTEXT_STRIDE + CLAIM), irq as u32) }
+    }
+}
+
+/// The PLIC of the machine
+pub static PLIC: Plic = Plic::new(PLIC_BASE);
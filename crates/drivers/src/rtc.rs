@@ -0,0 +1,35 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+/// Driver of the Goldfish real-time clock in the QEMU virt machine
+
+use core::ptr::read_volatile;
+
+/// Base address of the Goldfish RTC in the QEMU virt machine
+pub const RTC_BASE: usize = 0x0010_1000;
+
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+pub struct GoldfishRtc {
+    base: usize,
+}
+
+impl GoldfishRtc {
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    /// Nanoseconds since the Unix epoch
+    pub fn read_time(&self) -> u64 {
+        // Reading the low half latches the high half, so the order matters
+        let low = unsafe { read_volatile((self.base + TIME_LOW) as *const u32) };
+        let high = unsafe { read_volatile((self.base + TIME_HIGH) as *const u32) };
+        (high as u64) << 32 | low as u64
+    }
+}
+
+/// The real-time clock of the machine
+pub static RTC: GoldfishRtc = GoldfishRtc::new(RTC_BASE);
@@ -94,7 +94,8 @@ impl Uart16550<AtomicPtr<u8>> {
         unsafe { LineStatusFlags::from_bits_truncate(*self.line_status.load(Ordering::Relaxed)) }
     }
 
-    fn try_recv(&self) -> Result<Option<u8>, Error> {
+    /// Receive a byte if there is one in the FIFO
+    pub fn try_recv(&self) -> Result<Option<u8>, Error> {
         if self.line_sts().contains(LineStatusFlags::INPUT_FULL) {
             let data = unsafe { self.data.load(Ordering::Relaxed).read() };
             Ok(Some(data))
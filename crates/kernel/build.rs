@@ -4,13 +4,19 @@
 // LICENSE file in the root directory of this source tree
 
 use std::{
-    collections::BTreeMap, fs::{self, File}, path::{Path, PathBuf}
+    collections::BTreeMap, env, fs::{self, File}, os::unix::fs::PermissionsExt, path::{Path, PathBuf}
 };
 use std::io::Write;
 
-struct AppCategory {
-    name: String,
-    apps: BTreeMap<String, PathBuf>,
+/// File type bits of `st_mode`, as stored in the cpio header
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// A single entry of the initramfs
+struct Entry {
+    path: String,
+    mode: u32,
+    data: Vec<u8>,
 }
 
 fn project_root() -> PathBuf {
@@ -21,87 +27,109 @@ fn project_root() -> PathBuf {
         .to_path_buf()
 }
 
-fn insert_app_data(name_to_bin: Vec<AppCategory>) -> Result<(), std::io::Error> {
-    let mut f = File::create(project_root().join("crates/kernel/src/link_app.S")).unwrap();
+/// Append one record in the `newc` (SVR4 without CRC) format
+///
+/// Both the header with the name and the file data are padded to 4 bytes.
+fn write_newc(f: &mut File, ino: usize, path: &str, mode: u32, data: &[u8]) -> Result<(), std::io::Error> {
+    let name_size = path.len() + 1;
+    write!(
+        f,
+        "070701{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}",
+        ino, mode, 0, 0, 1, 0, data.len(), 0, 0, 0, 0, name_size, 0
+    )?;
+    f.write_all(path.as_bytes())?;
+    f.write_all(&[0])?;
+    f.write_all(&[0; 3][..(4 - (110 + name_size) % 4) % 4])?;
+    f.write_all(data)?;
+    f.write_all(&[0; 3][..(4 - data.len() % 4) % 4])?;
+    Ok(())
+}
 
-    for category in name_to_bin {
-        writeln!(
-            f,
-            r#"
-    .align 3
-    .section .data
-    .global _num_{0}
-_num_{0}:
-    .quad {1}"#,
-            category.name,
-            category.apps.len()
-        )?;
-        
-        for i in 0..category.apps.len() {
-            writeln!(f, r#"    .quad {}_{}_start"#, category.name, i)?;
+/// Pack the entries into a cpio archive
+///
+/// Parent directories are created on demand, so that every file in the archive can be reached by walking from the root.
+fn pack_initramfs(archive: &Path, entries: Vec<Entry>) -> Result<(), std::io::Error> {
+    let mut tree = BTreeMap::new();
+    for entry in entries {
+        let mut parent = Path::new(&entry.path).parent();
+        while let Some(dir) = parent.filter(|dir| !dir.as_os_str().is_empty()) {
+            tree.entry(dir.to_str().unwrap().to_string())
+                .or_insert((S_IFDIR | 0o755, Vec::new()));
+            parent = dir.parent();
         }
-        writeln!(f, r#"    .quad {}_{}_end"#, category.name, category.apps.len() - 1)?;
+        tree.insert(entry.path, (entry.mode, entry.data));
+    }
 
-        writeln!(
-            f,
-            r#"
-    .global _{0}_names
-_{0}_names:"#,
-            category.name
-        )?;
-        for (app_name, _) in category.apps.iter() {
-            writeln!(f, r#"    .string "{}""#, app_name)?;
-        }
-        for (idx, (app_name, bin)) in category.apps.iter().enumerate() {
-            println!("{}_{}: {}", category.name, idx, app_name);
-            writeln!(
-                f,
-                r#"
-    .section .data
-    .global {2}_{0}_start
-    .global {2}_{0}_end
-    .align 3
-{2}_{0}_start:
-    .incbin "{1}"
-{2}_{0}_end:"#,
-                idx,
-                bin.to_str().unwrap(),
-                category.name
-            )?;
-        }
+    let mut f = File::create(archive)?;
+    // BTreeMap keeps a directory before its children
+    for (ino, (path, (mode, data))) in tree.iter().enumerate() {
+        println!("initramfs: {:o} {}", mode, path);
+        write_newc(&mut f, ino + 1, path, *mode, data)?;
     }
+    write_newc(&mut f, 0, "TRAILER!!!", 0, &[])?;
     Ok(())
 }
 
+/// Collect the files under `dir` as entries under `prefix`, keeping their permission bits
+fn collect_dir(dir: &Path, prefix: &str, entries: &mut Vec<Entry>) {
+    for file in fs::read_dir(dir).unwrap() {
+        let path = file.unwrap().path();
+        let name = format!("{}/{}", prefix, path.file_name().unwrap().to_str().unwrap());
+        let permission = fs::metadata(&path).unwrap().permissions().mode() & 0o7777;
+        if path.is_dir() {
+            entries.push(Entry { path: name.clone(), mode: S_IFDIR | permission, data: Vec::new() });
+            collect_dir(&path, &name, entries);
+        } else {
+            entries.push(Entry { path: name, mode: S_IFREG | permission, data: fs::read(&path).unwrap() });
+        }
+    }
+}
 
-const APPS_CATEGORIES: [&str; 2] = ["app", "service"];
+/// Category of the programs, and the directory they are installed into
+const APPS_CATEGORIES: [(&str, &str); 2] = [("app", "bin"), ("service", "sbin")];
 
 fn main() {
     println!("cargo:rustc-link-arg=-Tcrates/kernel/src/linker.ld");
-    println!("cargo:rustc-env=RUSTFLAGS=-Cforce-frame-pointers=yes");
-    let mut name_to_bin = Vec::new();
-    for category in &APPS_CATEGORIES {
-        println!("cargo:rerun-if-changed=../{}", category);
-        let mut app_name_to_bin = BTreeMap::new();
-        let target = "riscv64gc-unknown-none-elf";
-        let mode = "debug";
-        let app_dir = project_root().join("crates").join(category).join("src/bin");
-        let target_dir = project_root().join("target").join(target).join(mode);
-        let apps: Vec<_> = fs::read_dir(app_dir)
-            .unwrap()
-            .map(|res| res.map(|e| e.path()))
-            .collect::<Result<Vec<_>, std::io::Error>>()
-            .unwrap();
+    let target = "riscv64gc-unknown-none-elf";
+    // The apps are built by the same cargo invocation, so they share the profile of the kernel
+    let mode = env::var("PROFILE").unwrap();
+    let target_dir = project_root().join("target").join(target).join(&mode);
 
-        for app in &apps {
-            let app_name = app.file_stem().unwrap().to_str().unwrap();
-            let elf = target_dir.join(app_name);
-            app_name_to_bin.insert(app_name.to_string(), elf.clone());
+    let mut entries = Vec::new();
+    for (category, install_dir) in &APPS_CATEGORIES {
+        let crate_dir = project_root().join("crates").join(category);
+        println!("cargo:rerun-if-changed={}", crate_dir.join("src").display());
+        println!("cargo:rerun-if-changed={}", crate_dir.join("Cargo.toml").display());
+        let app_dir = crate_dir.join("src/bin");
+        // Sorted, so that the archive does not depend on the order of the directory entries
+        let mut app_names: Vec<String> = fs::read_dir(app_dir)
+            .unwrap()
+            .map(|app| app.unwrap().path().file_stem().unwrap().to_str().unwrap().to_string())
+            .collect();
+        app_names.sort();
+        for app_name in app_names {
+            let elf = target_dir.join(&app_name);
+            // The apps may be rebuilt without their sources changing, e.g. with another profile
+            println!("cargo:rerun-if-changed={}", elf.display());
+            entries.push(Entry {
+                path: format!("{}/{}", install_dir, app_name),
+                mode: S_IFREG | 0o755,
+                data: fs::read(&elf).unwrap_or_else(|_| panic!("{} is not built", elf.display())),
+            });
         }
-        name_to_bin.push(AppCategory {
-            name: category.to_string(),
-            apps: app_name_to_bin,
+    }
+
+    // Extra files to be placed into the root file system as is
+    let rootfs = project_root().join("rootfs");
+    println!("cargo:rerun-if-changed={}", rootfs.display());
+    if rootfs.is_dir() {
+        collect_dir(&rootfs, "", &mut entries);
+        entries.iter_mut().for_each(|entry| {
+            entry.path = entry.path.trim_start_matches('/').to_string();
         });
     }
-    insert_app_data(name_to_bin).unwrap();
+
+    let archive = PathBuf::from(env::var("OUT_DIR").unwrap()).join("initramfs.cpio");
+    pack_initramfs(&archive, entries).unwrap();
+    println!("cargo:rustc-env=INITRAMFS={}", archive.display());
 }
@@ -3,12 +3,63 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
-use allocator::LockedBuddyAllocator;
+use core::{alloc::{GlobalAlloc, Layout}, cmp::max};
 
-use crate::config::KERNEL_HEAP_SIZE;
+use allocator::{BuddyStats, LockedSlabAllocator};
+use ksync::interrupt;
+
+use crate::{
+    config::{KERNEL_HEAP_GROW_SIZE, KERNEL_HEAP_SIZE, PAGE_SIZE},
+    mm::frame_alloc_heap,
+};
+
+/// The kernel heap, which disables interrupts while allocating
+///
+/// The lock of the slab allocator does not disable interrupts by itself, so a trap handler
+/// allocating on the same hart would spin on it forever.
+///
+/// The heap starts with `KERNEL_HEAP_SPACE` and takes more frames from the frame allocator when
+/// it runs out. The frame allocator never allocates from the heap while being locked, so this
+/// does not deadlock.
+struct KernelHeap(LockedSlabAllocator);
+
+impl KernelHeap {
+    /// Add frames to the heap, enough for an allocation of the layout
+    fn grow(&self, layout: &Layout) -> bool {
+        // A block is only carved from a range aligned to its size, which twice its size
+        // always contains
+        let block = max(layout.size().next_power_of_two(), layout.align());
+        let size = max(KERNEL_HEAP_GROW_SIZE, block * 2);
+        let count = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+        let Some((start, count)) = frame_alloc_heap(count) else {
+            return false;
+        };
+        let start = usize::from(start);
+        unsafe { self.0.add_segment(start, start + count * PAGE_SIZE) };
+        true
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let irq_enabled = interrupt::disable();
+        let mut ptr = self.0.alloc(layout);
+        if ptr.is_null() && self.grow(&layout) {
+            ptr = self.0.alloc(layout);
+        }
+        interrupt::restore(irq_enabled);
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let irq_enabled = interrupt::disable();
+        self.0.dealloc(ptr, layout);
+        interrupt::restore(irq_enabled);
+    }
+}
 
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedBuddyAllocator = LockedBuddyAllocator::empty();
+static HEAP_ALLOCATOR: KernelHeap = KernelHeap(LockedSlabAllocator::empty());
 
 static mut KERNEL_HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 
@@ -16,6 +67,14 @@ pub fn init_heap_allocator() {
     unsafe {
         let start = KERNEL_HEAP_SPACE.as_ptr() as usize;
         let end = start + KERNEL_HEAP_SPACE.len();
-        HEAP_ALLOCATOR.init(start, end);
+        HEAP_ALLOCATOR.0.init(start, end);
     }
 }
+
+/// Get the usage of the kernel heap
+pub fn heap_stats() -> BuddyStats {
+    let irq_enabled = interrupt::disable();
+    let stats = HEAP_ALLOCATOR.0.stats();
+    interrupt::restore(irq_enabled);
+    stats
+}
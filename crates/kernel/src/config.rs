@@ -3,15 +3,29 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
+use crate::sched::policy::PolicyKind;
+
 pub const KERNEL_STACK_SIZE: usize = 4096 * 8;
 pub const KERNEL_STACK_NUM: usize = 256;
 pub const USER_STACK_SIZE: usize = 4096 * 4;
+/// Size the user stack may grow to, above the guard page at its limit
+pub const USER_STACK_MAX_SIZE: usize = 4096 * 64;
+/// Whether to grow the user stack on a fault below it, rather than kill the process
+pub const USER_STACK_GROWTH: bool = true;
+/// Stack of each hart for handling a kernel stack overflow
+pub const OVERFLOW_STACK_SIZE: usize = 4096 * 4;
 
 pub const APP_MAX_NUM: usize = 16;
 pub const APP_BASE_ADDRESS: usize = 0x80400000;
 pub const APP_SIZE_LIMIT: usize = 0x20000;
 
 pub const KERNEL_HEAP_SIZE: usize = 0x300000;
+/// Least memory the kernel heap takes from the frame allocator when it runs out
+pub const KERNEL_HEAP_GROW_SIZE: usize = 0x40000;
+/// Whether to kill the process with the most memory when a process runs out of memory
+pub const OOM_KILLER: bool = true;
+/// Number of pages evicted to the swap area at once when there is no frame left
+pub const SWAP_RECLAIM_BATCH: usize = 32;
 
 pub const MEMORY_END: usize = 0x88000000;
 
@@ -21,10 +35,24 @@ pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
 pub const SERVICE_SEND_PORT: usize = TRAMPOLINE - PAGE_SIZE * 4;
 pub const SERVICE_RECV_PORT: usize = TRAMPOLINE - PAGE_SIZE * 7;
+/// Range of the user addresses where the shared memory objects are mapped
+pub const SHM_BASE: usize = 0x20_0000_0000;
+pub const SHM_END: usize = 0x40_0000_0000;
+
+/// Maximum number of harts, the same as `NUM_HART_MAX` in the firmware
+pub const MAX_HARTS: usize = 8;
+pub const BOOT_STACK_SIZE: usize = 4096 * 16;
 
 pub const CLOCK_FREQ: usize = 12500000;
 pub const MMIO: &[(usize, usize)] = &[
     (0x0010_0000, 0x00_2000), // VIRT_TEST/RTC  in virt machine
+    (0x0c00_0000, 0x40_0000), // PLIC in virt machine
+    (0x1000_0000, 0x00_1000), // UART in virt machine
+    (0x1000_1000, 0x00_8000), // VIRTIO in virt machine
 ];
+/// Number of interrupt sources of the PLIC in virt machine, where the source 0 is reserved
+pub const PLIC_SOURCES: usize = 96;
 
 pub const LOG: bool = false;
+
+pub const SCHED_POLICY: PolicyKind = PolicyKind::RoundRobin;
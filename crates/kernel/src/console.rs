@@ -3,42 +3,97 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
-use core::fmt::{Arguments, Write};
+use drivers::{console::UART, plic::UART_IRQ};
+use ksync::SpinLock;
+use lazy_static::lazy_static;
 
-use crate::sbi::{console_getchar, console_putchar};
+use crate::{
+    sched::{block_current_and_run_next, WaitQueue},
+    trap::register_irq_handler,
+};
 
-pub struct Stdin; // Standard input agent
-pub struct Stdout; // Standard output agent
+pub use drivers::console::Stdout; // Standard output agent
 
-impl Stdin {
-    pub fn getchar(&self) -> char {
-        console_getchar() as u8 as char
+/// Capacity of the console input buffer, further input is dropped when it is full
+const INPUT_BUFFER_SIZE: usize = 256;
+
+/// Ring buffer of the bytes received from the UART but not read yet
+struct InputBuffer {
+    buf: [u8; INPUT_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl InputBuffer {
+    const fn new() -> Self {
+        Self {
+            buf: [0; INPUT_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        }
     }
 
-    pub fn read(&mut self, buf: &mut [u8]) -> usize {
-        let mut i = 0;
-        while i < buf.len() {
-            buf[i] = self.getchar() as u8;
-            i += 1;
+    fn push(&mut self, byte: u8) -> bool {
+        if self.len == INPUT_BUFFER_SIZE {
+            return false;
         }
-        i
+        self.buf[(self.head + self.len) % INPUT_BUFFER_SIZE] = byte;
+        self.len += 1;
+        true
     }
-}
 
-impl Stdout {
-    pub fn putchar(&self, c: char) {
-        console_putchar(c as usize)
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buf[self.head];
+        self.head = (self.head + 1) % INPUT_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
     }
+}
+
+lazy_static! {
+    static ref INPUT: SpinLock<InputBuffer> = SpinLock::new(InputBuffer::new());
+    /// Threads waiting for the console input
+    static ref INPUT_WAITERS: SpinLock<WaitQueue> = SpinLock::new(WaitQueue::default());
+}
+
+pub struct Stdin; // Standard input agent
 
-    pub fn print(&mut self, args: Arguments) {
-        self.write_fmt(args).unwrap();
+impl Stdin {
+    /// Read the buffered input into `buf`, blocking until at least one byte is available
+    ///
+    /// Return the number of bytes read.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        loop {
+            let mut input = INPUT.lock();
+            if input.len > 0 {
+                let count = buf.len().min(input.len);
+                buf[..count].iter_mut().for_each(|byte| *byte = input.pop().unwrap());
+                return count;
+            }
+            block_current_and_run_next(&INPUT_WAITERS, input);
+        }
     }
 }
 
-impl Write for Stdout {
-    fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        s.chars().for_each(|c| self.putchar(c));
-        Ok(())
+/// Receive the console input through the interrupts of the UART
+pub fn init() {
+    register_irq_handler(UART_IRQ, handle_uart_interrupt);
+}
+
+/// Move the received bytes from the UART into the input buffer, and wake up the readers
+fn handle_uart_interrupt() {
+    let mut input = INPUT.lock();
+    while let Ok(Some(byte)) = UART.try_recv() {
+        input.push(byte);
+    }
+    if input.len > 0 {
+        INPUT_WAITERS.lock().wake_all();
     }
 }
 
@@ -65,4 +120,4 @@ macro_rules! log {
             $crate::println!($($arg)*);
         }
     }
-}
\ No newline at end of file
+}
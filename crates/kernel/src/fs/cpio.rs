@@ -0,0 +1,159 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+/// Reader of cpio archives in the `newc` format, which is the format of Linux initramfs
+///
+/// Each record consists of a 110-byte ASCII header, the NUL-terminated path name and the file data,
+/// where both the header with the name and the data are padded to 4 bytes. The archive ends with
+/// a record named `TRAILER!!!`.
+
+const NEWC_MAGIC: &[u8] = b"070701";
+const NEWC_CRC_MAGIC: &[u8] = b"070702";
+const NEWC_HEADER_SIZE: usize = 110;
+const NEWC_TRAILER: &str = "TRAILER!!!";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// A file or directory in the archive
+#[derive(Clone, Copy)]
+pub struct CpioEntry {
+    /// Path relative to the root, without leading `/` or `./`
+    pub name: &'static str,
+    pub mode: u32,
+    pub data: &'static [u8],
+}
+
+impl CpioEntry {
+    /// The root directory, which does not necessarily have a record in the archive
+    pub const fn root() -> Self {
+        Self {
+            name: "",
+            mode: S_IFDIR | 0o755,
+            data: &[],
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+
+    /// Get the permission bits of the entry
+    pub fn permission(&self) -> u32 {
+        self.mode & 0o7777
+    }
+
+    /// Get the last component of the path
+    pub fn file_name(&self) -> &'static str {
+        self.name.rsplit('/').next().unwrap()
+    }
+}
+
+/// A read-only view of a `newc` archive
+#[derive(Clone, Copy)]
+pub struct CpioArchive {
+    data: &'static [u8],
+}
+
+/// Iterator over the records of an archive
+pub struct CpioIter {
+    data: &'static [u8],
+    offset: usize,
+}
+
+fn align4(v: usize) -> usize {
+    (v + 3) & !3
+}
+
+/// Strip the leading `/` and `./` of a path, so that it can be compared with the names in the archive
+fn normalize(path: &str) -> &str {
+    let mut path = path;
+    loop {
+        if let Some(rest) = path.strip_prefix("./") {
+            path = rest;
+        } else if let Some(rest) = path.strip_prefix('/') {
+            path = rest;
+        } else {
+            break;
+        }
+    }
+    match path.trim_end_matches('/') {
+        "." => "",
+        path => path,
+    }
+}
+
+impl CpioIter {
+    /// Parse a header field of 8 hexadecimal digits
+    fn field(&self, idx: usize) -> Option<usize> {
+        let start = self.offset + 6 + idx * 8;
+        let digits = core::str::from_utf8(self.data.get(start..start + 8)?).ok()?;
+        usize::from_str_radix(digits, 16).ok()
+    }
+}
+
+impl Iterator for CpioIter {
+    type Item = CpioEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let magic = self.data.get(self.offset..self.offset + 6)?;
+        if magic != NEWC_MAGIC && magic != NEWC_CRC_MAGIC {
+            return None;
+        }
+        let mode = self.field(1)? as u32;
+        let file_size = self.field(6)?;
+        let name_size = self.field(11)?;
+
+        let name_start = self.offset + NEWC_HEADER_SIZE;
+        // The name size includes the terminating NUL
+        let name = self.data.get(name_start..name_start + name_size.checked_sub(1)?)?;
+        let name = core::str::from_utf8(name).ok()?;
+        if name == NEWC_TRAILER {
+            return None;
+        }
+
+        let data_start = align4(name_start + name_size);
+        let data = self.data.get(data_start..data_start + file_size)?;
+        self.offset = align4(data_start + file_size);
+        Some(CpioEntry {
+            name: normalize(name),
+            mode,
+            data,
+        })
+    }
+}
+
+impl CpioArchive {
+    pub const fn new(data: &'static [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn iter(&self) -> CpioIter {
+        CpioIter {
+            data: self.data,
+            offset: 0,
+        }
+    }
+
+    /// Find the entry with the given path
+    pub fn lookup(&self, path: &str) -> Option<CpioEntry> {
+        let path = normalize(path);
+        self.iter().find(|entry| entry.name == path)
+    }
+
+    /// Iterate over the direct children of a directory
+    pub fn read_dir<'a>(&self, path: &'a str) -> impl Iterator<Item = CpioEntry> + 'a {
+        let path = normalize(path);
+        self.iter().filter(move |entry| {
+            let parent = entry.name.rsplit_once('/').map_or("", |(parent, _)| parent);
+            !entry.name.is_empty() && parent == path
+        })
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+/// Boot sector signature at the end of both the BPB and the MBR
+const BOOT_SIGNATURE: u16 = 0xaa55;
+
+/// MBR partition types of FAT32 (CHS and LBA addressing)
+const PARTITION_FAT32: [u8; 2] = [0x0b, 0x0c];
+const PARTITION_TABLE_OFFSET: usize = 446;
+const PARTITION_ENTRY_SIZE: usize = 16;
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// The fields of the BIOS parameter block used by a FAT32 reader
+pub struct BiosParameterBlock {
+    pub bytes_per_sector: usize,
+    pub sectors_per_cluster: usize,
+    pub reserved_sectors: usize,
+    pub num_fats: usize,
+    pub total_sectors: usize,
+    /// Sectors occupied by one FAT
+    pub fat_size: usize,
+    pub root_cluster: u32,
+}
+
+impl BiosParameterBlock {
+    /// Parse the boot sector of a volume, return `None` if it is not FAT32
+    pub fn parse(sector: &[u8]) -> Option<Self> {
+        if read_u16(sector, 510) != BOOT_SIGNATURE || !matches!(sector[0], 0xeb | 0xe9) {
+            return None;
+        }
+        let bpb = Self {
+            bytes_per_sector: read_u16(sector, 11) as usize,
+            sectors_per_cluster: sector[13] as usize,
+            reserved_sectors: read_u16(sector, 14) as usize,
+            num_fats: sector[16] as usize,
+            total_sectors: match read_u16(sector, 19) {
+                0 => read_u32(sector, 32) as usize,
+                total => total as usize,
+            },
+            fat_size: read_u32(sector, 36) as usize,
+            root_cluster: read_u32(sector, 44),
+        };
+        // FAT12/16 have a fixed root directory and a 16-bit FAT size
+        let root_entries = read_u16(sector, 17);
+        let fat_size_16 = read_u16(sector, 22);
+        let valid = matches!(bpb.bytes_per_sector, 512 | 1024 | 2048 | 4096)
+            && bpb.sectors_per_cluster.is_power_of_two()
+            && bpb.num_fats > 0
+            && bpb.fat_size > 0
+            && root_entries == 0
+            && fat_size_16 == 0;
+        valid.then_some(bpb)
+    }
+
+    /// Byte offset of the first FAT in the volume
+    pub fn fat_offset(&self) -> usize {
+        self.reserved_sectors * self.bytes_per_sector
+    }
+
+    /// Byte offset of cluster 2, the first data cluster, in the volume
+    pub fn data_offset(&self) -> usize {
+        (self.reserved_sectors + self.num_fats * self.fat_size) * self.bytes_per_sector
+    }
+
+    pub fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster * self.bytes_per_sector
+    }
+
+    /// Number of data clusters in the volume
+    pub fn cluster_count(&self) -> usize {
+        let data_sectors = self
+            .total_sectors
+            .saturating_sub(self.data_offset() / self.bytes_per_sector);
+        data_sectors / self.sectors_per_cluster
+    }
+}
+
+/// Find the sector where the first FAT32 partition of an MBR partitioned disk starts
+pub fn find_partition(sector: &[u8]) -> Option<usize> {
+    if read_u16(sector, 510) != BOOT_SIGNATURE {
+        return None;
+    }
+    (0..4)
+        .map(|i| &sector[PARTITION_TABLE_OFFSET + i * PARTITION_ENTRY_SIZE..][..PARTITION_ENTRY_SIZE])
+        .find(|entry| PARTITION_FAT32.contains(&entry[4]))
+        .map(|entry| read_u32(entry, 8) as usize)
+}
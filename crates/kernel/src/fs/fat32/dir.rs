@@ -0,0 +1,149 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use alloc::{string::String, vec::Vec};
+
+pub const DIR_ENTRY_SIZE: usize = 32;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+/// Long file name entries are marked by READ_ONLY | HIDDEN | SYSTEM | VOLUME_ID
+const ATTR_LONG_NAME: u8 = 0x0f;
+
+const ENTRY_END: u8 = 0x00;
+const ENTRY_FREE: u8 = 0xe5;
+/// A short name starting with 0xe5 is stored as 0x05
+const ENTRY_KANJI: u8 = 0x05;
+const LAST_LONG_ENTRY: u8 = 0x40;
+const LONG_NAME_CHARS: usize = 13;
+const MAX_LONG_ENTRIES: usize = 20;
+
+/// Flags in the reserved byte used by Windows NT to keep the case of short names
+const NT_LOWER_BASE: u8 = 0x08;
+const NT_LOWER_EXT: u8 = 0x10;
+
+/// A file or directory in a FAT directory
+pub struct DirEntry {
+    pub name: String,
+    pub attr: u8,
+    pub first_cluster: u32,
+    pub size: usize,
+}
+
+impl DirEntry {
+    pub fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+}
+
+/// Result of feeding a raw entry to `DirParser`
+pub enum DirRecord {
+    Entry(DirEntry),
+    Skip,
+    End,
+}
+
+/// Parser of a sequence of raw directory entries
+///
+/// Long file name entries precede the short entry they belong to, in descending order, so they are
+/// collected until the short entry arrives and then checked against its checksum.
+#[derive(Default)]
+pub struct DirParser {
+    long_name: Vec<[u16; LONG_NAME_CHARS]>,
+    checksum: u8,
+    /// The order of the last long entry seen, 0 if there is none pending
+    order: usize,
+}
+
+/// Checksum of the 11-byte short name, stored in each of its long entries
+fn short_name_checksum(name: &[u8]) -> u8 {
+    name.iter()
+        .fold(0u8, |sum, &c| (sum >> 1).wrapping_add(sum << 7).wrapping_add(c))
+}
+
+fn short_name(raw: &[u8]) -> String {
+    let mut base = raw[0..8].to_vec();
+    let mut ext = raw[8..11].to_vec();
+    if base[0] == ENTRY_KANJI {
+        base[0] = ENTRY_FREE;
+    }
+    if raw[12] & NT_LOWER_BASE != 0 {
+        base.make_ascii_lowercase();
+    }
+    if raw[12] & NT_LOWER_EXT != 0 {
+        ext.make_ascii_lowercase();
+    }
+    let base = String::from_utf8_lossy(&base);
+    let ext = String::from_utf8_lossy(&ext);
+    let (base, ext) = (base.trim_end(), ext.trim_end());
+    if ext.is_empty() {
+        base.into()
+    } else {
+        alloc::format!("{}.{}", base, ext)
+    }
+}
+
+impl DirParser {
+    fn feed_long(&mut self, raw: &[u8]) {
+        let order = (raw[0] & !LAST_LONG_ENTRY) as usize;
+        if raw[0] & LAST_LONG_ENTRY != 0 {
+            if order == 0 || order > MAX_LONG_ENTRIES {
+                self.order = 0;
+                return;
+            }
+            self.long_name = alloc::vec![[0xffff; LONG_NAME_CHARS]; order];
+            self.checksum = raw[13];
+        } else if self.order == 0 || order + 1 != self.order || raw[13] != self.checksum {
+            // Orphaned long entry
+            self.order = 0;
+            return;
+        }
+        let chars = &mut self.long_name[order - 1];
+        let offsets = (1..11).step_by(2).chain((14..26).step_by(2)).chain((28..32).step_by(2));
+        for (c, offset) in chars.iter_mut().zip(offsets) {
+            *c = u16::from_le_bytes([raw[offset], raw[offset + 1]]);
+        }
+        self.order = order;
+    }
+
+    /// Take the pending long name if it belongs to the short entry
+    fn take_long_name(&mut self, raw: &[u8]) -> Option<String> {
+        let complete = self.order == 1 && short_name_checksum(&raw[0..11]) == self.checksum;
+        self.order = 0;
+        if !complete {
+            return None;
+        }
+        let units = self.long_name.iter().flatten().copied().take_while(|&c| c != 0x0000 && c != 0xffff);
+        Some(char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect())
+    }
+
+    pub fn feed(&mut self, raw: &[u8]) -> DirRecord {
+        match raw[0] {
+            ENTRY_END => return DirRecord::End,
+            ENTRY_FREE => {
+                self.order = 0;
+                return DirRecord::Skip;
+            }
+            _ => {}
+        }
+        let attr = raw[11];
+        if attr & ATTR_LONG_NAME == ATTR_LONG_NAME {
+            self.feed_long(raw);
+            return DirRecord::Skip;
+        }
+        let long_name = self.take_long_name(raw);
+        if attr & ATTR_VOLUME_ID != 0 {
+            return DirRecord::Skip;
+        }
+        let first_cluster = (u16::from_le_bytes([raw[20], raw[21]]) as u32) << 16
+            | u16::from_le_bytes([raw[26], raw[27]]) as u32;
+        DirRecord::Entry(DirEntry {
+            name: long_name.unwrap_or_else(|| short_name(raw)),
+            attr,
+            first_cluster,
+            size: u32::from_le_bytes([raw[28], raw[29], raw[30], raw[31]]) as usize,
+        })
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+/// Read-only FAT32 file system
+///
+/// Reference: Microsoft Extensible Firmware Initiative FAT32 File System Specification
+
+mod bpb;
+mod dir;
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use drivers::block::{BlockDevice, BlockError, BLOCK_SIZE};
+
+use bpb::{find_partition, BiosParameterBlock};
+use dir::{DirParser, DirRecord, DirEntry, DIR_ENTRY_SIZE};
+
+use super::vfs::{FileSystem, Inode};
+
+/// FAT entries not smaller than this mark the end of a cluster chain
+const FAT_END_OF_CHAIN: u32 = 0x0fff_fff8;
+/// Only the low 28 bits of a FAT32 entry are used
+const FAT_ENTRY_MASK: u32 = 0x0fff_ffff;
+
+/// A FAT32 volume on a block device
+struct Volume {
+    device: Arc<dyn BlockDevice>,
+    /// Byte offset of the volume on the device, non-zero on a partitioned disk
+    start: usize,
+    bpb: BiosParameterBlock,
+}
+
+impl Volume {
+    /// Read `buf.len()` bytes at the byte offset `offset` of the device
+    fn read_device(
+        device: &Arc<dyn BlockDevice>,
+        mut offset: usize,
+        buf: &mut [u8],
+    ) -> Result<(), BlockError> {
+        let mut block = [0u8; BLOCK_SIZE];
+        let mut done = 0;
+        while done < buf.len() {
+            let start = offset % BLOCK_SIZE;
+            let len = (BLOCK_SIZE - start).min(buf.len() - done);
+            device.read_block(offset / BLOCK_SIZE, &mut block)?;
+            buf[done..done + len].copy_from_slice(&block[start..start + len]);
+            done += len;
+            offset += len;
+        }
+        Ok(())
+    }
+
+    /// Read `buf.len()` bytes at the byte offset `offset` of the volume
+    fn read_bytes(&self, offset: usize, buf: &mut [u8]) -> Result<(), BlockError> {
+        Self::read_device(&self.device, self.start + offset, buf)
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> usize {
+        self.bpb.data_offset() + (cluster as usize - 2) * self.bpb.cluster_size()
+    }
+
+    fn is_data_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && (cluster as usize) < self.bpb.cluster_count() + 2
+    }
+
+    /// Look up the next cluster of the chain in the first FAT, a failed read ends the chain
+    fn next_cluster(&self, cluster: u32) -> Option<u32> {
+        let mut entry = [0u8; 4];
+        self.read_bytes(self.bpb.fat_offset() + cluster as usize * 4, &mut entry).ok()?;
+        let next = u32::from_le_bytes(entry) & FAT_ENTRY_MASK;
+        (next < FAT_END_OF_CHAIN && self.is_data_cluster(next)).then_some(next)
+    }
+
+    /// Iterate over the cluster chain starting at `first`
+    fn clusters(&self, first: u32) -> impl Iterator<Item = u32> + '_ {
+        let first = self.is_data_cluster(first).then_some(first);
+        // Bound the length of the chain, in case the FAT contains a loop
+        core::iter::successors(first, |&cluster| self.next_cluster(cluster))
+            .take(self.bpb.cluster_count())
+    }
+}
+
+/// A file or directory on a FAT32 volume
+pub struct FatInode {
+    volume: Arc<Volume>,
+    first_cluster: u32,
+    /// Size of a file, directories are recorded with size 0
+    size: usize,
+    is_dir: bool,
+}
+
+impl FatInode {
+    fn new(volume: &Arc<Volume>, entry: &DirEntry) -> Self {
+        // The `..` entry of a subdirectory of the root records cluster 0
+        let first_cluster = match entry.first_cluster {
+            0 if entry.is_dir() => volume.bpb.root_cluster,
+            cluster => cluster,
+        };
+        Self {
+            volume: volume.clone(),
+            first_cluster,
+            size: entry.size,
+            is_dir: entry.is_dir(),
+        }
+    }
+
+    /// Read the whole directory and parse its entries, up to the first failed read
+    fn dir_entries(&self) -> Vec<DirEntry> {
+        let cluster_size = self.volume.bpb.cluster_size();
+        let mut buf = vec![0u8; cluster_size];
+        let mut parser = DirParser::default();
+        let mut entries = Vec::new();
+        for cluster in self.volume.clusters(self.first_cluster) {
+            if self.volume.read_bytes(self.volume.cluster_offset(cluster), &mut buf).is_err() {
+                break;
+            }
+            for raw in buf.chunks(DIR_ENTRY_SIZE) {
+                match parser.feed(raw) {
+                    DirRecord::Entry(entry) => entries.push(entry),
+                    DirRecord::Skip => {}
+                    DirRecord::End => return entries,
+                }
+            }
+        }
+        entries
+    }
+}
+
+impl Inode for FatInode {
+    fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        if self.is_dir || offset >= self.size {
+            return 0;
+        }
+        let len = buf.len().min(self.size - offset);
+        let cluster_size = self.volume.bpb.cluster_size();
+        let mut done = 0;
+        for cluster in self.volume.clusters(self.first_cluster).skip(offset / cluster_size) {
+            if done == len {
+                break;
+            }
+            let start = (offset + done) % cluster_size;
+            let count = (cluster_size - start).min(len - done);
+            // A failed read makes a short read
+            let pos = self.volume.cluster_offset(cluster) + start;
+            if self.volume.read_bytes(pos, &mut buf[done..done + count]).is_err() {
+                break;
+            }
+            done += count;
+        }
+        done
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        if !self.is_dir {
+            return None;
+        }
+        // Names in FAT are case-insensitive
+        self.dir_entries()
+            .iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+            .map(|entry| Arc::new(FatInode::new(&self.volume, entry)) as Arc<dyn Inode>)
+    }
+}
+
+/// A mountable FAT32 file system
+pub struct Fat32 {
+    volume: Arc<Volume>,
+}
+
+impl Fat32 {
+    /// Open the FAT32 file system on the device, either on the whole device or on its first FAT32 partition
+    pub fn open(device: Arc<dyn BlockDevice>) -> Option<Self> {
+        let mut sector = [0u8; BLOCK_SIZE];
+        device.read_block(0, &mut sector).ok()?;
+        let (start, bpb) = match BiosParameterBlock::parse(&sector) {
+            Some(bpb) => (0, bpb),
+            None => {
+                let start = find_partition(&sector)? * BLOCK_SIZE;
+                Volume::read_device(&device, start, &mut sector).ok()?;
+                (start, BiosParameterBlock::parse(&sector)?)
+            }
+        };
+        Some(Self {
+            volume: Arc::new(Volume { device, start, bpb }),
+        })
+    }
+}
+
+impl FileSystem for Fat32 {
+    fn root(&self) -> Arc<dyn Inode> {
+        Arc::new(FatInode {
+            volume: self.volume.clone(),
+            first_cluster: self.volume.bpb.root_cluster,
+            size: 0,
+            is_dir: true,
+        })
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use bitflags::bitflags;
+use ksync::SpinLock;
+use lazy_static::lazy_static;
+
+use super::vfs::{lookup, Inode};
+use crate::sched::Mutex;
+
+bitflags! {
+    /// Flags of `open`
+    pub struct OpenFlags: u32 {
+        const RDONLY = 0;
+        const WRONLY = 1 << 0;
+        const RDWR = 1 << 1;
+        const CREATE = 1 << 9;
+        const TRUNC = 1 << 10;
+    }
+}
+
+/// A file opened by a process, sharing the offset between forked processes
+pub struct OpenFile {
+    inode: Arc<dyn Inode>,
+    offset: usize,
+}
+
+impl OpenFile {
+    pub fn is_dir(&self) -> bool {
+        self.inode.is_dir()
+    }
+
+    /// Read from the current offset and move the offset forward
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let len = self.inode.read_at(self.offset, buf);
+        self.offset += len;
+        len
+    }
+}
+
+/// File descriptors of a process, the descriptor `i` is stored at `i - FD_FIRST`
+type FdTable = Vec<Option<Arc<Mutex<OpenFile>>>>;
+
+/// Descriptors below are reserved for the console
+const FD_FIRST: usize = 3;
+
+lazy_static! {
+    /// File descriptor tables of the processes, indexed by pid
+    static ref FD_TABLES: SpinLock<BTreeMap<usize, FdTable>> = SpinLock::new(BTreeMap::new());
+}
+
+/// Open the file at `path` for the process, return the new file descriptor
+pub fn open_file(pid: usize, path: &str, flags: OpenFlags) -> Option<usize> {
+    // All the file systems are read-only
+    if flags.intersects(OpenFlags::WRONLY | OpenFlags::RDWR | OpenFlags::CREATE | OpenFlags::TRUNC) {
+        return None;
+    }
+    let file = Arc::new(Mutex::new(OpenFile { inode: lookup(path)?, offset: 0 }));
+    let mut fd_tables = FD_TABLES.lock();
+    let fd_table = fd_tables.entry(pid).or_default();
+    let idx = if let Some(idx) = fd_table.iter().position(|file| file.is_none()) {
+        fd_table[idx] = Some(file);
+        idx
+    } else {
+        fd_table.push(Some(file));
+        fd_table.len() - 1
+    };
+    Some(idx + FD_FIRST)
+}
+
+/// Close a file descriptor of the process, return whether it was open
+pub fn close_file(pid: usize, fd: usize) -> bool {
+    let mut fd_tables = FD_TABLES.lock();
+    fd.checked_sub(FD_FIRST)
+        .and_then(|idx| fd_tables.get_mut(&pid)?.get_mut(idx)?.take())
+        .is_some()
+}
+
+pub fn get_file(pid: usize, fd: usize) -> Option<Arc<Mutex<OpenFile>>> {
+    let fd_tables = FD_TABLES.lock();
+    fd_tables.get(&pid)?.get(fd.checked_sub(FD_FIRST)?)?.clone()
+}
+
+/// Let a forked child inherit the opened files of its parent
+pub fn fork_files(pid: usize, child_pid: usize) {
+    let mut fd_tables = FD_TABLES.lock();
+    if let Some(fd_table) = fd_tables.get(&pid).cloned() {
+        fd_tables.insert(child_pid, fd_table);
+    }
+}
+
+/// Close all the files of an exited process
+pub fn remove_files(pid: usize) {
+    FD_TABLES.lock().remove(&pid);
+}
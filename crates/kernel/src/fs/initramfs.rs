@@ -0,0 +1,74 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use alloc::{format, sync::Arc};
+use lazy_static::lazy_static;
+
+use super::cpio::{CpioArchive, CpioEntry};
+use super::vfs::{FileSystem, Inode};
+
+/// Wrapper to place the embedded archive at a page boundary
+#[repr(C, align(4096))]
+struct Aligned<T: ?Sized>(T);
+
+/// The initramfs packed by `build.rs`
+static INITRAMFS: &Aligned<[u8]> = &Aligned(*include_bytes!(env!("INITRAMFS")));
+
+lazy_static! {
+    /// The initial root file system, unpacked from the initramfs at boot
+    pub static ref ROOTFS: CpioArchive = CpioArchive::new(&INITRAMFS.0);
+}
+
+/// A file or directory in the initramfs
+pub struct CpioInode {
+    archive: CpioArchive,
+    entry: CpioEntry,
+}
+
+impl Inode for CpioInode {
+    fn is_dir(&self) -> bool {
+        self.entry.is_dir()
+    }
+
+    fn size(&self) -> usize {
+        self.entry.data.len()
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let data = self.entry.data;
+        if offset >= data.len() {
+            return 0;
+        }
+        let len = buf.len().min(data.len() - offset);
+        buf[..len].copy_from_slice(&data[offset..offset + len]);
+        len
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        if !self.entry.is_dir() {
+            return None;
+        }
+        let path = if self.entry.name.is_empty() {
+            name.into()
+        } else {
+            format!("{}/{}", self.entry.name, name)
+        };
+        self.archive.lookup(&path).map(|entry| {
+            Arc::new(CpioInode {
+                archive: self.archive,
+                entry,
+            }) as Arc<dyn Inode>
+        })
+    }
+}
+
+impl FileSystem for CpioArchive {
+    fn root(&self) -> Arc<dyn Inode> {
+        Arc::new(CpioInode {
+            archive: *self,
+            entry: CpioEntry::root(),
+        })
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+mod cpio;
+mod fat32;
+mod file;
+mod initramfs;
+mod vfs;
+
+use alloc::sync::Arc;
+use drivers::block::probe_block_devices;
+
+use fat32::Fat32;
+
+use crate::{
+    log,
+    mm::{try_init_swap, ContiguousFrameGuard},
+    println,
+};
+
+pub use cpio::{CpioArchive, CpioEntry};
+pub use file::{close_file, fork_files, get_file, open_file, remove_files, OpenFile, OpenFlags};
+pub use initramfs::ROOTFS;
+pub use vfs::{lookup, mount, FileSystem, Inode};
+
+/// Mount point of the FAT32 file system on the first virtio block device other than the swap area
+const FAT32_MOUNT_POINT: &str = "/mnt";
+
+/// Mount the initramfs as the root, and the FAT32 file system on the block device if there is one
+///
+/// A block device formatted by `mkswap` is taken as the swap area instead.
+pub fn init() {
+    mount("/", Arc::new(*ROOTFS));
+    let mut mounted = false;
+    for device in probe_block_devices::<ContiguousFrameGuard>() {
+        log!("[fs] found block device with {} blocks", device.num_blocks());
+        if try_init_swap(device.clone()) || mounted {
+            continue;
+        }
+        match Fat32::open(device) {
+            Some(fat32) => {
+                mount(FAT32_MOUNT_POINT, Arc::new(fat32));
+                mounted = true;
+            }
+            None => println!("[fs] block device does not contain a FAT32 file system"),
+        }
+    }
+}
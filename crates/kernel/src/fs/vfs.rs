@@ -0,0 +1,68 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use ksync::RwLock;
+use lazy_static::lazy_static;
+
+use crate::log;
+
+/// Interface of a file or directory in a mounted file system
+///
+/// All the file systems are read-only for now.
+pub trait Inode: Send + Sync {
+    fn is_dir(&self) -> bool;
+
+    /// Size of the file in bytes
+    fn size(&self) -> usize;
+
+    /// Read the file from `offset` into `buf`, return the number of bytes read
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
+
+    /// Find a child of the directory by its name
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>>;
+}
+
+/// Interface of a file system which can be mounted
+pub trait FileSystem: Send + Sync {
+    fn root(&self) -> Arc<dyn Inode>;
+}
+
+lazy_static! {
+    /// Mounted file systems, with the mount points written without leading `/`
+    static ref MOUNTS: RwLock<Vec<(String, Arc<dyn FileSystem>)>> = RwLock::new(Vec::new());
+}
+
+/// Split a path into its components, ignoring empty components and `.`
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|name| !name.is_empty() && *name != ".")
+}
+
+/// Mount a file system at `path`, replacing the one previously mounted there
+pub fn mount(path: &str, fs: Arc<dyn FileSystem>) {
+    let mount_point = components(path).collect::<Vec<_>>().join("/");
+    log!("[fs] mount file system at /{}", mount_point);
+    let mut mounts = MOUNTS.write();
+    mounts.retain(|(point, _)| *point != mount_point);
+    mounts.push((mount_point, fs));
+}
+
+/// Find the inode of an absolute path
+///
+/// The path is resolved in the file system mounted at its longest prefix.
+pub fn lookup(path: &str) -> Option<Arc<dyn Inode>> {
+    let path: Vec<&str> = components(path).collect();
+    let (depth, fs) = MOUNTS
+        .read()
+        .iter()
+        .filter_map(|(point, fs)| {
+            let point: Vec<&str> = components(point).collect();
+            path.starts_with(&point).then(|| (point.len(), fs.clone()))
+        })
+        .max_by_key(|(depth, _)| *depth)?;
+    path[depth..]
+        .iter()
+        .try_fold(fs.root(), |inode, name| inode.lookup(name))
+}
@@ -3,9 +3,9 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
-use core::panic::PanicInfo;
+use core::{alloc::Layout, panic::PanicInfo};
 
-use crate::println;
+use crate::{allocator::heap_stats, println};
 
 use crate::sbi::shutdown;
 
@@ -25,3 +25,12 @@ fn panic_handler(info: &PanicInfo) -> ! {
     }
     shutdown(true)
 }
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    let stats = heap_stats();
+    panic!(
+        "Heap allocation error, layout = {:?}, {} of {} bytes allocated, largest free block {} bytes",
+        layout, stats.allocated, stats.total, stats.largest_free
+    );
+}
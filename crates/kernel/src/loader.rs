@@ -3,121 +3,72 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
-use alloc::vec::Vec;
-use lazy_static::lazy_static;
+use core::ops::Deref;
 
+use alloc::{format, vec, vec::Vec};
+
+use crate::fs::{lookup, Inode, ROOTFS};
 use crate::println;
 
-lazy_static! {
-    static ref APP_NAMES: Vec<&'static str> = {
-        let num_app = get_num_app();
-        extern "C" {
-            fn _app_names();
-        }
-        let mut start = _app_names as usize as *const u8;
-        let mut v = Vec::new();
-        unsafe {
-            for _ in 0..num_app {
-                let mut end = start;
-                while end.read_volatile() != b'\0' {
-                    end = end.add(1);
-                }
-                let slice = core::slice::from_raw_parts(start, end as usize - start as usize);
-                let str = core::str::from_utf8(slice).unwrap();
-                v.push(str);
-                start = end.add(1);
-            }
-        }
-        v
-    };
-    
-    static ref SERVICE_NAMES: Vec<&'static str> = {
-        let num_service = get_num_service();
-        extern "C" {
-            fn _service_names();
-        }
-        let mut start = _service_names as usize as *const u8;
-        let mut v = Vec::new();
-        unsafe {
-            for _ in 0..num_service {
-                let mut end = start;
-                while end.read_volatile() != b'\0' {
-                    end = end.add(1);
-                }
-                let slice = core::slice::from_raw_parts(start, end as usize - start as usize);
-                let str = core::str::from_utf8(slice).unwrap();
-                v.push(str);
-                start = end.add(1);
-            }
-        }
-        v
-    };
-}
+/// Directory of the user applications in the root file system
+const APP_DIR: &str = "bin";
+/// Directory of the services in the root file system
+const SERVICE_DIR: &str = "sbin";
 
-pub fn get_num_app() -> usize {
-    extern "C" {
-        fn _num_app();
-    }
-    unsafe { (_num_app as usize as *const usize).read_volatile() }
+/// Content of an executable copied out of the root file system
+///
+/// `xmas_elf` requires the ELF headers to be naturally aligned, while file data in a `newc`
+/// archive is only aligned to 4 bytes, so the data is copied into a buffer of `u64`.
+pub struct AppData {
+    buf: Vec<u64>,
+    len: usize,
 }
 
-pub fn get_num_service() -> usize {
-    extern "C" {
-        fn _num_service();
+impl AppData {
+    /// Read the whole content of a file
+    fn read(inode: &dyn Inode) -> Self {
+        let len = inode.size();
+        let mut buf = vec![0u64; len.div_ceil(8)];
+        let data = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, len) };
+        let len = inode.read_at(0, data);
+        Self { buf, len }
     }
-    unsafe { (_num_service as usize as *const usize).read_volatile() }
 }
 
-pub fn get_app_data(app_id: usize) -> &'static [u8] {
-    extern "C" {
-        fn _num_app();
-    }
-    let num_app_ptr = _num_app as usize as *const usize;
-    let num_app = get_num_app();
-    let app_start = unsafe { core::slice::from_raw_parts(num_app_ptr.add(1), num_app + 1) };
-    assert!(app_id < num_app);
-    unsafe {
-        core::slice::from_raw_parts(
-            app_start[app_id] as *const u8,
-            app_start[app_id + 1] - app_start[app_id],
-        )
+impl Deref for AppData {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { core::slice::from_raw_parts(self.buf.as_ptr() as *const u8, self.len) }
     }
 }
 
-pub fn get_app_data_by_name(name: &str) -> Option<&'static [u8]> {
-    let num_app = get_num_app();
-    (0..num_app)
-        .find(|&i| APP_NAMES[i] == name)
-        .map(get_app_data)
+/// Read a regular file from the mounted file systems
+fn read_file(path: &str) -> Option<AppData> {
+    lookup(path)
+        .filter(|inode| !inode.is_dir())
+        .map(|inode| AppData::read(inode.as_ref()))
 }
 
-pub fn get_service_data(service_id: usize) -> &'static [u8] {
-    extern "C" {
-        fn _num_service();
-    }
-    let num_service_ptr = _num_service as usize as *const usize;
-    let num_service = get_num_service();
-    let service_start = unsafe { core::slice::from_raw_parts(num_service_ptr.add(1), num_service + 1) };
-    assert!(service_id < num_service);
-    unsafe {
-        core::slice::from_raw_parts(
-            service_start[service_id] as *const u8,
-            service_start[service_id + 1] - service_start[service_id],
-        )
+/// Get the executable at `path`
+///
+/// A bare name without `/` is searched in the application directory, like `PATH` in a shell.
+pub fn get_app_data_by_path(path: &str) -> Option<AppData> {
+    if path.contains('/') {
+        read_file(path)
+    } else {
+        read_file(&format!("{}/{}", APP_DIR, path))
     }
 }
 
-pub fn get_service_data_by_name(name: &str) -> Option<&'static [u8]> {
-    let num_service = get_num_service();
-    (0..num_service)
-        .find(|&i| SERVICE_NAMES[i] == name)
-        .map(get_service_data)
+pub fn get_service_data_by_name(name: &str) -> Option<AppData> {
+    read_file(&format!("{}/{}", SERVICE_DIR, name))
 }
 
 pub fn list_apps() {
     println!("/**** APPS ****");
-    for app in APP_NAMES.iter() {
-        println!("{}", app);
+    for app in ROOTFS.read_dir(APP_DIR).filter(|entry| entry.is_file()) {
+        println!("{}", app.file_name());
     }
     println!("**************/");
 }
@@ -6,14 +6,14 @@
 #![no_std]
 #![no_main]
 // #![deny(warnings)]
-#![feature(naked_functions, asm_const, panic_info_message, arbitrary_self_types)]
+#![feature(naked_functions, asm_const, panic_info_message, arbitrary_self_types, alloc_error_handler)]
 
 use core::arch::{asm, global_asm};
 
 extern crate alloc;
 
-use loader::get_app_data_by_name;
-use mm::{activate_kernel_space, init_frame_allocator, new_user_space};
+use loader::get_app_data_by_path;
+use mm::{activate_kernel_space, init_asid, init_frame_allocator, new_user_space};
 use alloc::boxed::Box;
 use drivers::init_device;
 use allocator::init_heap_allocator;
@@ -26,6 +26,7 @@ mod lang;
 mod sbi;
 mod config;
 mod console;
+mod fs;
 mod loader;
 mod trap;
 mod stack;
@@ -35,30 +36,67 @@ mod services;
 mod mm;
 mod sched;
 
-global_asm!(include_str!("entry.S"));
-global_asm!(include_str!("link_app.S"));
+global_asm!(
+    include_str!("entry.S"),
+    boot_stack_size = const config::BOOT_STACK_SIZE,
+    max_harts = const config::MAX_HARTS,
+);
 
 #[no_mangle]
-extern "C" fn rust_init() -> ! {
+extern "C" fn rust_init(hart_id: usize) -> ! {
     init_heap_allocator();
     init_frame_allocator();
     activate_kernel_space();
-    rust_main()
+    init_asid();
+    rust_main(hart_id)
+}
+
+/// Entry of the secondary harts, after the boot hart has initialized the kernel
+#[no_mangle]
+extern "C" fn rust_init_secondary(hart_id: usize) -> ! {
+    activate_kernel_space();
+    trap::init_hart();
+    log!("[kernel] Hart {} started", hart_id);
+    sched::scheduler::start_schedule()
+}
+
+/// Start all the other harts through SBI HSM, harts which do not exist are skipped
+///
+/// Only the boot hart runs if the firmware does not implement HSM.
+fn start_secondary_harts(boot_hart_id: usize) {
+    extern "C" {
+        fn _start_secondary();
+    }
+    if !sbi::has_hsm() {
+        log!("[kernel] No SBI HSM, running on hart {} only", boot_hart_id);
+        return;
+    }
+    for hart_id in (0..config::MAX_HARTS).filter(|&hart_id| hart_id != boot_hart_id) {
+        if sbi::hart_start(hart_id, _start_secondary as usize) {
+            log!("[kernel] Starting hart {}", hart_id);
+        }
+    }
 }
 
 fn add_init_process() {
     init_services();
-    let init_token = new_user_space(get_app_data_by_name("initproc").unwrap());
+    let init_token = new_user_space(&get_app_data_by_path("initproc").unwrap()).unwrap();
     init(init_token);
-    add_process(1, init_token)
+    add_process(1, 0, init_token)
 }
 
-fn rust_main() -> ! {
+fn rust_main(hart_id: usize) -> ! {
+    init_device();
     log!("[kernel] Hello, World!");
     trap::init();
+    console::init();
+    fs::init();
     loader::list_apps();
     trap::enable_timer_interrupt();
+    trap::enable_external_interrupt();
+    trap::enable_soft_interrupt();
     add_init_process();
+    start_secondary_harts(hart_id);
     sched::scheduler::start_schedule()
 }
 
@@ -0,0 +1,90 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+// Address space identifiers
+//
+// Each page table has an ASID of its own in its token, so that switching between address spaces
+// does not flush the TLB. ASID 0 belongs to the kernel, and is shared by the user spaces when the
+// harts support too few ASIDs, in which case the trampoline flushes the whole TLB on every switch.
+//
+// A freed ASID may still have entries in the TLBs, so it is only reused in the next generation,
+// which begins by flushing the TLBs of all the harts.
+
+use alloc::vec::Vec;
+use core::arch::asm;
+
+use ksync::SpinLock;
+use lazy_static::lazy_static;
+use riscv::register::satp;
+
+use crate::{log, sbi::remote_sfence_vma_all};
+
+pub const ASID_SHIFT: usize = 44;
+pub const ASID_MASK: usize = 0xffff;
+
+struct AsidAllocator {
+    /// Number of ASIDs the harts support
+    count: usize,
+    /// The ASIDs from `next` on have not been used in this generation
+    next: usize,
+    /// ASIDs freed in the earlier generations, which no TLB holds any more
+    free: Vec<usize>,
+    /// ASIDs freed in this generation
+    stale: Vec<usize>,
+    generation: usize,
+}
+
+impl AsidAllocator {
+    /// Start a new generation, where the ASIDs freed so far can be used again
+    fn rollover(&mut self) {
+        remote_sfence_vma_all();
+        self.free.append(&mut self.stale);
+        self.generation += 1;
+        log!("[kernel] ASID generation {} begins", self.generation);
+    }
+}
+
+lazy_static! {
+    static ref ASID_ALLOCATOR: SpinLock<AsidAllocator> = SpinLock::new(AsidAllocator {
+        count: 1,
+        next: 1,
+        free: Vec::new(),
+        stale: Vec::new(),
+        generation: 0,
+    });
+}
+
+/// Find out the number of ASIDs the current hart supports, with the kernel space active
+pub fn init_asid() {
+    let token = satp::read().bits();
+    unsafe {
+        satp::write(token | ASID_MASK << ASID_SHIFT);
+        let supported = satp::read().bits() >> ASID_SHIFT & ASID_MASK;
+        satp::write(token);
+        asm!("sfence.vma");
+        ASID_ALLOCATOR.lock().count = supported + 1;
+        log!("[kernel] {} ASIDs supported", supported + 1);
+    }
+}
+
+/// Allocate an ASID, or share ASID 0 if all of them are in use
+pub fn asid_alloc() -> usize {
+    let mut allocator = ASID_ALLOCATOR.lock();
+    if allocator.next < allocator.count {
+        allocator.next += 1;
+        return allocator.next - 1;
+    }
+    if allocator.free.is_empty() && !allocator.stale.is_empty() {
+        allocator.rollover();
+    }
+    allocator.free.pop().unwrap_or(0)
+}
+
+/// Free an ASID, which is reused in the next generation
+pub fn asid_dealloc(asid: usize) {
+    if asid != 0 {
+        ASID_ALLOCATOR.lock().stale.push(asid);
+    }
+}
@@ -3,7 +3,9 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
-use super::{frame_dealloc, PhysPageNum};
+use drivers::dma::DmaBuffer;
+
+use super::{frame_alloc_contiguous, frame_dealloc, frame_dealloc_contiguous, PhysAddr, PhysPageNum};
 
 pub struct FrameGuard {
     pub ppn: PhysPageNum,
@@ -25,3 +27,37 @@ impl Drop for FrameGuard {
         frame_dealloc(self.ppn);
     }
 }
+
+/// A run of contiguous frames, freed as a whole
+pub struct ContiguousFrameGuard {
+    pub ppn: PhysPageNum,
+    pub count: usize,
+    align: usize,
+}
+
+impl ContiguousFrameGuard {
+    /// Create a new guard of the frames and clear them
+    pub fn new(ppn: PhysPageNum, count: usize, align: usize) -> Self {
+        for i in 0..count {
+            PhysPageNum(ppn.0 + i).get_bytes_array().fill(0);
+        }
+        Self { ppn, count, align }
+    }
+}
+
+impl Drop for ContiguousFrameGuard {
+    fn drop(&mut self) {
+        frame_dealloc_contiguous(self.ppn, self.count, self.align);
+    }
+}
+
+/// Frames are identity mapped in the kernel space, so they can be shared with the devices
+impl DmaBuffer for ContiguousFrameGuard {
+    fn alloc(pages: usize) -> Option<Self> {
+        frame_alloc_contiguous(pages, 1)
+    }
+
+    fn paddr(&self) -> usize {
+        PhysAddr::from(self.ppn).into()
+    }
+}
@@ -4,20 +4,27 @@
 // LICENSE file in the root directory of this source tree.
 
 
-use crate::config::MEMORY_END;
+use core::alloc::Layout;
 
-use super::{FrameGuard, PhysAddr, PhysPageNum};
+use crate::config::{MEMORY_END, PAGE_SIZE};
+
+use super::{ContiguousFrameGuard, FrameGuard, PhysAddr, PhysPageNum};
 
-use ksync::UPSafeCell;
 use lazy_static::lazy_static;
+use ksync::SpinLock;
 
-use allocator::StackAllocator;
+use allocator::{BuddyAllocator, StackAllocator};
 
 /// Interface of a frame manager
 pub trait FrameManager : Default {
   fn init_frame(&mut self, start: PhysPageNum, end: PhysPageNum);
   fn alloc_frame(&mut self) -> Option<PhysPageNum>;
   fn dealloc_frame(&mut self, ppn: PhysPageNum);
+  /// Allocate `count` contiguous frames, the first of which is aligned to `align` frames, a
+  /// power of two
+  fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<PhysPageNum>;
+  /// Deallocate frames allocated by `alloc_contiguous` with the same arguments
+  fn dealloc_contiguous(&mut self, ppn: PhysPageNum, count: usize, align: usize);
 }
 
 impl FrameManager for StackAllocator {
@@ -32,12 +39,64 @@ impl FrameManager for StackAllocator {
   fn dealloc_frame(&mut self, ppn: PhysPageNum) {
       self.dealloc(ppn.into())
   }
+
+  fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<PhysPageNum> {
+      self.alloc_contiguous(count, align).map(|ppn| ppn.into())
+  }
+
+  fn dealloc_contiguous(&mut self, ppn: PhysPageNum, count: usize, _align: usize) {
+      for ppn in ppn.0..ppn.0 + count {
+          self.dealloc(ppn)
+      }
+  }
+}
+
+/// Frame manager on top of a buddy allocator over the physical memory
+///
+/// The free lists live in the free frames themselves, which are reachable through the identity
+/// map. A run of frames takes a whole block, so its length is rounded up to a power of two.
+#[derive(Default)]
+pub struct BuddyFrameManager(BuddyAllocator);
+
+impl BuddyFrameManager {
+  fn layout(count: usize, align: usize) -> Layout {
+      Layout::from_size_align(count * PAGE_SIZE, align * PAGE_SIZE).unwrap()
+  }
+}
+
+impl FrameManager for BuddyFrameManager {
+  fn init_frame(&mut self, start: PhysPageNum, end: PhysPageNum) {
+      unsafe {
+          self.0.add_segment(PhysAddr::from(start).into(), PhysAddr::from(end).into());
+      }
+  }
+
+  fn alloc_frame(&mut self) -> Option<PhysPageNum> {
+      self.alloc_contiguous(1, 1)
+  }
+
+  fn dealloc_frame(&mut self, ppn: PhysPageNum) {
+      self.dealloc_contiguous(ppn, 1, 1)
+  }
+
+  fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<PhysPageNum> {
+      let ptr = self.0.alloc(Self::layout(count, align));
+      (!ptr.is_null()).then(|| PhysAddr::from(ptr as usize).floor())
+  }
+
+  fn dealloc_contiguous(&mut self, ppn: PhysPageNum, count: usize, align: usize) {
+      unsafe {
+          self.0.dealloc(usize::from(PhysAddr::from(ppn)) as *mut u8, Self::layout(count, align));
+      }
+  }
 }
 
+/// Backend of the frame allocator, `StackAllocator` also implements `FrameManager`
+type FrameAllocatorImpl = BuddyFrameManager;
+
 // We don't use dyn FrameAllocator because lazy_static require Sized, so why should we design this trait?
 lazy_static! {
-  pub static ref FRAME_ALLOCATOR: UPSafeCell<StackAllocator> =
-      unsafe { UPSafeCell::new(StackAllocator::default()) };
+  pub static ref FRAME_ALLOCATOR: SpinLock<FrameAllocatorImpl> = SpinLock::new(FrameAllocatorImpl::default());
 }
 
 /// Initialize the frame allocator
@@ -45,7 +104,7 @@ pub fn init_frame_allocator() {
   extern "C" {
       fn ekernel();
   }
-  FRAME_ALLOCATOR.borrow_mut().init_frame(
+  FRAME_ALLOCATOR.lock().init_frame(
       PhysAddr::from(ekernel as usize).ceil(),
       PhysAddr::from(MEMORY_END).floor(),
   );
@@ -54,12 +113,37 @@ pub fn init_frame_allocator() {
 /// Allocate a frame
 pub fn frame_alloc() -> Option<FrameGuard> {
   FRAME_ALLOCATOR
-      .borrow_mut()
+      .lock()
       .alloc_frame()
       .map(FrameGuard::new)
 }
 
+/// Allocate `count` contiguous frames aligned to `align` frames
+pub fn frame_alloc_contiguous(count: usize, align: usize) -> Option<ContiguousFrameGuard> {
+  FRAME_ALLOCATOR
+      .lock()
+      .alloc_contiguous(count, align)
+      .map(|ppn| ContiguousFrameGuard::new(ppn, count, align))
+}
+
+/// Allocate at least `count` contiguous frames which are never returned, for the kernel heap
+///
+/// The run is rounded up to a power of two, which a buddy backend takes anyway, so that none of
+/// it is lost. Return its start and the number of frames.
+pub fn frame_alloc_heap(count: usize) -> Option<(PhysAddr, usize)> {
+  let count = count.next_power_of_two();
+  FRAME_ALLOCATOR
+      .lock()
+      .alloc_contiguous(count, 1)
+      .map(|ppn| (PhysAddr::from(ppn), count))
+}
+
 /// Deallocate a frame
 pub fn frame_dealloc(ppn: PhysPageNum) {
-  FRAME_ALLOCATOR.borrow_mut().dealloc_frame(ppn);
+  FRAME_ALLOCATOR.lock().dealloc_frame(ppn);
+}
+
+/// Deallocate frames allocated by `frame_alloc_contiguous`
+pub fn frame_dealloc_contiguous(ppn: PhysPageNum, count: usize, align: usize) {
+  FRAME_ALLOCATOR.lock().dealloc_contiguous(ppn, count, align);
 }
@@ -8,7 +8,10 @@ mod guard;
 mod types;
 
 pub use types::{PhysAddr, PhysPageNum};
-pub use guard::FrameGuard;
-pub use manager::{frame_alloc, frame_dealloc, init_frame_allocator};
+pub use guard::{ContiguousFrameGuard, FrameGuard};
+pub use manager::{
+    frame_alloc, frame_alloc_contiguous, frame_alloc_heap, frame_dealloc, frame_dealloc_contiguous,
+    init_frame_allocator,
+};
 
 
@@ -3,16 +3,22 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
-use alloc::vec::Vec;
+use alloc::{
+    collections::{btree_map::Entry, BTreeMap},
+    sync::Arc,
+    vec::Vec,
+};
 use core::arch::asm;
 use riscv::register::satp;
 
 use super::frame::{PhysAddr, PhysPageNum};
 use super::page::{VirtAddr, VirtPageNum};
 use super::page_table::{PTEFlags, PageTable};
+use super::shm::SharedMemory;
 use super::vm_area::{MapPermission, MapType, VMArea};
+use super::MemoryError;
 
-use crate::config::USER_STACK_SIZE;
+use crate::config::{SHM_BASE, SHM_END, USER_STACK_GROWTH, USER_STACK_MAX_SIZE, USER_STACK_SIZE};
 use crate::stack::KernelStack;
 use crate::{
     config::{MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT},
@@ -29,6 +35,10 @@ pub struct MMStruct {
     brk: usize,
     kernel_stack: Option<KernelStack>,
     heap_bottom: usize,
+    /// Lowest address the user stack may grow down to, right above its guard page
+    stack_limit: usize,
+    /// Pages the kernel is accessing, which are not evicted, with the number of their users
+    pinned: BTreeMap<VirtPageNum, usize>,
 }
 
 extern "C" {
@@ -57,17 +67,41 @@ impl MMStruct {
         self.areas.clear()
     }
 
+    /// Create an empty memory set
+    fn empty() -> Result<Self, MemoryError> {
+        Ok(Self {
+            page_table: PageTable::new()?,
+            areas: Vec::new(),
+            brk: 0,
+            kernel_stack: None,
+            heap_bottom: 0,
+            stack_limit: 0,
+            pinned: BTreeMap::new(),
+        })
+    }
+
+    /// Number of frames allocated for the data of the memory set
+    pub fn frame_count(&self) -> usize {
+        self.areas.iter().map(VMArea::frame_count).sum()
+    }
+
     /// Push a mapped area into the memory set.
-    fn push(&mut self, mut map_area: VMArea, data: Option<&[u8]>) {
-        map_area.map(&mut self.page_table);
+    fn push(&mut self, mut map_area: VMArea, data: Option<&[u8]>) -> Result<(), MemoryError> {
+        map_area.map(&mut self.page_table)?;
         if let Some(data) = data {
             map_area.copy_data(&mut self.page_table, data);
         }
         self.areas.push(map_area);
+        Ok(())
     }
 
     /// insert a new map area into the memory set.
-    pub fn insert(&mut self, start_va: VirtAddr, end_va: VirtAddr, permission: MapPermission) {
+    pub fn insert(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) -> Result<(), MemoryError> {
         self.push(
             VMArea::new(
                 // Note that this range may be conflict with the existing ones
@@ -77,7 +111,7 @@ impl MMStruct {
                 permission,
             ),
             None,
-        );
+        )
     }
 
     pub fn remove(&mut self, start_vpn: VirtPageNum) {
@@ -92,12 +126,12 @@ impl MMStruct {
         }
     }
 
-    fn map_trampoline(&mut self) {
+    fn map_trampoline(&mut self) -> Result<(), MemoryError> {
         self.page_table.map(
             VirtAddr::from(TRAMPOLINE).into(),
             PhysAddr::from(strampoline as usize).into(),
             PTEFlags::R | PTEFlags::X,
-        );
+        )
     }
 
     /// Create a kernel address space.
@@ -105,7 +139,7 @@ impl MMStruct {
         let mut mm = Self::default();
 
         // map trampline
-        mm.map_trampoline();
+        mm.map_trampoline().unwrap();
 
         // map kernel sections
         log!(
@@ -120,7 +154,8 @@ impl MMStruct {
                 MapPermission::R | MapPermission::X,
             ),
             None,
-        );
+        )
+        .unwrap();
         log!(
             "[kernel] mapping .rodata [{:#x}, {:#x})",
             srodata as usize, erodata as usize
@@ -133,7 +168,8 @@ impl MMStruct {
                 MapPermission::R,
             ),
             None,
-        );
+        )
+        .unwrap();
         log!(
             "[kernel] mapping .data [{:#x}, {:#x})",
             sdata as usize, edata as usize
@@ -146,7 +182,8 @@ impl MMStruct {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )
+        .unwrap();
         log!(
             "[kernel] mapping .bss [{:#x}, {:#x})",
             sbss_with_stack as usize, ebss as usize
@@ -159,7 +196,8 @@ impl MMStruct {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )
+        .unwrap();
 
         log!(
             "[kernel] mapping physical memory [{:#x}, {:#x})",
@@ -169,11 +207,12 @@ impl MMStruct {
             VMArea::new(
                 (ekernel as usize).into(),
                 MEMORY_END.into(),
-                MapType::Identical,
+                MapType::IdenticalHuge,
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )
+        .unwrap();
 
         for pair in MMIO {
             log!(
@@ -189,19 +228,20 @@ impl MMStruct {
                     MapPermission::R | MapPermission::W,
                 ),
                 None,
-            );
+            )
+            .unwrap();
         }
         mm
     }
 
-    pub fn new_app(app_data: &[u8]) -> (Self, usize, usize) {
-        let mut mm = Self::default();
+    pub fn new_app(app_data: &[u8]) -> Result<(Self, usize, usize), MemoryError> {
+        let mut mm = Self::empty()?;
 
         // map trampline
-        mm.map_trampoline();
+        mm.map_trampoline()?;
         
         // map kernel stack
-        mm.kernel_stack = Some(KernelStack::new_process());
+        mm.kernel_stack = Some(KernelStack::new_process()?);
         
         // map app sections
         let elf_data = xmas_elf::ElfFile::new(app_data).unwrap();
@@ -230,14 +270,14 @@ impl MMStruct {
                 }
 
                 // init mapped area
-                let map_area = VMArea::new(start_va, end_va, MapType::Framed, permission);
+                let map_area = VMArea::new(start_va, end_va, MapType::Framed, permission).swappable();
                 mm.push(
                     map_area,
                     Some(
                         &app_data[header.offset() as usize
                             ..(header.offset() + header.file_size()) as usize],
                     ),
-                );
+                )?;
                 log!(
                     "[kernel] mapping app section [{:#x}, {:#x})",
                     usize::from(start_va),
@@ -246,25 +286,29 @@ impl MMStruct {
             }
         }
 
+        // The stack may grow down to its limit, with a guard page between it and the sections
         let end_va: VirtAddr = mm.areas.last().unwrap().get_end().into();
         let end_va_usize: usize = end_va.into();
-        let user_stack_bottom: usize = end_va_usize + PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
+        let user_stack_limit: usize = end_va_usize + PAGE_SIZE;
+        let user_stack_top = user_stack_limit + USER_STACK_MAX_SIZE;
+        let user_stack_bottom = user_stack_top - USER_STACK_SIZE;
         log!(
             "[kernel] mapping user stack [{:#x}, {:#x})",
             user_stack_bottom, user_stack_top
         );
         mm.brk = user_stack_top;
         mm.heap_bottom = user_stack_top;
+        mm.stack_limit = user_stack_limit;
         mm.push(
             VMArea::new(
                 user_stack_bottom.into(),
                 user_stack_top.into(),
                 MapType::Framed,
                 MapPermission::R | MapPermission::W | MapPermission::U,
-            ),
+            )
+            .swappable(),
             None,
-        );
+        )?;
 
         // mapping user heap
         log!(
@@ -277,9 +321,10 @@ impl MMStruct {
                 user_stack_top.into(),
                 MapType::Framed,
                 MapPermission::R | MapPermission::W | MapPermission::U,
-            ),
+            )
+            .swappable(),
             None,
-        );
+        )?;
 
         // mapping the trap context
         log!(
@@ -294,16 +339,16 @@ impl MMStruct {
                 MapPermission::R | MapPermission::W,
             ),
             None,
-        );
+        )?;
 
-        (
+        Ok((
             mm,
             user_stack_top,
             elf_data.header.pt2.entry_point() as usize,
-        )
+        ))
     }
     
-    pub fn alloc_port(&mut self, va: usize) -> usize {
+    pub fn alloc_port(&mut self, va: usize) -> Result<usize, MemoryError> {
         log!(
             "[kernel] mapping port area [{:#x}, {:#x})",
             va, va + PAGE_SIZE * 2
@@ -314,14 +359,61 @@ impl MMStruct {
             MapType::Framed,
             MapPermission::R | MapPermission::W | MapPermission::U,
         );
-        self.push(port_area, None);
-        PhysAddr::from(self.translate(VirtAddr::from(va).into()).unwrap()).into()
+        self.push(port_area, None)?;
+        Ok(PhysAddr::from(self.translate(VirtAddr::from(va).into()).unwrap()).into())
+    }
+
+    /// Map the shared memory object at the lowest free address from `SHM_BASE`, and return it
+    pub fn map_shared(&mut self, shm: Arc<SharedMemory>, permission: MapPermission) -> Result<usize, MemoryError> {
+        let pages = shm.pages();
+        let mut areas: Vec<&VMArea> = self.areas.iter().collect();
+        areas.sort_by_key(|area| area.get_start());
+        let mut start = VirtAddr::from(SHM_BASE).floor();
+        for area in areas {
+            if area.get_end() <= start {
+                continue;
+            }
+            if area.get_start().0 >= start.0 + pages {
+                break;
+            }
+            start = area.get_end();
+        }
+        if start.0 + pages > VirtAddr::from(SHM_END).floor().0 {
+            return Err(MemoryError::InvalidArgument);
+        }
+        let start_va: VirtAddr = start.into();
+        self.push(VMArea::new_shared(start_va, shm, permission | MapPermission::U), None)?;
+        Ok(start_va.into())
+    }
+
+    /// Unmap the shared memory object mapped at `va`
+    pub fn unmap_shared(&mut self, va: VirtAddr) -> Result<(), MemoryError> {
+        if va.page_offset() != 0 {
+            return Err(MemoryError::InvalidArgument);
+        }
+        let idx = self
+            .areas
+            .iter()
+            .position(|area| area.is_shared() && area.get_start() == va.floor())
+            .ok_or(MemoryError::InvalidArgument)?;
+        let mut area = self.areas.remove(idx);
+        area.unmap(&mut self.page_table);
+        Ok(())
     }
 
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PhysPageNum> {
         self.page_table.translate(vpn).map(|pte| pte.ppn())
     }
 
+    /// Get the frame of the page at `vpn` if it is in a shared memory mapping, whose frames are
+    /// never evicted
+    pub fn shared_frame(&self, vpn: VirtPageNum) -> Option<PhysPageNum> {
+        self.areas
+            .iter()
+            .find(|area| area.is_shared() && area.get_start() <= vpn && vpn < area.get_end())?;
+        self.translate(vpn)
+    }
+
     pub fn activate(&self) {
         let satp = self.page_table.token();
         unsafe {
@@ -343,7 +435,7 @@ impl MMStruct {
         }
     }
 
-    fn append_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
+    fn append_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> Result<bool, MemoryError> {
         for area in &self.areas {
             log!(
                 "start = {:#x}, end = {:#x}",
@@ -358,50 +450,150 @@ impl MMStruct {
             .iter_mut()
             .find(|area| area.get_start() == start.floor())
         {
-            area.append_to(&mut self.page_table, new_end.ceil());
-            true
+            area.append_to(&mut self.page_table, new_end.ceil())?;
+            Ok(true)
         } else {
-            false
+            Ok(false)
         }
     }
 
-    pub fn change_brk(&mut self, size: i32) -> Option<usize> {
+    pub fn change_brk(&mut self, size: i32) -> Result<usize, MemoryError> {
         let old_break = self.brk;
         if old_break == 0 {
             panic!("brk is not initialized")
         }
         let new_brk = self.brk as isize + size as isize;
         if new_brk < self.heap_bottom as isize {
-            return None;
+            return Err(MemoryError::InvalidBreak);
         }
         let result = if size < 0 {
             self.shrink_to(VirtAddr(self.heap_bottom), VirtAddr(new_brk as usize))
         } else {
-            self.append_to(VirtAddr(self.heap_bottom), VirtAddr(new_brk as usize))
+            self.append_to(VirtAddr(self.heap_bottom), VirtAddr(new_brk as usize))?
         };
         if result {
             self.brk = new_brk as usize;
-            Some(old_break)
+            Ok(old_break)
         } else {
-            None
+            Err(MemoryError::InvalidBreak)
         }
     }
-}
 
-impl Clone for MMStruct {
-    fn clone(&self) -> Self {
-        let mut new_mm = Self::default();
-        new_mm.map_trampoline();
-        new_mm.kernel_stack = Some(KernelStack::new_process());
+    /// Run the clock hand over the swappable areas from the page `from`, in the order of their
+    /// addresses, until `count` frames are freed
+    ///
+    /// Return the number of frames freed, and the page to continue from if it stops early.
+    pub fn reclaim(&mut self, from: VirtPageNum, count: usize) -> (usize, Option<VirtPageNum>) {
+        let mut areas: Vec<&mut VMArea> = self
+            .areas
+            .iter_mut()
+            .filter(|area| area.get_end() > from)
+            .collect();
+        areas.sort_by_key(|area| area.get_start());
+        let mut freed = 0;
+        for area in areas {
+            let (evicted, next) =
+                area.reclaim(&mut self.page_table, &self.pinned, from, count - freed);
+            freed += evicted;
+            if next.is_some() {
+                return (freed, next);
+            }
+        }
+        (freed, None)
+    }
+
+    /// Handle a page fault at `vpn` for the access in `access`, bringing the page back if it is
+    /// swapped out or growing the stack down to it, and tell if the access may be retried
+    ///
+    /// The page may have been brought back by another thread of the space already.
+    pub fn handle_page_fault(&mut self, vpn: VirtPageNum, access: MapPermission) -> Result<bool, MemoryError> {
+        let entry = self.page_table.translate(vpn);
+        if let Some(entry) = entry.filter(|entry| entry.is_valid()) {
+            let access = PTEFlags::from_bits(access.bits()).unwrap();
+            return Ok(entry.flags().contains(access));
+        }
+        if entry.and_then(|entry| entry.swap_slot()).is_none() {
+            return self.grow_stack(vpn);
+        }
+        let area = self
+            .areas
+            .iter_mut()
+            .find(|area| area.get_start() <= vpn && vpn < area.get_end())
+            .unwrap();
+        area.swap_in(&mut self.page_table, vpn)?;
+        Ok(true)
+    }
+
+    /// Bring the page at `vpn` in if it is not resident, and keep it from being evicted until it
+    /// is unpinned as many times as it is pinned, return its frame
+    pub fn pin(&mut self, vpn: VirtPageNum) -> Result<PhysPageNum, MemoryError> {
+        let resident = self.page_table.translate(vpn).is_some_and(|entry| entry.is_valid());
+        if !resident && !self.handle_page_fault(vpn, MapPermission::empty())? {
+            return Err(MemoryError::BadAddress);
+        }
+        *self.pinned.entry(vpn).or_default() += 1;
+        Ok(self.page_table.translate(vpn).unwrap().ppn())
+    }
+
+    /// Let the page pinned at `vpn` be evicted again once all its users are gone
+    pub fn unpin(&mut self, vpn: VirtPageNum) {
+        if let Entry::Occupied(mut entry) = self.pinned.entry(vpn) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+            }
+        }
+    }
+
+    /// Grow the user stack down to the page faulted at, if it is below the stack and above the
+    /// guard page at its limit, and tell if it grows
+    ///
+    /// A fault in the guard page, or below the stack when it cannot grow, is a stack overflow.
+    fn grow_stack(&mut self, vpn: VirtPageNum) -> Result<bool, MemoryError> {
+        let top = VirtAddr::from(self.heap_bottom).floor();
+        let limit = VirtAddr::from(self.stack_limit).floor();
+        // The heap starts at the top of the stack
+        let Some(stack) = self
+            .areas
+            .iter_mut()
+            .find(|area| area.get_end() == top && area.get_start() != top)
+        else {
+            return Ok(false);
+        };
+        if vpn >= stack.get_start() || vpn.0 + 1 < limit.0 {
+            return Ok(false);
+        }
+        if !USER_STACK_GROWTH || vpn < limit {
+            return Err(MemoryError::StackOverflow);
+        }
+        stack.prepend_to(&mut self.page_table, vpn)?;
+        Ok(true)
+    }
+
+    /// Copy the memory set for a child process
+    pub fn fork(&self) -> Result<Self, MemoryError> {
+        let mut new_mm = Self::empty()?;
+        new_mm.map_trampoline()?;
+        new_mm.kernel_stack = Some(KernelStack::new_process()?);
         for area in &self.areas {
             // We cannot do deep copy here, since the page table is different
-            new_mm.push(area.clone(), None);
+            new_mm.push(area.clone(), None)?;
+            // The child maps the same frames of a shared memory object
+            if area.is_shared() {
+                continue;
+            }
             for vpn in area.vpn_range {
-                let src_ppn = self.translate(vpn).unwrap();
                 let dst_ppn = new_mm.translate(vpn).unwrap();
-                dst_ppn.get_bytes_array().copy_from_slice(src_ppn.get_bytes_array());
+                // A page swapped out is read from its slot rather than brought back
+                if !area.read_swapped(&self.page_table, vpn, dst_ppn)? {
+                    let src_ppn = self.translate(vpn).unwrap();
+                    dst_ppn.get_bytes_array().copy_from_slice(src_ppn.get_bytes_array());
+                }
             }
         }
-        new_mm
+        new_mm.brk = self.brk;
+        new_mm.heap_bottom = self.heap_bottom;
+        new_mm.stack_limit = self.stack_limit;
+        Ok(new_mm)
     }
-}
\ No newline at end of file
+}
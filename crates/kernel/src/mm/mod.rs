@@ -3,10 +3,13 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
+mod asid;
 mod frame;
 mod mm_struct;
 mod page;
 mod page_table;
+mod shm;
+mod swap;
 mod translation;
 mod vm_area;
 
@@ -17,16 +20,18 @@ use mm_struct::MMStruct;
 use page::StepByOne;
 use page_table::PageTable;
 
-use ksync::UPSafeCell;
+use ksync::SpinLock;
 
-pub use frame::init_frame_allocator;
+pub use asid::init_asid;
+pub use frame::{frame_alloc_heap, init_frame_allocator, ContiguousFrameGuard};
 pub use page::VirtAddr;
+pub use swap::try_init_swap;
 pub use translation::*;
 pub use vm_area::MapPermission;
 use vm_area::{MapType, VMArea};
 
 use crate::{
-    config::{PAGE_SIZE, SERVICE_RECV_PORT, SERVICE_SEND_PORT, TRAP_CONTEXT}, log, trap::{trap_handler, TrapContext}
+    config::{PAGE_SIZE, SERVICE_RECV_PORT, SERVICE_SEND_PORT, TRAP_CONTEXT}, log, sched::Mutex, trap::{trap_handler, TrapContext}
 };
 
 pub mod types {
@@ -35,20 +40,27 @@ pub mod types {
 
 lazy_static! {
   /// a memory set instance through lazy_static! managing kernel space
-  pub static ref KERNEL_SPACE: Arc<UPSafeCell<MMStruct>> =
-      Arc::new(unsafe { UPSafeCell::new(MMStruct::new_kernel()) });
-  pub static ref USER_SPACES: UPSafeCell<BTreeMap<usize, MMStruct>> =
-      unsafe { UPSafeCell::new(BTreeMap::new()) };
+  pub static ref KERNEL_SPACE: Arc<SpinLock<MMStruct>> =
+      Arc::new(SpinLock::new(MMStruct::new_kernel()));
+  /// user spaces indexed by their tokens, shared by all the harts
+  ///
+  /// Each space has a sleeping lock of its own, so that copying it on fork does not keep
+  /// interrupts disabled.
+  pub static ref USER_SPACES: SpinLock<BTreeMap<usize, Arc<Mutex<MMStruct>>>> =
+      SpinLock::new(BTreeMap::new());
+}
+
+/// Get the user space with the token, without holding `USER_SPACES` while using it
+fn user_space(token: usize) -> Arc<Mutex<MMStruct>> {
+    USER_SPACES.lock().get(&token).unwrap().clone()
 }
 
 pub fn activate_kernel_space() {
-    KERNEL_SPACE.borrow_mut().activate();
+    KERNEL_SPACE.lock().activate();
 }
 
 pub fn get_kernel_stack(token: usize) -> usize {
-    let user_spaces = USER_SPACES.borrow_mut();
-    let mm = user_spaces.get(&token).unwrap();
-    let sp = mm.kernel_stack_top();
+    let sp = user_space(token).lock().kernel_stack_top();
     if sp == 0 {
         panic!("kernel stack is not initialized");
     }
@@ -56,70 +68,131 @@ pub fn get_kernel_stack(token: usize) -> usize {
 }
 
 pub fn get_trap_ctx(token: usize) -> &'static mut TrapContext {
-    let user_spaces = USER_SPACES.borrow_mut();
-    let mm = user_spaces.get(&token).unwrap();
-    let trap_ctx_ppn = mm.translate(VirtAddr::from(TRAP_CONTEXT).into()).unwrap();
+    let mm = user_space(token);
+    let trap_ctx_ppn = mm.lock().translate(VirtAddr::from(TRAP_CONTEXT).into()).unwrap();
     trap_ctx_ppn.get_mut()
 }
 
-pub fn new_user_space(elf_data: &[u8]) -> usize {
-    let (mm, user_sp, entry_point) = MMStruct::new_app(elf_data);
+/// Error of the memory management
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryError {
+    /// No frame or kernel stack is left
+    OutOfMemory,
+    /// The program break would move out of the heap
+    InvalidBreak,
+    /// The shared memory object or its mapping does not fit the request
+    InvalidArgument,
+    /// The user stack would grow into its guard page
+    StackOverflow,
+    /// Reading or writing the swap area failed
+    SwapIo,
+    /// The user address is not mapped
+    BadAddress,
+}
+
+pub fn new_user_space(elf_data: &[u8]) -> Result<usize, MemoryError> {
+    let (mm, user_sp, entry_point) = MMStruct::new_app(elf_data)?;
     let trap_ctx_ppn = mm.translate(VirtAddr::from(TRAP_CONTEXT).into()).unwrap();
     let trap_ctx: &mut TrapContext = trap_ctx_ppn.get_mut();
     *trap_ctx = TrapContext::app_init_context(
         entry_point,
         user_sp,
-        KERNEL_SPACE.borrow_mut().token(),
+        KERNEL_SPACE.lock().token(),
         mm.kernel_stack_top(),
         trap_handler as usize,
     );
     let token = mm.token();
     log!("[kernel] New user space created: token = {:x}", token);
-    USER_SPACES.borrow_mut().insert(token, mm);
-    token
+    USER_SPACES.lock().insert(token, Arc::new(Mutex::new(mm)));
+    Ok(token)
 }
 
 pub fn new_service(elf_data: &[u8]) -> (usize, usize, usize) {
-    let (mut mm, user_sp, entry_point) = MMStruct::new_app(elf_data);
-    let service_send_port = mm.alloc_port(SERVICE_SEND_PORT);
-    let service_recv_port = mm.alloc_port(SERVICE_RECV_PORT);
+    let (mut mm, user_sp, entry_point) = MMStruct::new_app(elf_data).expect("[kernel] out of memory");
+    let service_send_port = mm.alloc_port(SERVICE_SEND_PORT).expect("[kernel] out of memory");
+    let service_recv_port = mm.alloc_port(SERVICE_RECV_PORT).expect("[kernel] out of memory");
     let trap_ctx_ppn = mm.translate(VirtAddr::from(TRAP_CONTEXT).into()).unwrap();
     let trap_ctx: &mut TrapContext = trap_ctx_ppn.get_mut();
     *trap_ctx = TrapContext::app_init_context(
         entry_point,
         user_sp,
-        KERNEL_SPACE.borrow_mut().token(),
+        KERNEL_SPACE.lock().token(),
         mm.kernel_stack_top(),
         trap_handler as usize,
     );
     let token = mm.token();
-    USER_SPACES.borrow_mut().insert(token, mm);
+    USER_SPACES.lock().insert(token, Arc::new(Mutex::new(mm)));
     (token, service_send_port, service_recv_port)
 }
 
-pub fn fork_user_space(token: usize) -> usize {
-    let mm = USER_SPACES.borrow_mut().get(&token).unwrap().clone();
+pub fn fork_user_space(token: usize) -> Result<usize, MemoryError> {
+    let mm = user_space(token).lock().fork()?;
     let trap_ctx_ppn = mm.translate(VirtAddr::from(TRAP_CONTEXT).into()).unwrap();
     let trap_ctx: &mut TrapContext = trap_ctx_ppn.get_mut();
     trap_ctx.kernel_sp = mm.kernel_stack_top();
     let token = mm.token();
-    USER_SPACES.borrow_mut().insert(token, mm);
-    token
+    USER_SPACES.lock().insert(token, Arc::new(Mutex::new(mm)));
+    Ok(token)
 }
 
 pub fn remove_user_space(token: usize) {
     log!("[kernel] Remove user space: token = {:x}", token);
-    USER_SPACES.borrow_mut().remove(&token).unwrap();
+    // Free the frames of the space after `USER_SPACES` is released
+    let mm = USER_SPACES.lock().remove(&token).unwrap();
+    drop(mm);
 }
 
 pub fn recycle_user_space(token: usize) {
-    let mut user_spaces = USER_SPACES.borrow_mut();
-    let mm = user_spaces.get_mut(&token).unwrap();
-    mm.recycle();
+    user_space(token).lock().recycle();
+}
+
+pub fn change_program_brk(token: usize, size: i32) -> Result<usize, MemoryError> {
+    user_space(token).lock().change_brk(size)
+}
+
+/// Handle a page fault of the user space at `va` for the access in `access`, and tell if the
+/// access may be retried
+pub fn handle_page_fault(token: usize, va: usize, access: MapPermission) -> Result<bool, MemoryError> {
+    user_space(token).lock().handle_page_fault(VirtAddr::from(va).floor(), access)
+}
+
+/// Get the physical address of `va` in the user space if it is in a shared memory mapping,
+/// which is the same in every process mapping the object
+pub fn shared_paddr(token: usize, va: usize) -> Option<usize> {
+    let va = VirtAddr::from(va);
+    let pa: types::PhysAddr = user_space(token).lock().shared_frame(va.floor())?.into();
+    Some(usize::from(pa) + va.page_offset())
+}
+
+/// Map the shared memory object named `name` into the user space with the permission, creating
+/// it with `len` bytes if there is none, and return the address of the mapping
+pub fn map_shared_memory(
+    token: usize,
+    name: &str,
+    len: usize,
+    permission: MapPermission,
+) -> Result<usize, MemoryError> {
+    let shm = shm::shm_open(name, len.div_ceil(PAGE_SIZE))?;
+    user_space(token).lock().map_shared(shm, permission)
+}
+
+/// Unmap the shared memory object mapped at `va` from the user space
+pub fn unmap_shared_memory(token: usize, va: usize) -> Result<(), MemoryError> {
+    user_space(token).lock().unmap_shared(VirtAddr::from(va))
 }
 
-pub fn change_program_brk(token: usize, size: i32) -> Option<usize> {
-    let mut user_spaces = USER_SPACES.borrow_mut();
-    let mm = user_spaces.get_mut(&token).unwrap();
-    mm.change_brk(size)
+/// Get the tokens of the user spaces from the one with the most frames, to find the victim when
+/// out of memory
+pub fn user_spaces_by_size() -> Vec<usize> {
+    let spaces: Vec<_> = USER_SPACES
+        .lock()
+        .iter()
+        .map(|(&token, mm)| (token, mm.clone()))
+        .collect();
+    let mut sizes: Vec<_> = spaces
+        .into_iter()
+        .map(|(token, mm)| (token, mm.lock().frame_count()))
+        .collect();
+    sizes.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+    sizes.into_iter().map(|(token, _)| token).collect()
 }
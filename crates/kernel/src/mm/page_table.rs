@@ -5,12 +5,17 @@
 
 use alloc::vec;
 use alloc::vec::Vec;
+use core::arch::asm;
 use bitflags::*;
+use crate::config::PAGE_SIZE;
 use crate::log;
+use crate::sbi::remote_sfence_vma_asid;
 
+use super::asid::{asid_alloc, asid_dealloc, ASID_MASK, ASID_SHIFT};
 use super::page::{VirtAddr, VirtPageNum};
 use super::types::*;
 use super::frame::{frame_alloc, FrameGuard};
+use super::MemoryError;
 
 bitflags! {
     /// Page table entry flags
@@ -26,6 +31,9 @@ bitflags! {
     }
 }
 
+/// Software bit of an invalid entry whose page is in the swap area, with the slot in the PPN field
+const PTE_SWAPPED: usize = 1 << 8;
+
 /// Page entry of the SV39 page table
 ///
 /// Contains 64 bits, in following format:
@@ -48,6 +56,18 @@ impl PageTableEntry {
         PageTableEntry { bits: 0 }
     }
 
+    /// Create an invalid entry of a page swapped out to `slot`
+    pub fn swapped(slot: usize) -> Self {
+        PageTableEntry {
+            bits: slot << 10 | PTE_SWAPPED,
+        }
+    }
+
+    /// Get the swap slot of a page swapped out
+    pub fn swap_slot(&self) -> Option<usize> {
+        (!self.is_valid() && self.bits & PTE_SWAPPED != 0).then(|| self.ppn().0)
+    }
+
     /// Get the PPN entry
     pub fn ppn(&self) -> PhysPageNum {
         (self.bits >> 10 & ((1usize << 44) - 1)).into()
@@ -77,17 +97,68 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+
+    /// Check if the page has been accessed since the bit was cleared
+    pub fn accessed(&self) -> bool {
+        (self.flags() & PTEFlags::A) != PTEFlags::empty()
+    }
+
+    /// Check if the page has been written since it was mapped
+    pub fn dirty(&self) -> bool {
+        (self.flags() & PTEFlags::D) != PTEFlags::empty()
+    }
+
+    /// Check if the entry maps a page rather than pointing to the next level
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid() && (self.readable() || self.writable() || self.executable())
+    }
+}
+
+/// Size of the page mapped by a leaf entry
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum PageSize {
+    /// 1 GiB, mapped by a leaf in the root table
+    Giga,
+    /// 2 MiB, mapped by a leaf in the second level
+    Mega,
+    /// 4 KiB
+    Normal,
+}
+
+impl PageSize {
+    /// Level of the leaf entry, counted from the root
+    const fn level(self) -> usize {
+        match self {
+            PageSize::Giga => 0,
+            PageSize::Mega => 1,
+            PageSize::Normal => 2,
+        }
+    }
+
+    const fn from_level(level: usize) -> Self {
+        match level {
+            0 => PageSize::Giga,
+            1 => PageSize::Mega,
+            _ => PageSize::Normal,
+        }
+    }
+
+    /// Number of 4 KiB pages in the page
+    pub const fn pages(self) -> usize {
+        1 << (9 * (2 - self.level()))
+    }
 }
 
 /// Structure of the SV39 page table
 pub struct PageTable {
     root_ppn: PhysPageNum,
+    asid: usize,
     frames: Vec<FrameGuard>,
 }
 
 impl Default for PageTable {
     fn default() -> Self {
-        PageTable::new()
+        PageTable::new().expect("[memory] failed to allocate page table")
     }
 }
 
@@ -95,43 +166,62 @@ impl PageTable {
     /// Create a new page table
     /// 
     /// Used when create a new memory space
-    fn new() -> Self {
-        let frame = frame_alloc().expect("[memory] failed to allocate frame");
-        PageTable {
+    pub fn new() -> Result<Self, MemoryError> {
+        let frame = frame_alloc().ok_or(MemoryError::OutOfMemory)?;
+        Ok(PageTable {
             root_ppn: frame.ppn,
+            asid: asid_alloc(),
             frames: vec![frame],
-        }
+        })
+    }
+
+    /// Flush the entries of the page at a virtual page number from the TLBs of all the harts
+    ///
+    /// The other harts may hold them from the times they ran this space.
+    fn flush(&self, vpn: VirtPageNum, size: PageSize) {
+        self.flush_local(vpn);
+        let va: usize = VirtAddr::from(vpn).into();
+        remote_sfence_vma_asid(va, size.pages() * PAGE_SIZE, self.asid);
     }
 
-    /// Find or create a page table entry by virtual page number
-    fn find_create_entry(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    /// Flush the entries of the page at a virtual page number from the TLB of the current hart
+    fn flush_local(&self, vpn: VirtPageNum) {
+        let va: usize = VirtAddr::from(vpn).into();
+        unsafe { asm!("sfence.vma {}, {}", in(reg) va, in(reg) self.asid) };
+    }
+
+    /// Find or create the entry at the level of `size` by virtual page number
+    fn find_create_entry(&mut self, vpn: VirtPageNum, size: PageSize) -> Result<&mut PageTableEntry, MemoryError> {
         let idx = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result = None;
         for (i, idx) in idx.iter().enumerate() {
             let entry = &mut ppn.get_pte_array()[*idx];
-            if i == 2 { // Leaf node
-                result = Some(entry);
-                break;
+            if i == size.level() { // Leaf node
+                return Ok(entry);
+            }
+            if entry.is_leaf() {
+                panic!("[memory] virtual address {:#x} is inside a huge page", vpn.0);
             }
             if !entry.is_valid() { // Not found
-                let new_frame = frame_alloc().expect("[memory] failed to allocate frame");
+                let new_frame = frame_alloc().ok_or(MemoryError::OutOfMemory)?;
                 *entry = PageTableEntry::new(new_frame.ppn, PTEFlags::V);
                 self.frames.push(new_frame);
             }
             ppn = entry.ppn();
         }
-        result
+        unreachable!()
     }
 
-    /// Find a page table entry by virtual page number
-    fn find_entry(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+    /// Find the leaf entry mapping a virtual page number, together with the size of its page
+    ///
+    /// The entry found in the last level may be invalid.
+    fn find_entry(&self, vpn: VirtPageNum) -> Option<(&mut PageTableEntry, PageSize)> {
         let idx = vpn.indexes();
         let mut ppn = self.root_ppn;
         for (i, idx) in idx.iter().enumerate() {
             let entry = &mut ppn.get_pte_array()[*idx];
-            if i == 2 { // Leaf node
-                return Some(entry);
+            if i == 2 || entry.is_leaf() { // Leaf node
+                return Some((entry, PageSize::from_level(i)));
             }
             if !entry.is_valid() { // Not found
                 return None;
@@ -142,31 +232,102 @@ impl PageTable {
     }
 
     /// Map a virtual page number to a physical page number
-    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let entry = self.find_create_entry(vpn).expect("[memory] failed to create page table entry");
+    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> Result<(), MemoryError> {
+        self.map_page(vpn, ppn, flags, PageSize::Normal)
+    }
+
+    /// Map a page of `size` at a virtual page number to the one at a physical page number, both
+    /// of which must be aligned to the size
+    ///
+    /// Fail only if there is no frame left for the page table itself.
+    pub fn map_page(
+        &mut self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        flags: PTEFlags,
+        size: PageSize,
+    ) -> Result<(), MemoryError> {
+        assert!(
+            vpn.0 % size.pages() == 0 && ppn.0 % size.pages() == 0,
+            "[memory] page {:#x} -> {:#x} is not aligned to {:?}", vpn.0, ppn.0, size
+        );
+        let entry = self.find_create_entry(vpn, size)?;
         if entry.is_valid() {
             panic!("[memory] virtual address {:#x} is already mapped", vpn.0);
         }
         *entry = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        // The current hart may have cached the entry while it was invalid
+        let va: usize = VirtAddr::from(vpn).into();
+        unsafe { asm!("sfence.vma {}, {}", in(reg) va, in(reg) self.asid) };
         // log!("[memory] page table {:#x} mapping {:#x} to {:#x}", self.token(), vpn.0, ppn.0);
+        Ok(())
     }
 
-    /// Unmap a virtual page number
+    /// Unmap the page starting at a virtual page number, whatever its size is
+    ///
+    /// A page swapped out is unmapped as well, and its slot is left to the caller.
     pub fn unmap(&mut self, vpn: VirtPageNum) {
-        let entry = self.find_entry(vpn).expect("[memory] failed to find page table entry");
+        let (entry, size) = self.find_entry(vpn).expect("[memory] failed to find page table entry");
+        if entry.swap_slot().is_some() {
+            *entry = PageTableEntry::empty();
+            return;
+        }
         if !entry.is_valid() {
             panic!("[memory] virtual address {:#x} is not mapped", vpn.0);
         }
+        assert!(vpn.0 % size.pages() == 0, "[memory] unmapping {:#x} inside a {:?} page", vpn.0, size);
         *entry = PageTableEntry::empty();
+        self.flush(vpn, size);
+    }
+
+    /// Change the flags of the page at a virtual page number
+    pub fn protect(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        let (entry, size) = self.find_entry(vpn).expect("[memory] failed to find page table entry");
+        if !entry.is_valid() {
+            panic!("[memory] virtual address {:#x} is not mapped", vpn.0);
+        }
+        *entry = PageTableEntry::new(entry.ppn(), flags | PTEFlags::V);
+        self.flush(vpn, size);
+    }
+
+    /// Clear the accessed bit of a 4 KiB page, giving it another round before it is evicted
+    ///
+    /// Only the TLB of the current hart is flushed. Another hart may keep accessing the page
+    /// through its cached entry without setting the bit again, which only delays the eviction.
+    pub fn clear_accessed(&mut self, vpn: VirtPageNum) {
+        let (entry, size) = self.find_entry(vpn).expect("[memory] failed to find page table entry");
+        assert!(entry.is_valid() && size == PageSize::Normal);
+        entry.bits &= !(PTEFlags::A.bits as usize);
+        self.flush_local(vpn);
+    }
+
+    /// Replace the mapping of a 4 KiB page with the swap slot holding its data, and return the
+    /// entry replaced
+    pub fn swap_out(&mut self, vpn: VirtPageNum, slot: usize) -> PageTableEntry {
+        let (entry, size) = self.find_entry(vpn).expect("[memory] failed to find page table entry");
+        assert!(entry.is_valid() && size == PageSize::Normal);
+        let old = *entry;
+        *entry = PageTableEntry::swapped(slot);
+        self.flush(vpn, size);
+        old
     }
 
     /// Find the physical page number of a virtual page number
+    ///
+    /// Inside a huge page, the entry returned points to the 4 KiB page of the virtual page number.
+    /// The entry of a page swapped out is returned as it is.
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_entry(vpn).map(|entry| *entry)
+        self.find_entry(vpn).map(|(entry, size)| {
+            if size == PageSize::Normal {
+                return *entry;
+            }
+            let offset = vpn.0 & (size.pages() - 1);
+            PageTableEntry::new(PhysPageNum(entry.ppn().0 + offset), entry.flags())
+        })
     }
     
     pub fn translate_va(&self, va: VirtAddr) -> Option<PhysAddr> {
-        self.find_entry(va.floor()).map(|entry| {
+        self.translate(va.floor()).map(|entry| {
             let pa: PhysAddr = entry.ppn().into();
             let offset = va.page_offset();
             let pa_usize: usize = pa.into();
@@ -175,7 +336,16 @@ impl PageTable {
     }
     
     pub fn token(&self) -> usize {
-        8usize << 60 | self.root_ppn.0
+        8usize << 60 | self.asid << ASID_SHIFT | self.root_ppn.0
+    }
+}
+
+impl Drop for PageTable {
+    fn drop(&mut self) {
+        // Page tables made from tokens do not own their ASIDs
+        if !self.frames.is_empty() {
+            asid_dealloc(self.asid);
+        }
     }
 }
 
@@ -186,6 +356,7 @@ impl From<usize> for PageTable {
     fn from(satp: usize) -> Self {
         PageTable {
             root_ppn: PhysPageNum(satp & ((1usize << 44) - 1)),
+            asid: satp >> ASID_SHIFT & ASID_MASK,
             frames: Vec::new(),
         }
     }
@@ -0,0 +1,89 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use ksync::SpinLock;
+use lazy_static::lazy_static;
+
+use crate::config::{PAGE_SIZE, SHM_BASE, SHM_END};
+
+use super::frame::{frame_alloc, FrameGuard, PhysPageNum};
+use super::MemoryError;
+
+/// Shared memory object, whose frames are freed once the last mapping of it goes away
+///
+/// Each mapping holds a reference to the object, while the table of names only holds a weak one.
+pub struct SharedMemory {
+    frames: Vec<FrameGuard>,
+}
+
+impl SharedMemory {
+    fn new(pages: usize) -> Result<Self, MemoryError> {
+        let frames = (0..pages)
+            .map(|_| frame_alloc().ok_or(MemoryError::OutOfMemory))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { frames })
+    }
+
+    /// Number of pages of the object
+    pub fn pages(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Get the frame of the `page`-th page
+    pub fn ppn(&self, page: usize) -> PhysPageNum {
+        self.frames[page].ppn
+    }
+}
+
+lazy_static! {
+    /// Shared memory objects by name
+    static ref SHARED_MEMORY: SpinLock<BTreeMap<String, Weak<SharedMemory>>> =
+        SpinLock::new(BTreeMap::new());
+}
+
+/// Get the shared memory object named `name`, or create one of `pages` pages if there is none
+///
+/// An object found must have at least `pages` pages, and one created must fit in the area of
+/// the shared memory mappings. The frames are allocated without holding the table of names.
+pub fn shm_open(name: &str, pages: usize) -> Result<Arc<SharedMemory>, MemoryError> {
+    if let Some(shm) = shm_find(name, pages)? {
+        return Ok(shm);
+    }
+    if pages == 0 || pages > (SHM_END - SHM_BASE) / PAGE_SIZE {
+        return Err(MemoryError::InvalidArgument);
+    }
+    let shm = Arc::new(SharedMemory::new(pages)?);
+    let mut objects = SHARED_MEMORY.lock();
+    // Another process may have created the object meanwhile, which is used instead
+    if let Some(found) = objects.get(name).and_then(Weak::upgrade) {
+        drop(objects);
+        return if found.pages() < pages {
+            Err(MemoryError::InvalidArgument)
+        } else {
+            Ok(found)
+        };
+    }
+    // Forget the objects whose mappings are all gone
+    objects.retain(|_, shm| shm.strong_count() > 0);
+    objects.insert(String::from(name), Arc::downgrade(&shm));
+    Ok(shm)
+}
+
+/// Get the shared memory object named `name`, which must have at least `pages` pages
+fn shm_find(name: &str, pages: usize) -> Result<Option<Arc<SharedMemory>>, MemoryError> {
+    let Some(shm) = SHARED_MEMORY.lock().get(name).and_then(Weak::upgrade) else {
+        return Ok(None);
+    };
+    if shm.pages() < pages {
+        return Err(MemoryError::InvalidArgument);
+    }
+    Ok(Some(shm))
+}
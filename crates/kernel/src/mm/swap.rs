@@ -0,0 +1,145 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use alloc::{sync::Arc, vec::Vec};
+use allocator::StackAllocator;
+use drivers::block::{BlockDevice, BlockError, BLOCK_SIZE};
+use ksync::SpinLock;
+use lazy_static::lazy_static;
+
+use crate::config::{PAGE_SIZE, SWAP_RECLAIM_BATCH};
+use crate::println;
+
+use super::frame::{frame_alloc, FrameGuard, PhysPageNum};
+use super::page::VirtPageNum;
+use super::USER_SPACES;
+
+/// Signature written by `mkswap` at the end of the first page of a swap area
+const SWAP_SIGNATURE: &[u8] = b"SWAPSPACE2";
+const BLOCKS_PER_PAGE: usize = PAGE_SIZE / BLOCK_SIZE;
+
+/// Swap area on a block device, in slots of one page
+///
+/// The first page holds the header written by `mkswap`, so slot 0 is never used.
+struct SwapArea {
+    device: Arc<dyn BlockDevice>,
+    slots: StackAllocator,
+}
+
+lazy_static! {
+    static ref SWAP_AREA: SpinLock<Option<SwapArea>> = SpinLock::new(None);
+    /// Position of the clock hand, as the token of a user space and the page to look at next
+    static ref CLOCK_HAND: SpinLock<(usize, VirtPageNum)> = SpinLock::new((0, VirtPageNum(0)));
+}
+
+/// Use the block device as the swap area if it is formatted by `mkswap`
+pub fn try_init_swap(device: Arc<dyn BlockDevice>) -> bool {
+    let mut block = [0u8; BLOCK_SIZE];
+    if device.read_block(BLOCKS_PER_PAGE - 1, &mut block).is_err()
+        || &block[BLOCK_SIZE - SWAP_SIGNATURE.len()..] != SWAP_SIGNATURE
+    {
+        return false;
+    }
+    let pages = device.num_blocks() / BLOCKS_PER_PAGE;
+    println!("[mm] swap area of {} pages enabled", pages.saturating_sub(1));
+    *SWAP_AREA.lock() = Some(SwapArea {
+        device,
+        slots: StackAllocator::new(1, pages.max(1)),
+    });
+    true
+}
+
+/// Get the device of the swap area, without holding the lock during the I/O
+fn swap_device() -> Arc<dyn BlockDevice> {
+    SWAP_AREA.lock().as_ref().unwrap().device.clone()
+}
+
+/// A slot of the swap area, freed when dropped
+pub struct SwapSlot(pub usize);
+
+impl SwapSlot {
+    /// Allocate a slot, if there is a swap area with room left
+    pub fn alloc() -> Option<Self> {
+        SWAP_AREA.lock().as_mut()?.slots.alloc().map(SwapSlot)
+    }
+
+    /// Write the frame into the slot
+    pub fn write(&self, ppn: PhysPageNum) -> Result<(), BlockError> {
+        let device = swap_device();
+        for (i, block) in ppn.get_bytes_array().chunks(BLOCK_SIZE).enumerate() {
+            device.write_block(self.0 * BLOCKS_PER_PAGE + i, block)?;
+        }
+        Ok(())
+    }
+
+    /// Read the slot into the frame
+    pub fn read(&self, ppn: PhysPageNum) -> Result<(), BlockError> {
+        let device = swap_device();
+        for (i, block) in ppn.get_bytes_array().chunks_mut(BLOCK_SIZE).enumerate() {
+            device.read_block(self.0 * BLOCKS_PER_PAGE + i, block)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        SWAP_AREA.lock().as_mut().unwrap().slots.dealloc(self.0);
+    }
+}
+
+/// Evict pages of the user spaces with the clock policy until `count` frames are freed
+///
+/// The hand sweeps the spaces in the order of their tokens, clearing the accessed bits of the
+/// pages it passes and evicting the pages not accessed since its last visit. It goes round twice
+/// at most, so that every page gets its second chance. Spaces locked by others are skipped,
+/// including the one of the caller. Return the number of frames freed.
+pub fn reclaim(count: usize) -> usize {
+    if SWAP_AREA.lock().is_none() {
+        return 0;
+    }
+    let spaces: Vec<_> = USER_SPACES
+        .lock()
+        .iter()
+        .map(|(&token, mm)| (token, mm.clone()))
+        .collect();
+    if spaces.is_empty() {
+        return 0;
+    }
+    // The hand is copied out, so that the lock is not held during the I/O
+    let mut hand = *CLOCK_HAND.lock();
+    let first = spaces.iter().position(|&(token, _)| token >= hand.0).unwrap_or(0);
+    let mut freed = 0;
+    for i in 0..=2 * spaces.len() {
+        let (token, mm) = &spaces[(first + i) % spaces.len()];
+        let from = if *token == hand.0 { hand.1 } else { VirtPageNum(0) };
+        let Some(mut mm) = mm.try_lock() else {
+            continue;
+        };
+        let (evicted, next) = mm.reclaim(from, count - freed);
+        freed += evicted;
+        if let Some(next) = next {
+            hand = (*token, next);
+            break;
+        }
+        // The space is done, so the hand moves on to the start of the next one
+        hand = (spaces[(first + i + 1) % spaces.len()].0, VirtPageNum(0));
+        if freed == count {
+            break;
+        }
+    }
+    *CLOCK_HAND.lock() = hand;
+    freed
+}
+
+/// Allocate a frame for a swappable page, evicting pages of the user spaces if none is left
+pub fn frame_alloc_or_reclaim() -> Option<FrameGuard> {
+    frame_alloc().or_else(|| {
+        if reclaim(SWAP_RECLAIM_BATCH) == 0 {
+            return None;
+        }
+        frame_alloc()
+    })
+}
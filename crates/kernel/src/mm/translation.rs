@@ -4,54 +4,129 @@
 // LICENSE file in the root directory of this source tree.
 
 use alloc::{string::String, vec::Vec};
+use core::mem::{size_of, MaybeUninit};
+use core::ops::{Deref, DerefMut};
 
-use super::{page::StepByOne, page_table::PageTable, VirtAddr};
+use super::{
+    page::{StepByOne, VirtPageNum},
+    types::PhysPageNum,
+    user_space, MemoryError, VirtAddr,
+};
+
+/// Find the frame of a user page and pin it, bringing it back if it is swapped out or growing
+/// the stack down to it
+///
+/// The frame stays valid until the page is unpinned with `unpin_page`.
+fn pin_page(token: usize, vpn: VirtPageNum) -> Result<PhysPageNum, MemoryError> {
+  user_space(token).lock().pin(vpn)
+}
+
+fn unpin_page(token: usize, vpn: VirtPageNum) {
+  user_space(token).lock().unpin(vpn)
+}
+
+/// Pieces of a user buffer in the frames of its pages, which are pinned while it lives
+///
+/// The pages are not swapped out under the kernel, even if it blocks while holding the buffer.
+pub struct UserBuffer {
+  token: usize,
+  pages: Vec<VirtPageNum>,
+  buffers: Vec<&'static mut [u8]>,
+}
+
+impl Deref for UserBuffer {
+  type Target = Vec<&'static mut [u8]>;
+
+  fn deref(&self) -> &Self::Target {
+      &self.buffers
+  }
+}
+
+impl DerefMut for UserBuffer {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+      &mut self.buffers
+  }
+}
+
+impl Drop for UserBuffer {
+  fn drop(&mut self) {
+      let mm = user_space(self.token);
+      let mut mm = mm.lock();
+      for &vpn in &self.pages {
+          mm.unpin(vpn);
+      }
+  }
+}
 
 /// translate a pointer to a mutable u8 Vec through page table
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-  let page_table = PageTable::from(token);
+pub fn translated_byte_buffer(
+  token: usize,
+  ptr: *const u8,
+  len: usize,
+) -> Result<UserBuffer, MemoryError> {
+  let mut buffer = UserBuffer {
+      token,
+      pages: Vec::new(),
+      buffers: Vec::new(),
+  };
   let mut start = ptr as usize;
   let end = start + len;
-  let mut v = Vec::new();
   while start < end {
       let start_va = VirtAddr::from(start);
       let mut vpn = start_va.floor();
-      let ppn = page_table.translate(vpn).unwrap().ppn();
+      // The pages pinned so far are unpinned by the buffer if this fails
+      let ppn = pin_page(token, vpn)?;
+      buffer.pages.push(vpn);
       vpn.step();
       let mut end_va: VirtAddr = vpn.into();
       end_va = end_va.min(VirtAddr::from(end));
       if end_va.page_offset() == 0 {
-          v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
+          buffer.buffers.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
       } else {
-          v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
+          buffer.buffers.push(&mut ppn.get_bytes_array()[start_va.page_offset()..end_va.page_offset()]);
       }
       start = end_va.into();
   }
-  v
+  Ok(buffer)
+}
+
+/// Copy `value` to the user buffer at `ptr`, which may cross pages
+pub fn copy_to_user<T>(token: usize, ptr: *mut T, value: &T) -> Result<(), MemoryError> {
+  let bytes = unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+  let mut copied = 0;
+  for buffer in translated_byte_buffer(token, ptr as *const u8, bytes.len())?.iter_mut() {
+      buffer.copy_from_slice(&bytes[copied..copied + buffer.len()]);
+      copied += buffer.len();
+  }
+  Ok(())
 }
 
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
-  let page_table = PageTable::from(token);
+/// Copy a value from the user buffer at `ptr`, which may cross pages
+pub fn copy_from_user<T: Copy>(token: usize, ptr: *const T) -> Result<T, MemoryError> {
+  let mut value = MaybeUninit::<T>::uninit();
+  let bytes = unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+  let mut copied = 0;
+  for buffer in translated_byte_buffer(token, ptr as *const u8, bytes.len())?.iter() {
+      bytes[copied..copied + buffer.len()].copy_from_slice(buffer);
+      copied += buffer.len();
+  }
+  Ok(unsafe { value.assume_init() })
+}
+
+pub fn translated_str(token: usize, ptr: *const u8) -> Result<String, MemoryError> {
   let mut string = String::new();
   let mut va = ptr as usize;
   loop {
-      let ch: u8 = *(page_table
-          .translate_va(VirtAddr::from(va))
-          .unwrap()
-          .get_mut());
-      if ch == 0 {
-          break;
-      } else {
-          string.push(ch as char);
-          va += 1;
+      // Read the string a page at a time, with the page pinned
+      let addr = VirtAddr::from(va);
+      let vpn = addr.floor();
+      let bytes = &pin_page(token, vpn)?.get_bytes_array()[addr.page_offset()..];
+      let len = bytes.iter().position(|&ch| ch == 0);
+      string.extend(bytes[..len.unwrap_or(bytes.len())].iter().map(|&ch| ch as char));
+      unpin_page(token, vpn);
+      match len {
+          Some(_) => return Ok(string),
+          None => va += bytes.len(),
       }
   }
-  string
-}
-
-pub fn translated_ptr<T>(token: usize, ptr: *mut T) -> *mut T {
-  let page_table = PageTable::from(token);
-  let va = ptr as usize;
-  let addr: usize = page_table.translate_va(VirtAddr::from(va)).unwrap().into();
-  addr as *mut T
 }
@@ -3,19 +3,26 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
-use alloc::collections::BTreeMap;
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
 use bitflags::bitflags;
 
 use crate::config::PAGE_SIZE;
 
 use super::frame::{FrameGuard, PhysPageNum, frame_alloc};
 use super::page::{StepByOne, VPNRange, VirtAddr, VirtPageNum};
-use super::page_table::{PTEFlags, PageTable};
+use super::page_table::{PTEFlags, PageSize, PageTable};
+use super::shm::SharedMemory;
+use super::swap::{frame_alloc_or_reclaim, SwapSlot};
+use super::MemoryError;
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum MapType {
     Identical,
+    /// Identical mapping with the largest pages the alignment allows
+    IdenticalHuge,
     Framed,
+    /// Frames of a shared memory object, which the area keeps alive
+    Shared,
 }
 
 bitflags! {
@@ -35,6 +42,12 @@ pub struct VMArea {
     data_frames: BTreeMap<VirtPageNum, FrameGuard>, // The data frames of the area
     map_type: MapType,
     map_perm: MapPermission,
+    /// Whether the pages may be evicted to the swap area
+    swappable: bool,
+    /// Slots holding the pages swapped out, and the copies of the resident pages read back
+    swap_slots: BTreeMap<VirtPageNum, SwapSlot>,
+    /// The object mapped by a shared area
+    shared: Option<Arc<SharedMemory>>,
 }
 
 impl Clone for VMArea {
@@ -44,6 +57,9 @@ impl Clone for VMArea {
             data_frames: BTreeMap::new(),
             map_type: self.map_type,
             map_perm: self.map_perm,
+            swappable: self.swappable,
+            swap_slots: BTreeMap::new(),
+            shared: self.shared.clone(),
         }
     }
 }
@@ -63,49 +79,212 @@ impl VMArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            swappable: false,
+            swap_slots: BTreeMap::new(),
+            shared: None,
         }
     }
-    
+
+    /// Create an area mapping the whole shared memory object from `start_va`
+    pub fn new_shared(start_va: VirtAddr, shm: Arc<SharedMemory>, map_perm: MapPermission) -> Self {
+        let start_vpn: VirtPageNum = start_va.floor();
+        Self {
+            vpn_range: VPNRange::new(start_vpn, VirtPageNum(start_vpn.0 + shm.pages())),
+            data_frames: BTreeMap::new(),
+            map_type: MapType::Shared,
+            map_perm,
+            swappable: false,
+            swap_slots: BTreeMap::new(),
+            shared: Some(shm),
+        }
+    }
+
+    /// Let the pages of a framed area be evicted to the swap area
+    pub fn swappable(mut self) -> Self {
+        assert_eq!(self.map_type, MapType::Framed);
+        self.swappable = true;
+        self
+    }
+
     /// Map a single page.
-    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
-        let ppn: PhysPageNum;
+    pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Result<(), MemoryError> {
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
         match self.map_type {
-            MapType::Identical => {
+            MapType::Identical | MapType::IdenticalHuge => {
                 // Identical mapping does not need to allocate a new frame
-                ppn = PhysPageNum(vpn.0);
+                page_table.map(vpn, PhysPageNum(vpn.0), pte_flags)
             }
             MapType::Framed => {
-                let frame = frame_alloc().unwrap();
-                ppn = frame.ppn;
+                let frame = if self.swappable {
+                    frame_alloc_or_reclaim()
+                } else {
+                    frame_alloc()
+                }
+                .ok_or(MemoryError::OutOfMemory)?;
+                page_table.map(vpn, frame.ppn, pte_flags)?;
                 self.data_frames.insert(vpn, frame);
+                Ok(())
+            }
+            MapType::Shared => {
+                let shm = self.shared.as_ref().unwrap();
+                page_table.map(vpn, shm.ppn(vpn.0 - self.get_start().0), pte_flags)
             }
         }
-        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
-        page_table.map(vpn, ppn, pte_flags);
     }
 
     /// Unmap a single page.
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         if let MapType::Framed = self.map_type {
             self.data_frames.remove(&vpn);
+            self.swap_slots.remove(&vpn);
         }
         page_table.unmap(vpn);
     }
 
-    /// Map all the pages in the range.
-    pub fn map(&mut self, page_table: &mut PageTable) {
-        for vpn in self.vpn_range {
-            self.map_one(page_table, vpn);
+    /// Run the clock hand over the resident pages from `from`, until `count` frames are freed
+    ///
+    /// A page accessed since the last round has its accessed bit cleared and is kept, otherwise
+    /// it is evicted. Pages in `pinned` are passed over. Return the number of frames freed, and
+    /// the page to continue from if it stops inside the area.
+    pub fn reclaim(
+        &mut self,
+        page_table: &mut PageTable,
+        pinned: &BTreeMap<VirtPageNum, usize>,
+        from: VirtPageNum,
+        count: usize,
+    ) -> (usize, Option<VirtPageNum>) {
+        if !self.swappable {
+            return (0, None);
+        }
+        let resident: Vec<VirtPageNum> = self.data_frames.range(from..).map(|(&vpn, _)| vpn).collect();
+        let mut freed = 0;
+        for vpn in resident {
+            if freed == count {
+                return (freed, Some(vpn));
+            }
+            if pinned.contains_key(&vpn) {
+                continue;
+            }
+            if page_table.translate(vpn).unwrap().accessed() {
+                page_table.clear_accessed(vpn);
+            } else if self.swap_out(page_table, vpn) {
+                freed += 1;
+            } else {
+                // The swap area is full or failed
+                return (freed, Some(vpn));
+            }
+        }
+        (freed, None)
+    }
+
+    /// Evict a resident page to its slot, writing it only if it has no clean copy there
+    ///
+    /// The page stays resident if it cannot be written.
+    fn swap_out(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> bool {
+        // A page read back from its slot keeps a copy there until it is written
+        let (slot, has_copy) = match self.swap_slots.remove(&vpn) {
+            Some(slot) => (slot, true),
+            None => match SwapSlot::alloc() {
+                Some(slot) => (slot, false),
+                None => return false,
+            },
+        };
+        // The page is unmapped before it is written, so that no hart can change it meanwhile
+        let old = page_table.swap_out(vpn, slot.0);
+        let frame = self.data_frames.remove(&vpn).unwrap();
+        if (old.dirty() || !has_copy) && slot.write(frame.ppn).is_err() {
+            // The intermediate tables are still there, so mapping the page again cannot fail
+            page_table.unmap(vpn);
+            page_table.map(vpn, frame.ppn, old.flags()).unwrap();
+            self.data_frames.insert(vpn, frame);
+            return false;
+        }
+        self.swap_slots.insert(vpn, slot);
+        true
+    }
+
+    /// Read the page at `vpn` into the frame if it is swapped out, and tell if it is
+    pub fn read_swapped(
+        &self,
+        page_table: &PageTable,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+    ) -> Result<bool, MemoryError> {
+        if page_table.translate(vpn).unwrap().swap_slot().is_none() {
+            return Ok(false);
         }
+        self.swap_slots[&vpn].read(ppn).map_err(|_| MemoryError::SwapIo)?;
+        Ok(true)
+    }
+
+    /// Read a page swapped out back into a new frame
+    ///
+    /// The slot is kept, so that the page is not written again if it is evicted while clean.
+    pub fn swap_in(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Result<(), MemoryError> {
+        let frame = frame_alloc_or_reclaim().ok_or(MemoryError::OutOfMemory)?;
+        self.swap_slots[&vpn].read(frame.ppn).map_err(|_| MemoryError::SwapIo)?;
+        page_table.unmap(vpn);
+        // Accessed already, so that the page is not evicted again before it is used
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap() | PTEFlags::A;
+        page_table.map(vpn, frame.ppn, pte_flags)?;
+        self.data_frames.insert(vpn, frame);
+        Ok(())
+    }
+
+    /// Map all the pages in the range, or none of them if it runs out of memory.
+    pub fn map(&mut self, page_table: &mut PageTable) -> Result<(), MemoryError> {
+        if self.map_type == MapType::IdenticalHuge {
+            let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+            for (vpn, size) in self.huge_pages() {
+                page_table.map_page(vpn, PhysPageNum(vpn.0), pte_flags, size)?;
+            }
+            return Ok(());
+        }
+        self.map_range(page_table, self.vpn_range)
+    }
+
+    /// Map the pages in `range`, unmapping them again if one fails
+    fn map_range(&mut self, page_table: &mut PageTable, range: VPNRange) -> Result<(), MemoryError> {
+        for vpn in range {
+            if let Err(err) = self.map_one(page_table, vpn) {
+                for mapped in VPNRange::new(range.get_start(), vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
     }
 
     /// Unmap all the pages in the range.
     pub fn unmap(&mut self, page_table: &mut PageTable) {
+        if self.map_type == MapType::IdenticalHuge {
+            for (vpn, _) in self.huge_pages() {
+                page_table.unmap(vpn);
+            }
+            return;
+        }
         for vpn in self.vpn_range {
             self.unmap_one(page_table, vpn);
         }
     }
 
+    /// Split the range into the largest pages their alignment allows, with the start of each
+    fn huge_pages(&self) -> Vec<(VirtPageNum, PageSize)> {
+        let mut pages = Vec::new();
+        let mut vpn = self.get_start().0;
+        let end = self.get_end().0;
+        while vpn < end {
+            let size = [PageSize::Giga, PageSize::Mega, PageSize::Normal]
+                .into_iter()
+                .find(|size| vpn % size.pages() == 0 && vpn + size.pages() <= end)
+                .unwrap();
+            pages.push((VirtPageNum(vpn), size));
+            vpn += size.pages();
+        }
+        pages
+    }
+
     /// Copy the data into the area.
     pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
         let mut start: usize = 0;
@@ -134,11 +313,27 @@ impl VMArea {
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
     }
 
-    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) {
-        for vpn in VPNRange::new(self.vpn_range.get_end(), new_end) {
-            self.map_one(page_table, vpn)
-        }
+    pub fn append_to(&mut self, page_table: &mut PageTable, new_end: VirtPageNum) -> Result<(), MemoryError> {
+        self.map_range(page_table, VPNRange::new(self.vpn_range.get_end(), new_end))?;
         self.vpn_range = VPNRange::new(self.vpn_range.get_start(), new_end);
+        Ok(())
+    }
+
+    /// Extend the area down to `new_start`, for a stack growing down
+    pub fn prepend_to(&mut self, page_table: &mut PageTable, new_start: VirtPageNum) -> Result<(), MemoryError> {
+        self.map_range(page_table, VPNRange::new(new_start, self.vpn_range.get_start()))?;
+        self.vpn_range = VPNRange::new(new_start, self.vpn_range.get_end());
+        Ok(())
+    }
+
+    /// Number of frames allocated for the area
+    pub fn frame_count(&self) -> usize {
+        self.data_frames.len()
+    }
+
+    /// Check if the area maps a shared memory object
+    pub fn is_shared(&self) -> bool {
+        self.map_type == MapType::Shared
     }
 
     pub const fn get_start(&self) -> VirtPageNum {
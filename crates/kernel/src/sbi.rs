@@ -5,19 +5,33 @@
 
 use sbi_rt::{NoReason, Shutdown, SystemFailure};
 
-pub fn console_putchar(c: usize) {
-    #[allow(deprecated)]
-    sbi_rt::legacy::console_putchar(c);
+pub fn set_timer(timer: usize) {
+    sbi_rt::set_timer(timer as _);
 }
 
-#[allow(unused)]
-pub fn console_getchar() -> usize {
-    #[allow(deprecated)]
-    sbi_rt::legacy::console_getchar()
+/// Whether the firmware implements the Hart State Management extension
+pub fn has_hsm() -> bool {
+    sbi_rt::probe_extension(sbi_rt::Hsm) != 0
 }
 
-pub fn set_timer(timer: usize) {
-    sbi_rt::set_timer(timer as _);
+/// Start a stopped hart at `start_addr` in S mode, with its hart id in `a0`
+pub fn hart_start(hart_id: usize, start_addr: usize) -> bool {
+    sbi_rt::hart_start(hart_id, start_addr, 0).error == 0
+}
+
+/// Raise a supervisor software interrupt on the harts in `hart_mask`
+pub fn send_ipi(hart_mask: usize) {
+    sbi_rt::send_ipi(hart_mask, 0);
+}
+
+/// Flush the TLB entries of `asid` in `[start, start + size)` on all the started harts
+pub fn remote_sfence_vma_asid(start: usize, size: usize, asid: usize) {
+    sbi_rt::remote_sfence_vma_asid(0, usize::MAX, start, size, asid);
+}
+
+/// Flush the whole TLB of all the started harts
+pub fn remote_sfence_vma_all() {
+    sbi_rt::remote_sfence_vma(0, usize::MAX, 0, usize::MAX);
 }
 
 pub fn shutdown(failure: bool) -> ! {
@@ -0,0 +1,62 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use ksync::SpinLock;
+use lazy_static::lazy_static;
+
+use super::{block_current_and_run_next, WaitQueue};
+
+/// Identity of a futex word
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FutexKey {
+    /// A word of one address space, by its token and the virtual address of the word
+    ///
+    /// The key does not change when the page of the word is swapped out and back to another frame.
+    Private(usize, usize),
+    /// A word in shared memory, by its physical address, which is the same in every process
+    /// mapping it and does not change since shared memory is never swapped out
+    Shared(usize),
+}
+
+lazy_static! {
+    /// Threads waiting on the futexes
+    static ref FUTEXES: SpinLock<BTreeMap<FutexKey, Arc<SpinLock<WaitQueue>>>> =
+        SpinLock::new(BTreeMap::new());
+}
+
+/// Block the current thread on the futex `key`, if its word still holds `val`
+///
+/// `word` is the kernel address of the word, whose page the caller keeps pinned. Return false
+/// without blocking if the word has changed. The value is checked with the table locked, which a
+/// waker has to take, so no wakeup after the check is missed.
+pub fn futex_wait(key: FutexKey, word: usize, val: u32) -> bool {
+    let mut futexes = FUTEXES.lock();
+    if unsafe { (*(word as *const AtomicU32)).load(Ordering::SeqCst) } != val {
+        return false;
+    }
+    let queue = futexes.entry(key).or_default().clone();
+    block_current_and_run_next(&queue, futexes);
+    true
+}
+
+/// Wake up at most `count` threads waiting on the futex `key`
+///
+/// Return the number of threads woken up.
+pub fn futex_wake(key: FutexKey, count: usize) -> usize {
+    let mut futexes = FUTEXES.lock();
+    let Some(queue) = futexes.get(&key) else {
+        return 0;
+    };
+    let mut queue = queue.lock();
+    let woken = (0..count).take_while(|_| queue.wake_one()).count();
+    if queue.is_empty() {
+        drop(queue);
+        futexes.remove(&key);
+    }
+    woken
+}
@@ -3,21 +3,84 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
-use alloc::sync::Arc;
-use proc::{schedule, take_current_task};
-use scheduler::add_thread;
+use alloc::{collections::BTreeMap, sync::Arc};
+use ksync::{interrupt, Park, SpinLock};
+use lazy_static::lazy_static;
+use preempt::disable_preemption;
+use proc::{current_processor, current_task, report_idle_time, schedule, take_current_task};
+use scheduler::{add_thread, remove_process, SCHEDULER};
 use thread_info::ThreadInfo;
 
-use crate::{log, sbi::shutdown, services::pm::exit};
+use crate::{fs::remove_files, log, sbi::shutdown, services::pm::exit};
 
+pub mod futex;
+pub mod policy;
+pub mod preempt;
 pub mod proc;
 pub mod scheduler;
 mod switch;
 mod thread_info;
+mod wait_queue;
 
+pub use wait_queue::WaitQueue;
+
+lazy_static! {
+    /// Threads which have exited, by the tokens of their spaces, until the spaces are removed
+    static ref EXITED: SpinLock<BTreeMap<usize, Arc<SpinLock<ThreadInfo>>>> =
+        SpinLock::new(BTreeMap::new());
+}
+
+/// A lock that puts the threads waiting for it to sleep
+pub type Mutex<T> = ksync::Mutex<T, ThreadParker>;
+
+/// Parks threads of the scheduler for the sleeping locks
+pub struct ThreadParker;
+
+impl Park for ThreadParker {
+    type Thread = Arc<SpinLock<ThreadInfo>>;
+
+    fn current() -> Self::Thread {
+        current_task().unwrap()
+    }
+
+    fn park() {
+        park_current_and_run_next();
+    }
+
+    fn unpark(thread: Self::Thread) {
+        add_thread(thread);
+    }
+
+    fn acquired() {
+        preempt::mutex_acquired();
+    }
+
+    fn released() {
+        preempt::mutex_released();
+    }
+}
+
+/// Give up the CPU voluntarily and switch to the next thread
 pub fn suspend_current_and_run_next() {
     let thread = take_current_task().unwrap();
-    let mut thread_info = thread.borrow_mut();
+    SCHEDULER.lock().yield_thread(&thread);
+    switch_from(thread);
+}
+
+/// Account a timer tick to the current thread, and switch to the next one if its slice is over
+pub fn tick_current_and_run_next() {
+    let thread = take_current_task().unwrap();
+    if SCHEDULER.lock().tick(&thread) {
+        switch_from(thread);
+    } else {
+        current_processor().set_current(thread);
+    }
+}
+
+/// Put the thread taken from the processor back to the scheduler and switch to the next one
+fn switch_from(thread: Arc<SpinLock<ThreadInfo>>) {
+    let mut thread_info = thread.lock();
+    thread_info.charge_system();
     let thread_info_ptr = &mut *thread_info as *mut ThreadInfo;
 
     drop(thread_info);
@@ -25,9 +88,44 @@ pub fn suspend_current_and_run_next() {
     schedule(thread_info_ptr);
 }
 
+/// Switch to the next thread without putting the current one back to the scheduler
+///
+/// Whoever holds another reference to the thread is responsible for waking it up.
+fn park_current_and_run_next() {
+    let thread = take_current_task().unwrap();
+    let mut thread_info = thread.lock();
+    thread_info.charge_system();
+    let thread_info_ptr = &mut *thread_info as *mut ThreadInfo;
+    drop(thread_info);
+    assert!(Arc::strong_count(&thread) > 1, "parked thread is unreachable");
+    drop(thread);
+    schedule(thread_info_ptr);
+}
+
+/// Put the current thread into the wait queue, release `guard` and switch to the next one
+///
+/// The thread is not scheduled again until it is woken up from the queue. `guard` protects the
+/// condition waited for, so that a wakeup between checking it and queueing the thread is not lost.
+///
+/// The thread must not be preempted once queued, or it would be in the run queue and the wait
+/// queue at the same time.
+pub fn block_current_and_run_next<G>(wait_queue: &SpinLock<WaitQueue>, guard: G) {
+    let preempt = disable_preemption();
+    wait_queue.lock().push(current_task().unwrap());
+    drop(guard);
+    // Switching away requires preemption enabled, so keep the thread from it with interrupts
+    let irq_enabled = interrupt::disable();
+    drop(preempt);
+    park_current_and_run_next();
+    interrupt::restore(irq_enabled);
+}
+
 pub fn exit_current_and_run_next(exit_code: i32) {
     let thread = take_current_task().unwrap();
-    let pid = thread.borrow_mut().pid;
+    let mut thread_info = thread.lock();
+    thread_info.charge_system();
+    let (pid, token, times) = (thread_info.pid, thread_info.token, thread_info.total_times());
+    drop(thread_info);
     log!(
         "[kernel] Task {} exit with exit_code {} ...",
         pid,
@@ -39,17 +137,32 @@ pub fn exit_current_and_run_next(exit_code: i32) {
             "[kernel] Init process exit with exit_code {} ...",
             exit_code
         );
+        report_idle_time();
         if exit_code != 0 {
             shutdown(true)
         } else {
             shutdown(false)
         }
     }
-    
-    assert!(Arc::strong_count(&thread) == 1);
+
+    // Nobody looks the thread up by its pid from now on, though others may still hold it
+    remove_process(pid);
     log!("[kernel] Calling task_struct->exit...");
-    exit(pid, exit_code);
+    remove_files(pid);
+    // The space holds the kernel stack this runs on, which PM may have removed on another hart
+    // as soon as it is told, so the thread is kept until it has switched out
+    EXITED.lock().insert(token, thread);
+    exit(pid, exit_code, times);
 
     let mut empty_ctx = ThreadInfo::default();
     schedule(&mut empty_ctx as *mut ThreadInfo)
 }
+
+/// Wait until the thread exited from the space `token` has switched out of its kernel stack,
+/// which is freed together with the space
+pub fn reap_exited(token: usize) {
+    let Some(thread) = EXITED.lock().remove(&token) else {
+        return;
+    };
+    thread.lock().wait_off_cpu();
+}
@@ -0,0 +1,227 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use alloc::{sync::Arc, vec::Vec};
+use ksync::SpinLock;
+
+use crate::trap::get_time;
+
+use super::{SchedPolicy, ThreadInfo};
+
+/// Fixed-point unit of the bandwidth, which is the share of a hart a real-time thread reserves
+const BW_UNIT: usize = 1 << 20;
+/// Bandwidth all the real-time threads can reserve together, 95% of one hart as in Linux, so that
+/// the other threads are never starved
+const MAX_BANDWIDTH: usize = BW_UNIT * 95 / 100;
+
+/// Reservation of a periodic real-time thread, in clock cycles
+#[derive(Clone, Copy)]
+pub struct DeadlineParams {
+    /// CPU time the thread may use in each period
+    pub runtime: usize,
+    /// Time from the start of a period by which its job should be done
+    pub deadline: usize,
+    pub period: usize,
+}
+
+impl DeadlineParams {
+    /// Whether the reservation can be met at all by itself
+    pub fn is_valid(&self) -> bool {
+        0 < self.runtime && self.runtime <= self.deadline && self.deadline <= self.period
+    }
+
+    /// Share of a hart reserved, which is at most `BW_UNIT` for a valid reservation
+    fn bandwidth(&self) -> usize {
+        // Long runtimes in cycles would overflow when scaled to the unit
+        (self.runtime as u128 * BW_UNIT as u128 / self.period as u128) as usize
+    }
+}
+
+/// Per-thread state of a real-time thread
+///
+/// The thread runs one job per period. A job ends when the thread yields, or when it has used up
+/// its runtime, which counts as an overrun. Either way the thread is throttled until the next
+/// period begins.
+#[derive(Clone)]
+pub struct DeadlineEntity {
+    pub params: DeadlineParams,
+    /// Absolute deadline of the current job
+    deadline: usize,
+    /// Start of the next period
+    next_release: usize,
+    /// CPU time of the thread when the current job started
+    budget_base: usize,
+    /// Whether the current job has ended and the thread waits for the next period
+    throttled: bool,
+    /// Number of jobs which used up their runtime before ending
+    pub overruns: usize,
+}
+
+impl DeadlineEntity {
+    /// Start the first job of the thread now
+    pub fn new(params: DeadlineParams, cpu_time: usize) -> Self {
+        let now = get_time();
+        Self {
+            params,
+            deadline: now + params.deadline,
+            next_release: now + params.period,
+            budget_base: cpu_time,
+            throttled: false,
+            overruns: 0,
+        }
+    }
+
+    /// End the current job, the thread does not run again until the next period
+    pub fn complete(&mut self) {
+        self.throttled = true;
+    }
+
+    /// Start a new job in the period containing `now`, skipping the periods missed entirely
+    ///
+    /// A job started before the current period is over starts the period over from `now`, which
+    /// postpones the next one.
+    fn replenish(&mut self, now: usize, cpu_time: usize) {
+        let period = self.params.period;
+        let start = if now >= self.next_release {
+            self.next_release + (now - self.next_release) / period * period
+        } else {
+            now
+        };
+        self.deadline = start + self.params.deadline;
+        self.next_release = start + period;
+        self.budget_base = cpu_time;
+        self.throttled = false;
+    }
+
+    /// Time when the current job uses up its runtime if it keeps running from `now`
+    pub fn budget_end(&self, now: usize, cpu_time: usize) -> usize {
+        let used = cpu_time - self.budget_base;
+        now + self.params.runtime.saturating_sub(used)
+    }
+}
+
+fn entity(thread_info: &mut ThreadInfo) -> &mut DeadlineEntity {
+    thread_info.sched.deadline.as_mut().expect("not a real-time thread")
+}
+
+/// Earliest deadline first among the real-time threads, which run before all the other threads
+///
+/// A thread is admitted only if the bandwidth of all the real-time threads stays within
+/// `MAX_BANDWIDTH`, so that they all fit on one hart.
+#[derive(Default)]
+pub struct EarliestDeadlineFirst {
+    /// Threads with a job to run
+    ready: Vec<Arc<SpinLock<ThreadInfo>>>,
+    /// Threads waiting for their next period
+    throttled: Vec<Arc<SpinLock<ThreadInfo>>>,
+    /// Bandwidth reserved by the admitted threads
+    bandwidth: usize,
+}
+
+impl EarliestDeadlineFirst {
+    /// Replace the reservation `old` of a thread with `new`, return false if it does not fit
+    pub fn reserve(&mut self, old: Option<&DeadlineParams>, new: Option<&DeadlineParams>) -> bool {
+        let old = old.map_or(0, DeadlineParams::bandwidth);
+        let new = new.map_or(0, DeadlineParams::bandwidth);
+        if self.bandwidth - old + new > MAX_BANDWIDTH {
+            return false;
+        }
+        self.bandwidth = self.bandwidth - old + new;
+        true
+    }
+
+    /// Move the threads whose next period has begun to the ready queue
+    fn release(&mut self, now: usize) {
+        let mut idx = 0;
+        while idx < self.throttled.len() {
+            let mut thread_info = self.throttled[idx].lock();
+            if entity(&mut thread_info).next_release > now {
+                idx += 1;
+                continue;
+            }
+            let cpu_time = thread_info.cpu_time();
+            entity(&mut thread_info).replenish(now, cpu_time);
+            drop(thread_info);
+            let thread = self.throttled.swap_remove(idx);
+            self.ready.push(thread);
+        }
+    }
+
+    /// Earliest deadline of the ready threads
+    fn earliest_deadline(&self) -> Option<usize> {
+        self.ready
+            .iter()
+            .map(|thread| entity(&mut thread.lock()).deadline)
+            .min()
+    }
+
+    /// Time when a throttled thread is released next, for the timer to wake up a hart then
+    pub fn next_release(&self) -> Option<usize> {
+        self.throttled
+            .iter()
+            .map(|thread| entity(&mut thread.lock()).next_release)
+            .min()
+    }
+}
+
+impl SchedPolicy for EarliestDeadlineFirst {
+    fn enqueue(&mut self, thread: Arc<SpinLock<ThreadInfo>>) {
+        let now = get_time();
+        let mut thread_info = thread.lock();
+        let cpu_time = thread_info.cpu_time();
+        let dl = entity(&mut thread_info);
+        if dl.throttled && now < dl.next_release {
+            drop(thread_info);
+            self.throttled.push(thread);
+            return;
+        }
+        // A thread which has slept past its deadline starts a new job rather than running late
+        if dl.throttled || now >= dl.deadline {
+            dl.replenish(now, cpu_time);
+        }
+        drop(thread_info);
+        self.ready.push(thread);
+    }
+
+    fn dequeue(&mut self, thread: &Arc<SpinLock<ThreadInfo>>) -> bool {
+        let len = self.ready.len() + self.throttled.len();
+        self.ready.retain(|other| !Arc::ptr_eq(other, thread));
+        self.throttled.retain(|other| !Arc::ptr_eq(other, thread));
+        self.ready.len() + self.throttled.len() != len
+    }
+
+    fn pick_next(&mut self) -> Option<Arc<SpinLock<ThreadInfo>>> {
+        self.release(get_time());
+        let (idx, _) = self
+            .ready
+            .iter()
+            .map(|thread| entity(&mut thread.lock()).deadline)
+            .enumerate()
+            .min_by_key(|&(_, deadline)| deadline)?;
+        Some(self.ready.swap_remove(idx))
+    }
+
+    fn on_tick(&mut self, thread: &Arc<SpinLock<ThreadInfo>>) -> bool {
+        let now = get_time();
+        let mut thread_info = thread.lock();
+        thread_info.charge_system();
+        let cpu_time = thread_info.cpu_time();
+        let dl = entity(&mut thread_info);
+        if cpu_time - dl.budget_base >= dl.params.runtime {
+            dl.overruns += 1;
+            dl.throttled = true;
+            return true;
+        }
+        let deadline = dl.deadline;
+        drop(thread_info);
+        self.release(now);
+        self.earliest_deadline().is_some_and(|earliest| earliest < deadline)
+    }
+
+    fn is_empty(&self) -> bool {
+        let now = get_time();
+        self.ready.is_empty() && self.next_release().map_or(true, |release| release > now)
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use ksync::SpinLock;
+
+use super::{SchedEntity, SchedPolicy, ThreadInfo};
+
+const NUM_LEVELS: usize = 3;
+/// Interval in ticks to move all the threads back to the highest level, so that none starves
+const BOOST_INTERVAL: usize = 100;
+
+/// Time slice in ticks of the thread at its level, doubled at each lower level and longer for a
+/// higher priority
+fn time_slice(entity: &SchedEntity) -> usize {
+    (1 << entity.level) * entity.slice_scale()
+}
+
+/// Highest level a thread can be at, lower for a larger nice value
+fn top_level(entity: &SchedEntity) -> usize {
+    match entity.nice {
+        nice if nice <= 0 => 0,
+        nice if nice < 10 => 1,
+        _ => 2,
+    }
+}
+
+/// Multi-level feedback queue
+///
+/// A thread starts at the highest level and moves down one level when it uses up its time slice.
+/// Giving up the CPU early does not reset the used ticks, so a thread cannot stay at a high level
+/// by yielding right before the end of its slice. Threads with a negative nice value get longer
+/// slices but are moved down as well, so that they cannot starve the others between two boosts,
+/// and threads with a positive one start and are boosted to a lower level.
+#[derive(Default)]
+pub struct MultiLevelFeedbackQueue {
+    queues: [VecDeque<Arc<SpinLock<ThreadInfo>>>; NUM_LEVELS],
+    ticks: usize,
+}
+
+impl MultiLevelFeedbackQueue {
+    fn boost(&mut self) {
+        for level in 1..NUM_LEVELS {
+            while let Some(thread) = self.queues[level].pop_front() {
+                let mut thread_info = thread.lock();
+                let top = top_level(&thread_info.sched);
+                thread_info.sched.level = top;
+                thread_info.sched.ticks = 0;
+                drop(thread_info);
+                self.queues[top].push_back(thread);
+            }
+        }
+    }
+}
+
+impl SchedPolicy for MultiLevelFeedbackQueue {
+    fn enqueue(&mut self, thread: Arc<SpinLock<ThreadInfo>>) {
+        let mut thread_info = thread.lock();
+        let entity = &mut thread_info.sched;
+        entity.level = entity.level.max(top_level(entity));
+        let level = entity.level;
+        drop(thread_info);
+        self.queues[level].push_back(thread);
+    }
+
+    fn dequeue(&mut self, thread: &Arc<SpinLock<ThreadInfo>>) -> bool {
+        let queue = &mut self.queues[thread.lock().sched.level];
+        let len = queue.len();
+        queue.retain(|other| !Arc::ptr_eq(other, thread));
+        queue.len() != len
+    }
+
+    fn pick_next(&mut self) -> Option<Arc<SpinLock<ThreadInfo>>> {
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+
+    fn on_tick(&mut self, thread: &Arc<SpinLock<ThreadInfo>>) -> bool {
+        self.ticks += 1;
+        let mut thread_info = thread.lock();
+        let entity = &mut thread_info.sched;
+        entity.ticks += 1;
+        let expired = entity.ticks >= time_slice(entity);
+        if expired {
+            entity.level = (entity.level + 1).min(NUM_LEVELS - 1);
+            entity.ticks = 0;
+        }
+        if self.ticks % BOOST_INTERVAL == 0 {
+            entity.level = top_level(entity);
+            entity.ticks = 0;
+            drop(thread_info);
+            self.boost();
+            return true;
+        }
+        expired
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+}
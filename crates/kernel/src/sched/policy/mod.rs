@@ -0,0 +1,122 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+mod edf;
+mod mlfq;
+mod rr;
+mod stride;
+
+use alloc::{boxed::Box, sync::Arc};
+use ksync::SpinLock;
+
+pub use edf::{DeadlineEntity, DeadlineParams, EarliestDeadlineFirst};
+pub use mlfq::MultiLevelFeedbackQueue;
+pub use rr::RoundRobin;
+pub use stride::Stride;
+
+use super::thread_info::ThreadInfo;
+
+/// Scheduling policies which can be selected in `config::SCHED_POLICY`
+#[allow(unused)]
+pub enum PolicyKind {
+    RoundRobin,
+    Stride,
+    MultiLevelFeedbackQueue,
+}
+
+impl PolicyKind {
+    pub fn build(&self) -> Box<dyn SchedPolicy> {
+        match self {
+            PolicyKind::RoundRobin => Box::new(RoundRobin::default()),
+            PolicyKind::Stride => Box::new(Stride::default()),
+            PolicyKind::MultiLevelFeedbackQueue => Box::new(MultiLevelFeedbackQueue::default()),
+        }
+    }
+}
+
+/// Highest priority a process can request
+pub const NICE_MIN: i32 = -20;
+/// Lowest priority a process can request
+pub const NICE_MAX: i32 = 19;
+/// Fixed priority of the services, higher than any process can request
+///
+/// It only gives the services a larger share of the CPU, so that a busy service cannot starve
+/// the processes.
+pub const NICE_SERVICE: i32 = NICE_MIN - 1;
+
+/// Load weight of each nice value from `NICE_SERVICE` to `NICE_MAX`, as in Linux
+///
+/// Each step of nice changes the share of the CPU by about 10% against a thread at nice 0.
+const NICE_TO_WEIGHT: [usize; 41] = [
+    110951,
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916,
+    9548, 7620, 6100, 4904, 3906, 3121, 2501, 1991, 1586, 1277,
+    1024, 820, 655, 526, 423, 335, 272, 215, 172, 137,
+    110, 87, 70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+/// Load weight of a thread at nice 0
+const NICE_0_WEIGHT: usize = 1024;
+/// Largest factor a time slice is scaled by for a high priority
+const MAX_SLICE_SCALE: usize = 8;
+
+/// Per-thread state kept by the scheduling policies
+#[derive(Clone, Default)]
+pub struct SchedEntity {
+    /// Priority of the thread, where a smaller value runs first or longer
+    pub nice: i32,
+    /// Virtual time of the thread in stride scheduling
+    pub pass: usize,
+    /// Queue level of the thread in MLFQ, where 0 is the highest priority
+    pub level: usize,
+    /// Ticks the thread has run since it got its current time slice
+    pub ticks: usize,
+    /// Reservation of a real-time thread, which is scheduled by `EarliestDeadlineFirst` instead
+    pub deadline: Option<DeadlineEntity>,
+}
+
+impl SchedEntity {
+    pub fn with_nice(nice: i32) -> Self {
+        Self {
+            nice,
+            ..Self::default()
+        }
+    }
+
+    pub fn weight(&self) -> usize {
+        NICE_TO_WEIGHT[(self.nice.clamp(NICE_SERVICE, NICE_MAX) - NICE_SERVICE) as usize]
+    }
+
+    /// Factor of the time slices of the thread, growing with its weight from 1 at nice 0 or above
+    /// up to `MAX_SLICE_SCALE`
+    pub fn slice_scale(&self) -> usize {
+        (self.weight() / NICE_0_WEIGHT).clamp(1, MAX_SLICE_SCALE)
+    }
+}
+
+/// Interface of a scheduling policy over the runnable threads
+///
+/// The running thread is not in the run queue of the policy. It is given back with `enqueue`
+/// when it is preempted, yields or is woken up. The priority in `SchedEntity::nice` of a thread
+/// does not change while it is in the run queue.
+pub trait SchedPolicy: Send {
+    /// Add a runnable thread to the run queue
+    fn enqueue(&mut self, thread: Arc<SpinLock<ThreadInfo>>);
+
+    /// Remove a thread from the run queue, return whether it was there
+    fn dequeue(&mut self, thread: &Arc<SpinLock<ThreadInfo>>) -> bool;
+
+    /// Take the thread to run next out of the run queue
+    fn pick_next(&mut self) -> Option<Arc<SpinLock<ThreadInfo>>>;
+
+    /// Account a timer tick to the running thread, return whether it should be preempted
+    fn on_tick(&mut self, thread: &Arc<SpinLock<ThreadInfo>>) -> bool;
+
+    /// Called when the running thread gives up the CPU by itself, before it is enqueued again
+    fn on_yield(&mut self, _thread: &Arc<SpinLock<ThreadInfo>>) {}
+
+    /// Whether there is no runnable thread
+    fn is_empty(&self) -> bool;
+}
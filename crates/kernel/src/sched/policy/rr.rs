@@ -0,0 +1,48 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use ksync::SpinLock;
+
+use super::{SchedPolicy, ThreadInfo};
+
+/// Weighted round-robin
+///
+/// All the runnable threads take turns, and each turn lasts `SchedEntity::slice_scale` ticks, so
+/// that a higher priority gets a larger share of the CPU without starving the lower ones.
+#[derive(Default)]
+pub struct RoundRobin {
+    queue: VecDeque<Arc<SpinLock<ThreadInfo>>>,
+}
+
+impl SchedPolicy for RoundRobin {
+    fn enqueue(&mut self, thread: Arc<SpinLock<ThreadInfo>>) {
+        self.queue.push_back(thread);
+    }
+
+    fn dequeue(&mut self, thread: &Arc<SpinLock<ThreadInfo>>) -> bool {
+        let len = self.queue.len();
+        self.queue.retain(|other| !Arc::ptr_eq(other, thread));
+        self.queue.len() != len
+    }
+
+    fn pick_next(&mut self) -> Option<Arc<SpinLock<ThreadInfo>>> {
+        let thread = self.queue.pop_front()?;
+        // Each turn starts a new time slice
+        thread.lock().sched.ticks = 0;
+        Some(thread)
+    }
+
+    fn on_tick(&mut self, thread: &Arc<SpinLock<ThreadInfo>>) -> bool {
+        let mut thread_info = thread.lock();
+        let entity = &mut thread_info.sched;
+        entity.ticks += 1;
+        entity.ticks >= entity.slice_scale()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
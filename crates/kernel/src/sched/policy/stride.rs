@@ -0,0 +1,68 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use alloc::{sync::Arc, vec::Vec};
+use ksync::SpinLock;
+
+use super::{SchedPolicy, ThreadInfo};
+
+/// Stride of a thread is `BIG_STRIDE` divided by its weight, so that its share of the CPU is
+/// proportional to the weight
+const BIG_STRIDE: usize = 1 << 30;
+
+/// Compare two passes which may have wrapped around
+fn pass_before(a: usize, b: usize) -> bool {
+    (a.wrapping_sub(b) as isize) < 0
+}
+
+/// Stride scheduling, always running the thread with the smallest pass
+#[derive(Default)]
+pub struct Stride {
+    threads: Vec<Arc<SpinLock<ThreadInfo>>>,
+    /// Pass of the thread scheduled last, which no thread in the queue is behind
+    global_pass: usize,
+}
+
+impl SchedPolicy for Stride {
+    fn enqueue(&mut self, thread: Arc<SpinLock<ThreadInfo>>) {
+        // A new or long blocked thread must not monopolize the CPU to catch up
+        let mut thread_info = thread.lock();
+        if pass_before(thread_info.sched.pass, self.global_pass) {
+            thread_info.sched.pass = self.global_pass;
+        }
+        drop(thread_info);
+        self.threads.push(thread);
+    }
+
+    fn dequeue(&mut self, thread: &Arc<SpinLock<ThreadInfo>>) -> bool {
+        let len = self.threads.len();
+        self.threads.retain(|other| !Arc::ptr_eq(other, thread));
+        self.threads.len() != len
+    }
+
+    fn pick_next(&mut self) -> Option<Arc<SpinLock<ThreadInfo>>> {
+        let (idx, _) = self
+            .threads
+            .iter()
+            .map(|thread| thread.lock().sched.pass)
+            .enumerate()
+            .reduce(|min, cur| if pass_before(cur.1, min.1) { cur } else { min })?;
+        let thread = self.threads.swap_remove(idx);
+        let mut thread_info = thread.lock();
+        self.global_pass = thread_info.sched.pass;
+        let stride = BIG_STRIDE / thread_info.sched.weight();
+        thread_info.sched.pass = thread_info.sched.pass.wrapping_add(stride);
+        drop(thread_info);
+        Some(thread)
+    }
+
+    fn on_tick(&mut self, _thread: &Arc<SpinLock<ThreadInfo>>) -> bool {
+        true
+    }
+
+    fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+}
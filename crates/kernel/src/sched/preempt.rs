@@ -0,0 +1,78 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+// Kernel preemption control
+//
+// A thread running in the kernel with interrupts enabled is preempted by the timer only when the
+// preempt count of its hart is zero. Disabling preemption keeps the thread on the current hart,
+// while interrupts are still handled. A preemption missed while disabled waits for the next tick.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use ksync::interrupt;
+
+use crate::config::MAX_HARTS;
+
+use super::proc::hart_id;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO_COUNT: AtomicUsize = AtomicUsize::new(0);
+static PREEMPT_COUNT: [AtomicUsize; MAX_HARTS] = [ZERO_COUNT; MAX_HARTS];
+/// Sleeping locks held by the thread running on each hart, which go with the thread when it
+/// switches out
+static MUTEX_COUNT: [AtomicUsize; MAX_HARTS] = [ZERO_COUNT; MAX_HARTS];
+
+/// Preemption stays disabled on the current hart until the guard is dropped
+pub struct PreemptGuard;
+
+impl Drop for PreemptGuard {
+    fn drop(&mut self) {
+        // The thread has not moved since the count was increased
+        PREEMPT_COUNT[hart_id()].fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Disable preemption on the current hart, can be nested
+pub fn disable_preemption() -> PreemptGuard {
+    // The thread must not move to another hart between reading the hart id and increasing the count
+    let irq_enabled = interrupt::disable();
+    PREEMPT_COUNT[hart_id()].fetch_add(1, Ordering::Relaxed);
+    interrupt::restore(irq_enabled);
+    PreemptGuard
+}
+
+/// Whether the thread running on the current hart can be switched away
+pub fn preemptible() -> bool {
+    PREEMPT_COUNT[hart_id()].load(Ordering::Relaxed) == 0
+}
+
+/// Count a sleeping lock taken by the thread running on the current hart
+pub fn mutex_acquired() {
+    let irq_enabled = interrupt::disable();
+    MUTEX_COUNT[hart_id()].fetch_add(1, Ordering::Relaxed);
+    interrupt::restore(irq_enabled);
+}
+
+/// Count a sleeping lock released by the thread running on the current hart
+pub fn mutex_released() {
+    let irq_enabled = interrupt::disable();
+    MUTEX_COUNT[hart_id()].fetch_sub(1, Ordering::Relaxed);
+    interrupt::restore(irq_enabled);
+}
+
+/// Whether the thread running on the current hart holds any sleeping lock
+pub fn holds_mutex() -> bool {
+    MUTEX_COUNT[hart_id()].load(Ordering::Relaxed) != 0
+}
+
+/// Take the count of sleeping locks of the thread switching out of the current hart
+pub fn take_mutex_count() -> usize {
+    MUTEX_COUNT[hart_id()].swap(0, Ordering::Relaxed)
+}
+
+/// Give the count of sleeping locks of the thread switching in to the current hart
+pub fn set_mutex_count(count: usize) {
+    MUTEX_COUNT[hart_id()].store(count, Ordering::Relaxed);
+}
@@ -3,61 +3,110 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
+use core::arch::asm;
+
 use alloc::sync::Arc;
-use ksync::UPSafeCell;
+use ksync::{interrupt, SpinLock, SpinLockGuard};
 use lazy_static::lazy_static;
 
-use crate::{mm::get_trap_ctx, trap::TrapContext};
+use crate::{
+    config::{CLOCK_FREQ, MAX_HARTS},
+    log,
+    mm::get_trap_ctx,
+    trap::TrapContext,
+};
 
-use super::{switch::__switch, thread_info::ThreadInfo};
+use super::{
+    preempt::{disable_preemption, preemptible, take_mutex_count},
+    switch::__switch,
+    thread_info::ThreadInfo,
+};
 
 lazy_static! {
-    pub static ref PROCESSOR: UPSafeCell<Processor> =
-        unsafe { UPSafeCell::new(Processor::default()) };
+    /// Processors of the harts, each one is only accessed by its own hart
+    static ref PROCESSORS: [SpinLock<Processor>; MAX_HARTS] =
+        core::array::from_fn(|_| SpinLock::new(Processor::default()));
 }
 
 #[derive(Default)]
 pub struct Processor {
-    current: Option<Arc<UPSafeCell<ThreadInfo>>>,
+    current: Option<Arc<SpinLock<ThreadInfo>>>,
     scheduler: ThreadInfo,
+    /// Time spent with nothing to run, in clock cycles
+    idle_time: usize,
 }
 
 impl Processor {
-    pub fn take_current(&mut self) -> Option<Arc<UPSafeCell<ThreadInfo>>> {
+    pub fn take_current(&mut self) -> Option<Arc<SpinLock<ThreadInfo>>> {
         self.current.take()
     }
 
-    pub fn current(&self) -> Option<Arc<UPSafeCell<ThreadInfo>>> {
+    pub fn current(&self) -> Option<Arc<SpinLock<ThreadInfo>>> {
         self.current.clone()
     }
 
-    pub fn set_current(&mut self, thread: Arc<UPSafeCell<ThreadInfo>>) {
+    pub fn set_current(&mut self, thread: Arc<SpinLock<ThreadInfo>>) {
         self.current = Some(thread);
     }
 
     pub fn scheduler(&mut self) -> *mut ThreadInfo {
         &mut self.scheduler as *mut ThreadInfo
     }
+
+    pub fn add_idle_time(&mut self, time: usize) {
+        self.idle_time += time;
+    }
+}
+
+/// Log the idle time of the harts which have run the scheduler
+pub fn report_idle_time() {
+    for (hart_id, processor) in PROCESSORS.iter().enumerate() {
+        let idle_time = processor.lock().idle_time;
+        if idle_time > 0 {
+            log!("[kernel] Hart {} idle for {} ms", hart_id, idle_time / (CLOCK_FREQ / 1000));
+        }
+    }
+}
+
+/// Id of the current hart, which is kept in `tp` while running in the kernel
+pub fn hart_id() -> usize {
+    let hart_id;
+    unsafe {
+        asm!("mv {}, tp", out(reg) hart_id);
+    }
+    hart_id
+}
+
+pub fn current_processor() -> SpinLockGuard<'static, Processor> {
+    // Stay on this hart until the lock disables interrupts
+    let _preempt = disable_preemption();
+    PROCESSORS[hart_id()].lock()
+}
+
+pub fn take_current_task() -> Option<Arc<SpinLock<ThreadInfo>>> {
+    current_processor().take_current()
 }
 
-pub fn take_current_task() -> Option<Arc<UPSafeCell<ThreadInfo>>> {
-    PROCESSOR.borrow_mut().take_current()
+pub fn current_task() -> Option<Arc<SpinLock<ThreadInfo>>> {
+    current_processor().current()
 }
 
-pub fn current_task() -> Option<Arc<UPSafeCell<ThreadInfo>>> {
-    PROCESSOR.borrow_mut().current()
+/// Get the current thread unless the processor is locked, for a trap that may be taken with it
+/// locked, which runs with interrupts disabled
+pub fn try_current_task() -> Option<Arc<SpinLock<ThreadInfo>>> {
+    PROCESSORS[hart_id()].try_lock()?.current()
 }
 
 pub fn current_pid() -> usize {
-    PROCESSOR.borrow_mut().current().unwrap().borrow_mut().pid
+    current_processor().current().unwrap().lock().pid
 }
 
 pub fn current_user_token() -> usize {
-    PROCESSOR.borrow_mut().current().unwrap().borrow_mut().token
+    current_processor().current().unwrap().lock().token
 }
 
 pub fn set_user_token(token: usize) {
-    PROCESSOR.borrow_mut().current().unwrap().borrow_mut().token = token;
+    current_processor().current().unwrap().lock().token = token;
 }
 
 pub fn current_trap_ctx() -> &'static mut TrapContext {
@@ -65,11 +114,19 @@ pub fn current_trap_ctx() -> &'static mut TrapContext {
     get_trap_ctx(token)
 }
 
+/// Switch from the thread to the scheduler loop of the current hart
+///
+/// The scheduler loop runs with interrupts disabled, and the thread gets back its own interrupt
+/// state when it is switched in again, possibly on another hart.
 pub fn schedule(switched_thread: *mut ThreadInfo) {
-    let mut processor = PROCESSOR.borrow_mut();
+    assert!(preemptible(), "switching away with preemption disabled");
+    let irq_enabled = interrupt::disable();
+    let mut processor = current_processor();
     let next_thread = processor.scheduler();
     drop(processor);
     unsafe {
+        (*switched_thread).mutexes = take_mutex_count();
         __switch(switched_thread, next_thread);
     }
+    interrupt::restore(irq_enabled);
 }
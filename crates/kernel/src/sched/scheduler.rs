@@ -4,91 +4,316 @@
 // LICENSE file in the root directory of this source tree.
 
 use core::arch::asm;
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
 
-use alloc::{collections::{BTreeMap, VecDeque}, string::{String, ToString}, sync::Arc};
-use ksync::UPSafeCell;
+use alloc::{boxed::Box, collections::BTreeMap, string::{String, ToString}, sync::{Arc, Weak}, vec::Vec};
+use ksync::{SpinLock, TicketLock};
 use lazy_static::lazy_static;
-use super::{proc::PROCESSOR, switch::__switch};
+use super::{
+    policy::{
+        DeadlineEntity, DeadlineParams, EarliestDeadlineFirst, SchedEntity, SchedPolicy, NICE_MAX,
+        NICE_MIN, NICE_SERVICE,
+    },
+    preempt::set_mutex_count,
+    proc::{current_processor, current_task, hart_id},
+    switch::__switch,
+};
 use super::thread_info::ThreadInfo;
 
-use crate::log;
+use crate::{
+    config::SCHED_POLICY,
+    log,
+    sbi::send_ipi,
+    trap::{get_time, wait_for_interrupt},
+};
 
 lazy_static! {
-    pub static ref SCHEDULER: UPSafeCell<Scheduler> =
-        unsafe { UPSafeCell::new(Scheduler::default()) };
+    /// Run queue shared by all the harts, taken in FIFO order as every hart polls it
+    pub static ref SCHEDULER: TicketLock<Scheduler> =
+        TicketLock::new(Scheduler::new(SCHED_POLICY.build()));
+    /// Threads of the processes indexed by pid, whether they are running, runnable or blocked
+    static ref THREADS: SpinLock<BTreeMap<usize, Weak<SpinLock<ThreadInfo>>>> =
+        SpinLock::new(BTreeMap::new());
 }
 
-#[derive(Default)]
+/// Harts sleeping in `idle`, one of which is woken up when a thread becomes runnable
+static IDLE_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Run queue of the scheduling classes, where the real-time threads always run first
 pub struct Scheduler {
-    threads: VecDeque<Arc<UPSafeCell<ThreadInfo>>>,
+    rt: EarliestDeadlineFirst,
+    policy: Box<dyn SchedPolicy>,
+}
+
+/// Whether the thread is scheduled as a real-time thread
+fn is_real_time(thread: &Arc<SpinLock<ThreadInfo>>) -> bool {
+    thread.lock().sched.deadline.is_some()
 }
 
 impl Scheduler {
-    fn add_thread(&mut self, thread: Arc<UPSafeCell<ThreadInfo>>) {
-        self.threads.push_back(thread.clone());
+    pub fn new(policy: Box<dyn SchedPolicy>) -> Self {
+        Self {
+            rt: EarliestDeadlineFirst::default(),
+            policy,
+        }
+    }
+
+    fn add_thread(&mut self, thread: Arc<SpinLock<ThreadInfo>>) {
+        self.add(thread);
     }
     
-    pub fn add(&mut self, thread: Arc<UPSafeCell<ThreadInfo>>) {
-        self.threads.push_back(thread);
+    pub fn add(&mut self, thread: Arc<SpinLock<ThreadInfo>>) {
+        if is_real_time(&thread) {
+            self.rt.enqueue(thread);
+        } else {
+            self.policy.enqueue(thread);
+        }
     }
 
-    pub fn pop(&mut self) -> Option<Arc<UPSafeCell<ThreadInfo>>> {
-        self.threads.pop_front()
+    pub fn pop(&mut self) -> Option<Arc<SpinLock<ThreadInfo>>> {
+        self.rt.pick_next().or_else(|| self.policy.pick_next())
+    }
+
+    pub fn remove(&mut self, thread: &Arc<SpinLock<ThreadInfo>>) -> bool {
+        if is_real_time(thread) {
+            self.rt.dequeue(thread)
+        } else {
+            self.policy.dequeue(thread)
+        }
+    }
+
+    /// Account a timer tick to the running thread, return whether it should be preempted
+    ///
+    /// Any real-time thread with a job to run preempts the other threads.
+    pub fn tick(&mut self, thread: &Arc<SpinLock<ThreadInfo>>) -> bool {
+        if is_real_time(thread) {
+            self.rt.on_tick(thread)
+        } else {
+            !self.rt.is_empty() || self.policy.on_tick(thread)
+        }
+    }
+
+    pub fn yield_thread(&mut self, thread: &Arc<SpinLock<ThreadInfo>>) {
+        if is_real_time(thread) {
+            self.rt.on_yield(thread)
+        } else {
+            self.policy.on_yield(thread)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rt.is_empty() && self.policy.is_empty()
+    }
+
+    /// Time when a real-time thread waiting for its next period becomes runnable
+    pub fn next_release(&self) -> Option<usize> {
+        self.rt.next_release()
     }
 }
 
-pub fn add_process(pid: usize, token: usize) {
-    let thread = Arc::new(unsafe { UPSafeCell::new(ThreadInfo::new(pid, token)) });
-    SCHEDULER.borrow_mut().add_thread(thread);
+pub fn add_process(pid: usize, parent: usize, token: usize) {
+    let thread = Arc::new(SpinLock::new(ThreadInfo::new(pid, parent, token)));
+    THREADS.lock().insert(pid, Arc::downgrade(&thread));
+    SCHEDULER.lock().add_thread(thread);
+    wake_idle_hart();
 }
 
 pub fn add_service(token: usize) {
-    let thread = Arc::new(unsafe { UPSafeCell::new(ThreadInfo::new(0, token)) });
-    SCHEDULER.borrow_mut().add_thread(thread)
+    let mut thread_info = ThreadInfo::new(0, 0, token);
+    thread_info.sched = SchedEntity::with_nice(NICE_SERVICE);
+    let thread = Arc::new(SpinLock::new(thread_info));
+    SCHEDULER.lock().add_thread(thread)
+}
+
+/// Forget the thread of an exited process, whose children are adopted by init
+///
+/// The bandwidth reserved by a real-time thread is given back.
+pub fn remove_process(pid: usize) {
+    let Some(thread) = THREADS.lock().remove(&pid).and_then(|thread| thread.upgrade()) else {
+        return;
+    };
+    let threads: Vec<_> = THREADS.lock().values().filter_map(Weak::upgrade).collect();
+    for child in threads {
+        let mut child = child.lock();
+        if child.parent == pid {
+            child.parent = 1;
+        }
+    }
+    let mut scheduler = SCHEDULER.lock();
+    if let Some(entity) = thread.lock().sched.deadline.take() {
+        log!("[kernel] Task {} overran its runtime {} times", pid, entity.overruns);
+        scheduler.rt.reserve(Some(&entity.params), None);
+    }
 }
 
-pub fn add_thread(thread: Arc<UPSafeCell<ThreadInfo>>) {
-    SCHEDULER.borrow_mut().add(thread);
+/// Make a process exit the next time it returns to user mode
+///
+/// A thread blocked in the kernel exits only after it wakes up.
+pub fn kill_process(pid: usize) -> bool {
+    let Some(thread) = THREADS.lock().get(&pid).and_then(Weak::upgrade) else {
+        return false;
+    };
+    thread.lock().killed = true;
+    true
 }
 
-pub fn pop_thread() -> Option<Arc<UPSafeCell<ThreadInfo>>> {
-    SCHEDULER.borrow_mut().pop()
+/// Whether the process `pid` is `caller` itself or one of its children
+pub fn is_self_or_child(caller: usize, pid: usize) -> bool {
+    if caller == pid {
+        return true;
+    }
+    let Some(thread) = THREADS.lock().get(&pid).and_then(Weak::upgrade) else {
+        return false;
+    };
+    let parent = thread.lock().parent;
+    parent == caller
+}
+
+/// Get the nice value of a process
+pub fn get_priority(pid: usize) -> Option<i32> {
+    let thread = THREADS.lock().get(&pid)?.upgrade()?;
+    let nice = thread.lock().sched.nice;
+    Some(nice)
+}
+
+/// Set the nice value of a process, clamped into `[NICE_MIN, NICE_MAX]`
+///
+/// A runnable thread is put back into the run queue, so that the policy sees the new priority.
+pub fn set_priority(pid: usize, nice: i32) -> bool {
+    let Some(thread) = THREADS.lock().get(&pid).and_then(Weak::upgrade) else {
+        return false;
+    };
+    let is_current = current_task().is_some_and(|current| Arc::ptr_eq(&current, &thread));
+    let mut scheduler = SCHEDULER.lock();
+    let queued = !is_current && scheduler.remove(&thread);
+    thread.lock().sched.nice = nice.clamp(NICE_MIN, NICE_MAX);
+    if queued {
+        scheduler.add(thread);
+    }
+    true
+}
+
+/// Reasons for `set_deadline` to fail
+pub enum DeadlineError {
+    NoProcess,
+    /// The real-time threads would reserve more bandwidth than the harts can serve
+    Overloaded,
+}
+
+/// Make a process a real-time thread with the reservation, or a normal one with `None`
+///
+/// A runnable thread is put back into the run queue of its new class, and a real-time thread
+/// starts its first job right away.
+pub fn set_deadline(pid: usize, params: Option<DeadlineParams>) -> Result<(), DeadlineError> {
+    let Some(thread) = THREADS.lock().get(&pid).and_then(Weak::upgrade) else {
+        return Err(DeadlineError::NoProcess);
+    };
+    let is_current = current_task().is_some_and(|current| Arc::ptr_eq(&current, &thread));
+    let mut scheduler = SCHEDULER.lock();
+    let old = thread.lock().sched.deadline.as_ref().map(|entity| entity.params);
+    if !scheduler.rt.reserve(old.as_ref(), params.as_ref()) {
+        return Err(DeadlineError::Overloaded);
+    }
+    let queued = !is_current && scheduler.remove(&thread);
+    let mut thread_info = thread.lock();
+    let cpu_time = thread_info.cpu_time();
+    thread_info.sched.deadline = params.map(|params| DeadlineEntity::new(params, cpu_time));
+    drop(thread_info);
+    if queued {
+        scheduler.add(thread);
+    }
+    Ok(())
+}
+
+/// Get the reservation of a process and the number of its overruns, `None` for a normal process
+pub fn get_deadline(pid: usize) -> Option<Option<(DeadlineParams, usize)>> {
+    let thread = THREADS.lock().get(&pid)?.upgrade()?;
+    let thread_info = thread.lock();
+    let deadline = thread_info.sched.deadline.as_ref();
+    Some(deadline.map(|entity| (entity.params, entity.overruns)))
+}
+
+/// End the current job of the current thread if it is a real-time one
+pub fn complete_current_job() {
+    let thread = current_task().unwrap();
+    if let Some(entity) = thread.lock().sched.deadline.as_mut() {
+        entity.complete();
+    }
+}
+
+/// Time when a real-time thread waiting for its next period becomes runnable
+pub fn next_release() -> Option<usize> {
+    SCHEDULER.lock().next_release()
+}
+
+pub fn add_thread(thread: Arc<SpinLock<ThreadInfo>>) {
+    SCHEDULER.lock().add(thread);
+    wake_idle_hart();
+}
+
+/// Wake up another idle hart to run the thread just added
+fn wake_idle_hart() {
+    // Pairs with the check of the run queue in `idle`, either the added thread is seen there or
+    // the idle hart is seen here
+    fence(Ordering::SeqCst);
+    let idle_harts = IDLE_HARTS.load(Ordering::SeqCst) & !(1 << hart_id());
+    if idle_harts != 0 {
+        send_ipi(idle_harts & idle_harts.wrapping_neg());
+    }
+}
+
+/// Sleep until an interrupt, as there is nothing to run on the current hart
+fn idle() {
+    let hart_mask = 1 << hart_id();
+    let start = get_time();
+    IDLE_HARTS.fetch_or(hart_mask, Ordering::SeqCst);
+    // A thread added before the hart is marked idle does not wake it up
+    let scheduler = SCHEDULER.lock();
+    let (is_empty, next_release) = (scheduler.is_empty(), scheduler.next_release());
+    drop(scheduler);
+    if is_empty {
+        wait_for_interrupt(next_release);
+    }
+    IDLE_HARTS.fetch_and(!hart_mask, Ordering::SeqCst);
+    current_processor().add_idle_time(get_time() - start);
+}
+
+pub fn pop_thread() -> Option<Arc<SpinLock<ThreadInfo>>> {
+    SCHEDULER.lock().pop()
 }
 
 pub fn start_schedule() -> ! {
     loop {
-        let mut processor = PROCESSOR.borrow_mut();
-        if let Some(thread) = pop_thread() {
-            let scheduler = processor.scheduler();
-            let mut next_thread = thread.borrow_mut();
-            let next_thread_pid = next_thread.pid;
-            let next_thread_ptr = &mut *next_thread as *mut ThreadInfo;
-            let mut current_sp: usize;
-            unsafe {
-                asm!("mv {}, sp", out(reg) current_sp);
-            }
-            log!(
-                "[kernel] Switch to task {}:{:x} with sp {:x} ...",
-                next_thread_pid,
-                next_thread.get_sp(),
-                current_sp,
-            );
-            // unsafe {
-                // asm!("lw {}, 0x0({})", out(reg) current_sp, in(reg) next_thread.sp - 4);
-            // }
-            // log!(
-            //     "[kernel] Switch to task {} with data {:x} ...",
-            //     next_thread_pid,
-            //     current_sp,
-            // );
-            drop(next_thread);
-
-            processor.set_current(thread);
-            drop(processor);
-            // log!("Here");
-            unsafe {
-                __switch(scheduler, next_thread_ptr);
-            }
+        let Some(thread) = pop_thread() else {
+            idle();
+            continue;
+        };
+        let mut processor = current_processor();
+        let scheduler = processor.scheduler();
+        let mut next_thread = thread.lock();
+        let next_thread_pid = next_thread.pid;
+        let next_thread_ptr = &mut *next_thread as *mut ThreadInfo;
+        let mut current_sp: usize;
+        unsafe {
+            asm!("mv {}, sp", out(reg) current_sp);
+        }
+        log!(
+            "[kernel] Switch to task {}:{:x} with sp {:x} ...",
+            next_thread_pid,
+            next_thread.get_sp(),
+            current_sp,
+        );
+        next_thread.acquire_cpu();
+        next_thread.start_clock();
+        set_mutex_count(next_thread.mutexes);
+        drop(next_thread);
+
+        processor.set_current(thread);
+        drop(processor);
+        unsafe {
+            __switch(scheduler, next_thread_ptr);
+            // The thread has switched back to this hart with its context saved
+            (*next_thread_ptr).release_cpu();
         }
     }
-}
\ No newline at end of file
+}
@@ -3,32 +3,126 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
-use crate::{log, mm::get_kernel_stack, trap::trap_return};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use ksync::msg::task::CpuTimes;
+
+use crate::{
+    log,
+    mm::get_kernel_stack,
+    trap::{get_time, trap_return},
+};
+
+use super::policy::SchedEntity;
 
 /// Task Context
 ///
 /// This struct is used to store the context of a task, containing the return address of the task, the stack pointer of the task, and the callee-saved registers.
 #[repr(C)]
-#[derive(Clone, Default)]
+#[derive(Default)]
 pub struct ThreadInfo {
     ra: usize,
     sp: usize,
     s: [usize; 12],
     pub pid: usize,
+    /// Pid of the parent process, which is init for an orphan, and 0 for init and the services
+    pub parent: usize,
     pub token: usize,
+    pub sched: SchedEntity,
+    /// Whether a hart is running the thread or has not saved its context yet
+    on_cpu: AtomicBool,
+    /// CPU time used by the thread
+    pub times: CpuTimes,
+    /// CPU time used by the children reaped by the thread, including their reaped children
+    pub children_times: CpuTimes,
+    /// Time of the last switch or trap boundary, from which the running time is charged
+    stamp: usize,
+    /// Whether the thread is to exit before returning to user mode
+    pub killed: bool,
+    /// Sleeping locks held by the thread while it is switched out
+    pub mutexes: usize,
 }
 
 impl ThreadInfo {
-    pub fn new(pid: usize, token: usize) -> Self {
+    pub fn new(pid: usize, parent: usize, token: usize) -> Self {
         Self {
             ra: trap_return as usize,
             sp: get_kernel_stack(token),
             s: [0; 12],
             pid,
+            parent,
             token,
+            sched: SchedEntity::default(),
+            on_cpu: AtomicBool::new(false),
+            times: CpuTimes::default(),
+            children_times: CpuTimes::default(),
+            stamp: 0,
+            killed: false,
+            mutexes: 0,
+        }
+    }
+
+    /// Mark the thread as running on the current hart
+    ///
+    /// A thread is put back into the run queue before it switches out, so another hart may pick
+    /// it up before its context is saved, and has to wait for that.
+    pub fn acquire_cpu(&self) {
+        while self
+            .on_cpu
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Mark the context of the thread as saved, called after it has switched out
+    pub fn release_cpu(&self) {
+        self.on_cpu.store(false, Ordering::Release);
+    }
+
+    /// Wait until no hart runs the thread, so that its kernel stack is not in use
+    pub fn wait_off_cpu(&self) {
+        while self.on_cpu.load(Ordering::Acquire) {
+            core::hint::spin_loop();
         }
     }
     
+    /// Start charging time to the thread, called when it switches in
+    pub fn start_clock(&mut self) {
+        self.stamp = get_time();
+    }
+
+    /// Charge the time since the last boundary as user time, called on a trap from user mode
+    pub fn charge_user(&mut self) {
+        self.times.user += self.elapsed();
+    }
+
+    /// Charge the time since the last boundary as system time, called on returning to user mode
+    /// or switching out
+    pub fn charge_system(&mut self) {
+        self.times.system += self.elapsed();
+    }
+
+    fn elapsed(&mut self) -> usize {
+        let now = get_time();
+        let elapsed = now.saturating_sub(self.stamp);
+        self.stamp = now;
+        elapsed
+    }
+
+    /// CPU time of the thread, as charged at the last boundary
+    pub fn cpu_time(&self) -> usize {
+        self.times.user + self.times.system
+    }
+
+    /// CPU time of the thread together with its reaped children, reported to its parent on exit
+    pub fn total_times(&self) -> CpuTimes {
+        let mut times = self.times;
+        times += self.children_times;
+        times
+    }
+
     pub fn get_sp(&self) -> usize {
         self.sp
     }
@@ -0,0 +1,35 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use ksync::SpinLock;
+
+use super::{scheduler::add_thread, thread_info::ThreadInfo};
+
+/// Threads blocked on an event, waiting to be woken up in FIFO order
+#[derive(Default)]
+pub struct WaitQueue {
+    threads: VecDeque<Arc<SpinLock<ThreadInfo>>>,
+}
+
+impl WaitQueue {
+    pub fn push(&mut self, thread: Arc<SpinLock<ThreadInfo>>) {
+        self.threads.push_back(thread);
+    }
+
+    /// Move the first waiting thread back to the scheduler, return whether there was one
+    pub fn wake_one(&mut self) -> bool {
+        self.threads.pop_front().map(add_thread).is_some()
+    }
+
+    /// Move all the waiting threads back to the scheduler
+    pub fn wake_all(&mut self) {
+        self.threads.drain(..).for_each(add_thread);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+}
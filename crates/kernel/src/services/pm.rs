@@ -6,22 +6,22 @@
 use core::mem::size_of;
 
 use ksync::msg::{
-    queue::MsgQueue, task::{Kernel2PM, PM2Kernel}, Kernel2PMPort
+    queue::MsgQueue, task::{CpuTimes, Kernel2PM, PM2Kernel}, Kernel2PMPort
 };
 
 use crate::{
-    loader::get_service_data_by_name, log, mm::{new_service, recycle_user_space, remove_user_space}, recv_msg, resolve_msg, sched::scheduler::add_service, send_msg, send_msg_and_wait, syscall::sys_yield
+    loader::get_service_data_by_name, log, mm::{new_service, recycle_user_space, remove_user_space}, recv_msg, resolve_msg, sched::{reap_exited, scheduler::add_service, suspend_current_and_run_next}, send_msg, send_msg_and_wait
 };
 
 static mut MSG_QUEUE: Kernel2PMPort = Kernel2PMPort::default();
 
 pub fn yield_current_and_run_next() {
-    sys_yield();
+    suspend_current_and_run_next();
 }
 
 pub fn init_pm() {
     let pm_data = get_service_data_by_name("pm").unwrap();
-    let (token, recv_pa, send_pa) = new_service(pm_data);
+    let (token, recv_pa, send_pa) = new_service(&pm_data);
     unsafe {
         MSG_QUEUE.init(send_pa, recv_pa, yield_current_and_run_next);
         *(send_pa as *mut MsgQueue<Kernel2PM, 32>) = MsgQueue::default();
@@ -40,6 +40,7 @@ pub fn reply() {
         match msg {
             PM2Kernel::Remove { token } => {
                 log!("[kernel] Remove mm token: {:x}", token);
+                reap_exited(token);
                 remove_user_space(token)
             },
             PM2Kernel::Recycle { token }  => {
@@ -68,16 +69,25 @@ pub fn exec(pid: usize, new_token: usize) {
     });
 }
 
-pub fn waitpid(pid: usize, child_pid: isize) -> (isize, i32) {
-    if let (_, PM2Kernel::WaitPIDReply { result, exit_code }) =
+pub fn waitpid(pid: usize, child_pid: isize) -> (isize, i32, CpuTimes) {
+    if let (_, PM2Kernel::WaitPIDReply { result, exit_code, times }) =
         send_msg_and_wait!(Kernel2PM::WaitPID { pid, child_pid })
     {
-        (result, exit_code)
+        (result, exit_code, times)
     } else {
         panic!("Waitpid failed");
     }
 }
 
-pub fn exit(pid: usize, exit_code: i32) {
-    send_msg!(Kernel2PM::Exit { pid, exit_code });
+/// Ask PM for the process owning the user space `token`, if it may be killed when out of memory
+pub fn oom_victim(token: usize) -> Option<usize> {
+    if let (_, PM2Kernel::OomVictimReply { pid }) = send_msg_and_wait!(Kernel2PM::OomVictim { token }) {
+        usize::try_from(pid).ok()
+    } else {
+        panic!("OOM victim failed");
+    }
+}
+
+pub fn exit(pid: usize, exit_code: i32, times: CpuTimes) {
+    send_msg!(Kernel2PM::Exit { pid, exit_code, times });
 }
@@ -4,10 +4,10 @@
 // LICENSE file in the root directory of this source tree.
 
 use allocator::StackAllocator;
-use ksync::UPSafeCell;
+use ksync::SpinLock;
 use lazy_static::lazy_static;
 
-use crate::mm::{MapPermission, VirtAddr};
+use crate::mm::{MapPermission, MemoryError, VirtAddr};
 use crate::mm::KERNEL_SPACE;
 use crate::{log, println};
 use crate::config::*;
@@ -19,29 +19,43 @@ pub struct KernelStack {
 }
 
 lazy_static! {
-    static ref KERNEL_STACK_ALLOCATOR: UPSafeCell<StackAllocator> = unsafe {
-        UPSafeCell::new(StackAllocator::new(0, KERNEL_STACK_NUM))
-    };
+    static ref KERNEL_STACK_ALLOCATOR: SpinLock<StackAllocator> =
+        SpinLock::new(StackAllocator::new(0, KERNEL_STACK_NUM));
 }
 
+/// Get the top and bottom of a kernel stack, with an unmapped guard page below each stack
 fn get_kernel_stack_addr(id: usize) -> (usize, usize) {
     let top = TRAMPOLINE - id * (KERNEL_STACK_SIZE + PAGE_SIZE);
     let bottom = top - KERNEL_STACK_SIZE;
     (top, bottom)
 }
 
+/// Find the kernel stack whose guard page holds the address, and get its top and bottom
+///
+/// This is the check made by `__kerneltrap` in `kernel_trap.S` on the stack pointer.
+pub fn guarded_kernel_stack(addr: usize) -> Option<(usize, usize)> {
+    let offset = TRAMPOLINE.checked_sub(addr)?;
+    let id = offset / (KERNEL_STACK_SIZE + PAGE_SIZE);
+    if id >= KERNEL_STACK_NUM || offset % (KERNEL_STACK_SIZE + PAGE_SIZE) <= KERNEL_STACK_SIZE {
+        return None;
+    }
+    Some(get_kernel_stack_addr(id))
+}
+
 impl KernelStack {
-    pub fn new_process() -> Self {
-        let id = KERNEL_STACK_ALLOCATOR.borrow_mut().alloc().unwrap();
+    pub fn new_process() -> Result<Self, MemoryError> {
+        let id = KERNEL_STACK_ALLOCATOR.lock().alloc().ok_or(MemoryError::OutOfMemory)?;
         log!("[kernel] allocate new kernel stack id: {}", id);
         let (top, bottom) = get_kernel_stack_addr(id);
         log!("[kernel] mapping kernel stack [{:#x}, {:#x})", bottom, top);
-        KERNEL_SPACE.borrow_mut().insert(
+        // Dropping the stack frees the id if mapping fails
+        let stack = Self { top, id };
+        KERNEL_SPACE.lock().insert(
             bottom.into(),
             top.into(),
             MapPermission::R | MapPermission::W,
-        );
-        Self { top, id }
+        )?;
+        Ok(stack)
     }
     
     pub fn get_top(&self) -> usize {
@@ -59,7 +73,7 @@ impl Drop for KernelStack {
             "[kernel] unmapping kernel stack [{:#x}, {:#x})",
             bottom, self.top);
         let start_va: VirtAddr = bottom.into();
-        KERNEL_SPACE.borrow_mut().remove(start_va.into());
-        KERNEL_STACK_ALLOCATOR.borrow_mut().dealloc(self.id);
+        KERNEL_SPACE.lock().remove(start_va.into());
+        KERNEL_STACK_ALLOCATOR.lock().dealloc(self.id);
     }
 }
\ No newline at end of file
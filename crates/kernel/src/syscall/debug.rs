@@ -0,0 +1,16 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+// Debugging syscalls, numbered from 2000
+
+use abi::heap::BuddyStats;
+
+use crate::{allocator::heap_stats, mm::copy_to_user, sched::proc::current_user_token};
+
+/// Get the usage of the kernel heap
+pub fn sys_heap_stats(stats: *mut BuddyStats) -> isize {
+    try_user!(copy_to_user(current_user_token(), stats, &heap_stats()));
+    0
+}
@@ -0,0 +1,34 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+// Error numbers of the syscalls, which return them negated, as in Linux
+
+use crate::mm::MemoryError;
+
+pub const EPERM: isize = 1;
+pub const ESRCH: isize = 3;
+pub const EAGAIN: isize = 11;
+pub const ENOMEM: isize = 12;
+pub const EFAULT: isize = 14;
+pub const EBUSY: isize = 16;
+pub const EINVAL: isize = 22;
+
+/// Error number of a failed access to the user memory
+pub fn memory_errno(err: MemoryError) -> isize {
+    match err {
+        MemoryError::OutOfMemory => ENOMEM,
+        _ => EFAULT,
+    }
+}
+
+/// Unwrap the result of an access to the user memory, or fail the syscall with its error number
+macro_rules! try_user {
+    ($result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(err) => return -$crate::syscall::errno::memory_errno(err),
+        }
+    };
+}
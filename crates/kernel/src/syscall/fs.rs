@@ -3,7 +3,13 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
-use crate::{mm::translated_byte_buffer, print, sbi::console_getchar, sched::{proc::current_user_token, suspend_current_and_run_next}};
+use crate::{
+    fs::{close_file, get_file, open_file, OpenFlags},
+    mm::{translated_byte_buffer, translated_str},
+    print,
+    console::Stdin,
+    sched::proc::{current_pid, current_user_token},
+};
 
 const FD_STDIN: usize = 0;
 const FD_STDOUT: usize = 1;
@@ -13,36 +19,65 @@ const FD_STDERR: usize = 2;
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     match fd {
         FD_STDOUT | FD_STDERR => {
-            let buffers = translated_byte_buffer(current_user_token(), buf, len);
-            for buffer in buffers {
+            let buffers = try_user!(translated_byte_buffer(current_user_token(), buf, len));
+            for buffer in buffers.iter() {
                 print!("{}", core::str::from_utf8(buffer).unwrap());
             }
             len as isize
         }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
-        }
+        // Opened files are on read-only file systems
+        _ => -1,
     }
 }
 
 pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> isize {
     match fd {
         FD_STDIN => {
-            let ch = loop {
-                let input = console_getchar();
-                if input == 0 {
-                    suspend_current_and_run_next();
-                    continue;
-                } else {
-                    break input;
-                }
-            } as u8;
-            let mut buffers = translated_byte_buffer(current_user_token(), buf, len);
-            unsafe { buffers[0].as_mut_ptr().write_volatile(ch); }
-            1
+            // Only fill the first page of the buffer, as the reader may block in between, which
+            // the page stays pinned for
+            match try_user!(translated_byte_buffer(current_user_token(), buf, len)).first_mut() {
+                Some(buffer) => Stdin.read(buffer) as isize,
+                None => 0,
+            }
         }
         _ => {
-            panic!("Unsupported fd in sys_read!");
+            let Some(file) = get_file(current_pid(), fd) else {
+                return -1;
+            };
+            let mut buffers = try_user!(translated_byte_buffer(current_user_token(), buf, len));
+            let mut file = file.lock();
+            if file.is_dir() {
+                return -1;
+            }
+            let mut total = 0;
+            for buffer in buffers.iter_mut() {
+                let count = file.read(buffer);
+                total += count;
+                if count < buffer.len() {
+                    break;
+                }
+            }
+            total as isize
         }
     }
 }
+
+/// Open a file by its absolute path, return the file descriptor or -1 on failure
+pub fn sys_open(path: *const u8, flags: u32) -> isize {
+    let path = try_user!(translated_str(current_user_token(), path));
+    let Some(flags) = OpenFlags::from_bits(flags) else {
+        return -1;
+    };
+    match open_file(current_pid(), &path, flags) {
+        Some(fd) => fd as isize,
+        None => -1,
+    }
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    if close_file(current_pid(), fd) {
+        0
+    } else {
+        -1
+    }
+}
@@ -0,0 +1,37 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use crate::{
+    sched::proc::{current_pid, current_user_token},
+    trap::{ack_service_irq, attach_service_irq, wait_service_irq},
+};
+
+/// Attach the calling service to an interrupt source
+///
+/// Only services, which run with pid 0, may drive devices. The interrupt number is used as the
+/// capability afterwards, which is checked against the service that attached it.
+pub fn sys_irq_attach(irq: usize) -> isize {
+    if current_pid() != 0 || !attach_service_irq(irq, current_user_token()) {
+        return -1;
+    }
+    0
+}
+
+/// Block until the interrupt fires, return the number of interrupts since the last wait
+pub fn sys_irq_wait(irq: usize) -> isize {
+    match wait_service_irq(irq, current_user_token()) {
+        Some(count) => count as isize,
+        None => -1,
+    }
+}
+
+/// Unmask the interrupt after the device has been served
+pub fn sys_irq_ack(irq: usize) -> isize {
+    if ack_service_irq(irq, current_user_token()) {
+        0
+    } else {
+        -1
+    }
+}
@@ -3,12 +3,83 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
-use crate::{mm::change_program_brk, sched::proc::current_pid};
+use crate::{
+    config::OOM_KILLER,
+    log,
+    mm::{
+        change_program_brk, map_shared_memory, translated_str, unmap_shared_memory,
+        user_spaces_by_size, MapPermission, MemoryError,
+    },
+    sched::{proc::current_user_token, scheduler::kill_process},
+    services::pm::oom_victim,
+};
+
+use super::errno::{EINVAL, ENOMEM};
+
+/// Bits of the protection of a shared memory mapping, as `PROT_*` of `mmap`
+const PROT_READ: usize = 1 << 0;
+const PROT_WRITE: usize = 1 << 1;
+const PROT_EXEC: usize = 1 << 2;
 
 pub fn sys_sbrk(size: i32) -> isize {
-    if let Some(old_brk) = change_program_brk(current_pid(), size) {
-        old_brk as isize
-    } else {
-        -1
+    match change_program_brk(current_user_token(), size) {
+        Ok(old_brk) => old_brk as isize,
+        Err(MemoryError::OutOfMemory) => {
+            out_of_memory();
+            -ENOMEM
+        }
+        Err(_) => -1,
+    }
+}
+
+/// Map the shared memory object named by the string at `name`, creating it with `len` bytes if
+/// there is none, and return the address of the mapping
+pub fn sys_shm_map(name: *const u8, len: usize, prot: usize) -> isize {
+    if prot == 0 || prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+        return -EINVAL;
+    }
+    let mut permission = MapPermission::empty();
+    if prot & PROT_READ != 0 {
+        permission |= MapPermission::R;
+    }
+    if prot & PROT_WRITE != 0 {
+        permission |= MapPermission::W;
+    }
+    if prot & PROT_EXEC != 0 {
+        permission |= MapPermission::X;
+    }
+    let token = current_user_token();
+    let name = try_user!(translated_str(token, name));
+    match map_shared_memory(token, &name, len, permission) {
+        Ok(va) => va as isize,
+        Err(MemoryError::OutOfMemory) => {
+            out_of_memory();
+            -ENOMEM
+        }
+        Err(_) => -EINVAL,
+    }
+}
+
+/// Unmap the shared memory object mapped at `addr`
+pub fn sys_shm_unmap(addr: usize) -> isize {
+    match unmap_shared_memory(current_user_token(), addr) {
+        Ok(()) => 0,
+        Err(_) => -EINVAL,
+    }
+}
+
+/// Kill a victim, whose memory is freed once it exits, and tell if there is one
+///
+/// The victim is the process with the most frames among those PM allows to kill, which are all
+/// but init. It may be the caller itself, and it is picked again as long as it has not exited,
+/// so that no more processes are killed than needed.
+pub fn out_of_memory() -> bool {
+    if !OOM_KILLER {
+        return false;
     }
+    let Some(pid) = user_spaces_by_size().into_iter().find_map(oom_victim) else {
+        return false;
+    };
+    log!("[kernel] Out of memory, killing process {}", pid);
+    kill_process(pid)
 }
@@ -3,40 +3,80 @@
 
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
+const SYSCALL_OPEN: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_FUTEX: usize = 98;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_SETPRIORITY: usize = 140;
+const SYSCALL_GETPRIORITY: usize = 141;
+const SYSCALL_TIMES: usize = 153;
+const SYSCALL_GETRUSAGE: usize = 165;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
 const SYSCALL_SBRK: usize = 214;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_SCHED_SETATTR: usize = 274;
+const SYSCALL_SCHED_GETATTR: usize = 275;
+const SYSCALL_IRQ_ATTACH: usize = 1000;
+const SYSCALL_IRQ_WAIT: usize = 1001;
+const SYSCALL_IRQ_ACK: usize = 1002;
+const SYSCALL_HEAP_STATS: usize = 2000;
+const SYSCALL_SHM_MAP: usize = 3000;
+const SYSCALL_SHM_UNMAP: usize = 3001;
 
+#[macro_use]
+mod errno;
+mod debug;
 mod fs;
+mod irq;
 mod process;
 mod mem;
+mod sync;
+mod time;
 
-pub use process::sys_yield;
-use fs::{sys_read, sys_write};
-use self::{mem::sys_sbrk, process::*};
+use debug::sys_heap_stats;
+use fs::{sys_close, sys_open, sys_read, sys_write};
+use irq::{sys_irq_ack, sys_irq_attach, sys_irq_wait};
+pub use mem::out_of_memory;
+use self::{mem::{sys_sbrk, sys_shm_map, sys_shm_unmap}, process::*, sync::sys_futex, time::{sys_clock_gettime, sys_getrusage, sys_times}};
 
 /// Syscall handler
 /// 
 /// This function will dispatch the syscall to the corresponding handler.
 pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
     match syscall_id {
+        SYSCALL_OPEN => sys_open(args[0] as *const u8, args[1] as u32),
+        SYSCALL_CLOSE => sys_close(args[0]),
         SYSCALL_READ => sys_read(args[0], args[1] as *mut u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
+        SYSCALL_FUTEX => sys_futex(args[0] as *mut u32, args[1], args[2]),
+        SYSCALL_CLOCK_GETTIME => sys_clock_gettime(args[0], args[1] as *mut _),
         SYSCALL_YIELD => sys_yield(),
+        SYSCALL_SETPRIORITY => sys_setpriority(args[0], args[1], args[2] as i32),
+        SYSCALL_GETPRIORITY => sys_getpriority(args[0], args[1]),
+        SYSCALL_TIMES => sys_times(args[0] as *mut _),
+        SYSCALL_GETRUSAGE => sys_getrusage(args[0] as isize, args[1] as *mut _),
         SYSCALL_GET_TIME => sys_get_time(),
         SYSCALL_GETPID => sys_getpid(),
         SYSCALL_SBRK => sys_sbrk(args[0] as i32),
         SYSCALL_FORK => sys_fork(),
         SYSCALL_EXEC => sys_exec(args[0] as *const u8),
         SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_SCHED_SETATTR => sys_sched_setattr(args[0], args[1] as *const _, args[2]),
+        SYSCALL_SCHED_GETATTR => sys_sched_getattr(args[0], args[1] as *mut _, args[2]),
+        SYSCALL_IRQ_ATTACH => sys_irq_attach(args[0]),
+        SYSCALL_IRQ_WAIT => sys_irq_wait(args[0]),
+        SYSCALL_IRQ_ACK => sys_irq_ack(args[0]),
+        SYSCALL_HEAP_STATS => sys_heap_stats(args[0] as *mut _),
+        SYSCALL_SHM_MAP => sys_shm_map(args[0] as *const u8, args[1], args[2]),
+        SYSCALL_SHM_UNMAP => sys_shm_unmap(args[0]),
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
     }
 }
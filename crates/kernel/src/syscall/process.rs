@@ -1,20 +1,29 @@
 // Copyright (c) 2024 Conless Pan
 
+use abi::sched::{SchedAttr, SCHED_DEADLINE, SCHED_NORMAL};
 use alloc::sync::Arc;
 use ksync::UPSafeCell;
 
-use crate::loader::get_app_data_by_name;
+use crate::fs::fork_files;
+use crate::loader::get_app_data_by_path;
 use crate::mm::{fork_user_space, get_trap_ctx, new_user_space};
-use crate::sched::proc::{current_pid, current_user_token, set_user_token};
-use crate::sched::scheduler::add_process;
+use crate::sched::proc::{current_pid, current_task, current_user_token, set_user_token};
+use crate::sched::policy::{DeadlineParams, NICE_MAX, NICE_MIN};
+use crate::sched::scheduler::{
+    add_process, complete_current_job, get_deadline, get_priority, is_self_or_child, set_deadline,
+    set_priority, DeadlineError,
+};
 use crate::sched::{exit_current_and_run_next, suspend_current_and_run_next};
 use crate::services::pm::{exec, fork, waitpid};
+use super::errno::{EBUSY, EINVAL, ENOMEM, EPERM, ESRCH};
+use super::mem::out_of_memory;
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 //
+use crate::config::CLOCK_FREQ;
 use crate::trap::get_time_ms;
 use crate::{
-    mm::{translated_ptr, translated_str},
+    mm::{copy_from_user, copy_to_user, translated_str},
     log,
 };
 
@@ -27,8 +36,10 @@ pub fn sys_exit(exit_code: i32) -> ! {
     unreachable!()
 }
 
+/// Give up the CPU, which ends the current job of a real-time thread
 pub fn sys_yield() -> isize {
     // log!("[kernel] Yield to next task");
+    complete_current_job();
     suspend_current_and_run_next();
     0
 }
@@ -42,7 +53,10 @@ pub fn sys_getpid() -> isize {
 }
 
 pub fn sys_fork() -> isize {
-    let new_token = fork_user_space(current_user_token());
+    let Ok(new_token) = fork_user_space(current_user_token()) else {
+        out_of_memory();
+        return -ENOMEM;
+    };
     let new_task_pid = fork(current_pid(), new_token);
     log!(
         "[kernel] Process {} finish forking a child process {}",
@@ -50,17 +64,25 @@ pub fn sys_fork() -> isize {
         new_task_pid);
     let new_task_trap_ctx = get_trap_ctx(new_token);
     new_task_trap_ctx.regs[10] = 0; // fork return 0 in child process
-    add_process(new_task_pid, new_token);
+    fork_files(current_pid(), new_task_pid);
+    add_process(new_task_pid, current_pid(), new_token);
+    // The child inherits the priority of the parent
+    if let Some(nice) = get_priority(current_pid()) {
+        set_priority(new_task_pid, nice);
+    }
     new_task_pid as isize
 }
 
 pub fn sys_exec(path: *const u8) -> isize {
     let current_pid = current_pid();
     let current_token = current_user_token();
-    let path = translated_str(current_token, path);
+    let path = try_user!(translated_str(current_token, path));
     log!("[kernel] Process {} exec {:?}", current_pid, path);
-    if let Some(app_data) = get_app_data_by_name(path.as_str()) {
-        let new_token = new_user_space(app_data);
+    if let Some(app_data) = get_app_data_by_path(path.as_str()) {
+        let Ok(new_token) = new_user_space(&app_data) else {
+            out_of_memory();
+            return -ENOMEM;
+        };
         exec(current_pid, new_token);
         set_user_token(new_token);
         0
@@ -70,9 +92,138 @@ pub fn sys_exec(path: *const u8) -> isize {
 }
 
 pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
-    let (result, exit_code) = waitpid(current_pid(), pid);
+    let (result, exit_code, times) = waitpid(current_pid(), pid);
     if result >= 0 {
-        unsafe { *translated_ptr(current_user_token(), exit_code_ptr) = exit_code };
+        current_task().unwrap().lock().children_times += times;
+        try_user!(copy_to_user(current_user_token(), exit_code_ptr, &exit_code));
     }
     result
 }
+
+const PRIO_PROCESS: usize = 0;
+
+/// Resolve the target of `setpriority` and `getpriority`, where 0 is the calling process
+fn priority_target(which: usize, who: usize) -> Option<usize> {
+    if which != PRIO_PROCESS {
+        return None;
+    }
+    match who {
+        0 => Some(current_pid()),
+        pid => Some(pid),
+    }
+}
+
+/// Whether the calling process may give the process `pid` the nice value `nice`
+///
+/// A process may only change itself and its children, and only init may raise a priority, so
+/// that no process can starve the others.
+fn may_set_priority(pid: usize, nice: i32) -> bool {
+    let caller = current_pid();
+    caller == 1
+        || is_self_or_child(caller, pid)
+            && get_priority(pid).is_some_and(|old| nice.clamp(NICE_MIN, NICE_MAX) >= old)
+}
+
+/// Set the nice value of a process, services have a fixed priority and cannot be changed
+pub fn sys_setpriority(which: usize, who: usize, nice: i32) -> isize {
+    match priority_target(which, who) {
+        Some(pid) if pid != 0 && may_set_priority(pid, nice) && set_priority(pid, nice) => 0,
+        _ => -1,
+    }
+}
+
+/// Get the nice value of a process as `20 - nice`, so that a valid result is always positive
+pub fn sys_getpriority(which: usize, who: usize) -> isize {
+    match priority_target(which, who).filter(|&pid| pid != 0).and_then(get_priority) {
+        Some(nice) => 20 - nice as isize,
+        None => -1,
+    }
+}
+
+const NSEC_PER_SEC: u128 = 1_000_000_000;
+
+fn ns_to_cycles(ns: u64) -> usize {
+    (ns as u128 * CLOCK_FREQ as u128 / NSEC_PER_SEC) as usize
+}
+
+fn cycles_to_ns(cycles: usize) -> u64 {
+    (cycles as u128 * NSEC_PER_SEC / CLOCK_FREQ as u128) as u64
+}
+
+/// Set the scheduling class of a process, where 0 is the calling process
+///
+/// `SCHED_DEADLINE` makes it a real-time thread with the runtime, deadline and period, where a
+/// zero period is the same as the deadline. It fails with `EBUSY` if the real-time threads would
+/// reserve more than the harts can serve. `SCHED_NORMAL` makes it a normal thread with the nice
+/// value. As with `setpriority`, only the process itself and its parent may change it, and only
+/// init may lower the nice value.
+pub fn sys_sched_setattr(pid: usize, attr: *const SchedAttr, flags: usize) -> isize {
+    let Some(pid) = priority_target(PRIO_PROCESS, pid).filter(|&pid| pid != 0) else {
+        return -EINVAL;
+    };
+    if flags != 0 {
+        return -EINVAL;
+    }
+    let caller = current_pid();
+    if caller != 1 && !is_self_or_child(caller, pid) {
+        return -EPERM;
+    }
+    let attr = try_user!(copy_from_user(current_user_token(), attr));
+    if (attr.size as usize) < core::mem::size_of::<SchedAttr>() {
+        return -EINVAL;
+    }
+    let result = match attr.policy {
+        SCHED_NORMAL if !may_set_priority(pid, attr.nice) => return -EPERM,
+        SCHED_NORMAL => set_deadline(pid, None).map(|()| {
+            set_priority(pid, attr.nice);
+        }),
+        SCHED_DEADLINE => {
+            let period = if attr.period == 0 { attr.deadline } else { attr.period };
+            let params = DeadlineParams {
+                runtime: ns_to_cycles(attr.runtime),
+                deadline: ns_to_cycles(attr.deadline),
+                period: ns_to_cycles(period),
+            };
+            if !params.is_valid() {
+                return -EINVAL;
+            }
+            set_deadline(pid, Some(params))
+        }
+        _ => return -EINVAL,
+    };
+    match result {
+        Ok(()) => 0,
+        Err(DeadlineError::NoProcess) => -ESRCH,
+        Err(DeadlineError::Overloaded) => -EBUSY,
+    }
+}
+
+/// Get the scheduling class of a process into `attr`, which must hold at least `size` bytes
+pub fn sys_sched_getattr(pid: usize, attr: *mut SchedAttr, size: usize) -> isize {
+    let Some(pid) = priority_target(PRIO_PROCESS, pid).filter(|&pid| pid != 0) else {
+        return -EINVAL;
+    };
+    if size < core::mem::size_of::<SchedAttr>() {
+        return -EINVAL;
+    }
+    let (Some(nice), Some(deadline)) = (get_priority(pid), get_deadline(pid)) else {
+        return -ESRCH;
+    };
+    let (policy, params, overruns) = match deadline {
+        Some((params, overruns)) => (SCHED_DEADLINE, params, overruns),
+        None => (SCHED_NORMAL, DeadlineParams { runtime: 0, deadline: 0, period: 0 }, 0),
+    };
+    let value = SchedAttr {
+        size: core::mem::size_of::<SchedAttr>() as u32,
+        policy,
+        flags: 0,
+        nice,
+        priority: 0,
+        runtime: cycles_to_ns(params.runtime),
+        deadline: cycles_to_ns(params.deadline),
+        period: cycles_to_ns(params.period),
+        overruns: overruns as u64,
+    };
+    try_user!(copy_to_user(current_user_token(), attr, &value));
+    0
+}
@@ -0,0 +1,55 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use super::errno::{EAGAIN, EINVAL};
+use crate::{
+    mm::{shared_paddr, translated_byte_buffer},
+    sched::{
+        futex::{futex_wait, futex_wake, FutexKey},
+        proc::current_user_token,
+    },
+};
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+/// The futex is only used by the threads of the process, even if its word is in shared memory
+const FUTEX_PRIVATE_FLAG: usize = 128;
+
+/// Key of the futex word at `va`, shared with the other processes mapping it unless `private`
+fn futex_key(token: usize, va: usize, private: bool) -> FutexKey {
+    match shared_paddr(token, va) {
+        Some(pa) if !private => FutexKey::Shared(pa),
+        _ => FutexKey::Private(token, va),
+    }
+}
+
+/// Wait on or wake up the futex word at `uaddr`
+///
+/// `FUTEX_WAIT` blocks while the word holds `val`, and fails with `EAGAIN` if it does not.
+/// `FUTEX_WAKE` wakes up at most `val` waiters and returns their number.
+pub fn sys_futex(uaddr: *mut u32, op: usize, val: usize) -> isize {
+    if uaddr as usize % core::mem::align_of::<u32>() != 0 {
+        return -EINVAL;
+    }
+    let token = current_user_token();
+    let key = futex_key(token, uaddr as usize, op & FUTEX_PRIVATE_FLAG != 0);
+    match op & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => {
+            // The page of the word stays pinned until the thread is woken up
+            let buffer = try_user!(translated_byte_buffer(
+                token,
+                uaddr as *const u8,
+                core::mem::size_of::<u32>()
+            ));
+            if futex_wait(key, buffer[0].as_ptr() as usize, val as u32) {
+                0
+            } else {
+                -EAGAIN
+            }
+        }
+        FUTEX_WAKE => futex_wake(key, val) as isize,
+        _ => -EINVAL,
+    }
+}
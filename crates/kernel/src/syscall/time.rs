@@ -0,0 +1,81 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use abi::time::{RUsage, TimeSpec, TimeVal, Tms, CLK_TCK};
+use drivers::rtc::RTC;
+
+use crate::{
+    config::CLOCK_FREQ,
+    mm::copy_to_user,
+    sched::proc::{current_task, current_user_token},
+    trap::{get_time, get_time_ns},
+};
+
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_MONOTONIC: usize = 1;
+
+const USEC_PER_SEC: usize = 1_000_000;
+
+const RUSAGE_SELF: isize = 0;
+const RUSAGE_CHILDREN: isize = -1;
+
+/// Get the time of the clock, which is the wall-clock time from the RTC for `CLOCK_REALTIME`,
+/// or the time since boot for `CLOCK_MONOTONIC`
+pub fn sys_clock_gettime(clock_id: usize, ts: *mut TimeSpec) -> isize {
+    let nanos = match clock_id {
+        CLOCK_REALTIME => RTC.read_time() as usize,
+        CLOCK_MONOTONIC => get_time_ns(),
+        _ => return -1,
+    };
+    try_user!(copy_to_user(current_user_token(), ts, &TimeSpec::from_nanos(nanos)));
+    0
+}
+
+fn cycles_to_ticks(cycles: usize) -> usize {
+    cycles / (CLOCK_FREQ / CLK_TCK)
+}
+
+fn cycles_to_timeval(cycles: usize) -> TimeVal {
+    TimeVal {
+        sec: cycles / CLOCK_FREQ,
+        usec: cycles % CLOCK_FREQ * USEC_PER_SEC / CLOCK_FREQ,
+    }
+}
+
+/// Get the CPU times of the current process and its reaped children, and return the clock ticks
+/// since boot
+pub fn sys_times(tms: *mut Tms) -> isize {
+    let thread = current_task().unwrap();
+    let thread_info = thread.lock();
+    let (times, children_times) = (thread_info.times, thread_info.children_times);
+    drop(thread_info);
+    let value = Tms {
+        utime: cycles_to_ticks(times.user),
+        stime: cycles_to_ticks(times.system),
+        cutime: cycles_to_ticks(children_times.user),
+        cstime: cycles_to_ticks(children_times.system),
+    };
+    try_user!(copy_to_user(current_user_token(), tms, &value));
+    cycles_to_ticks(get_time()) as isize
+}
+
+/// Get the CPU times of the current process, or of its reaped children for `RUSAGE_CHILDREN`
+pub fn sys_getrusage(who: isize, usage: *mut RUsage) -> isize {
+    let thread = current_task().unwrap();
+    let thread_info = thread.lock();
+    let times = match who {
+        RUSAGE_SELF => thread_info.times,
+        RUSAGE_CHILDREN => thread_info.children_times,
+        _ => return -1,
+    };
+    drop(thread_info);
+    let value = RUsage {
+        utime: cycles_to_timeval(times.user),
+        stime: cycles_to_timeval(times.system),
+        ..RUsage::default()
+    };
+    try_user!(copy_to_user(current_user_token(), usage, &value));
+    0
+}
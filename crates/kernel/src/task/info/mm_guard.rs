@@ -4,14 +4,14 @@
 // LICENSE file in the root directory of this source tree.
 
 use crate::{
-    loader::get_app_data_by_name, log, mm::{new_user_space, remove_user_space}
+    loader::get_app_data_by_path, log, mm::{new_user_space, remove_user_space}
 };
 
 pub struct MMGuard(pub usize);
 
 impl MMGuard {
     pub fn from_name(app_name: &str) -> Option<Self> {
-        let result = get_app_data_by_name(app_name).map(|data| MMGuard(new_user_space(data)));
+        let result = get_app_data_by_path(app_name).map(|data| MMGuard(new_user_space(&data)));
         match &result {
             Some(guard) => log!("MMGuard from name: {} -> {:x}", app_name, guard.0),
             None => log!("MMGuard from name: {} -> None", app_name),
@@ -13,6 +13,8 @@ pub struct TrapContext {
     pub kernel_satp: usize,
     pub kernel_sp: usize,
     pub trap_handler: usize,
+    /// Id of the hart the thread runs on, loaded into `tp` when trapping into the kernel
+    pub hart_id: usize,
 }
 
 impl TrapContext {
@@ -35,6 +37,7 @@ impl TrapContext {
             kernel_satp: satp,
             kernel_sp,
             trap_handler,
+            hart_id: 0,
         };
         ctx.set_sp(sp);
         ctx
@@ -0,0 +1,140 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use drivers::plic::{supervisor_context, PLIC};
+use ksync::SpinLock;
+use lazy_static::lazy_static;
+
+use crate::{
+    config::PLIC_SOURCES,
+    log,
+    sched::{block_current_and_run_next, proc::hart_id, WaitQueue},
+};
+
+/// Priority given to all the attached interrupt sources
+const IRQ_PRIORITY: u32 = 1;
+
+/// An interrupt source handled by a user-space driver service
+///
+/// The source is masked when it fires, until the service acknowledges it after serving the device,
+/// so that a level-triggered device does not interrupt the kernel again before that.
+struct ServiceIrq {
+    /// Token of the service holding the capability
+    owner: usize,
+    /// Number of interrupts not yet seen by the service
+    pending: usize,
+    waiters: Arc<SpinLock<WaitQueue>>,
+}
+
+enum IrqHandler {
+    Kernel(fn()),
+    Service(ServiceIrq),
+}
+
+lazy_static! {
+    static ref IRQ_HANDLERS: SpinLock<BTreeMap<usize, IrqHandler>> =
+        SpinLock::new(BTreeMap::new());
+}
+
+/// Hart which all the external interrupts are routed to
+const IRQ_HART: usize = 0;
+
+/// Context of the PLIC for the kernel
+fn context() -> usize {
+    supervisor_context(IRQ_HART)
+}
+
+/// Deliver all the interrupt sources with a non-zero priority to the kernel
+pub fn init() {
+    PLIC.set_threshold(context(), 0);
+}
+
+fn attach(irq: usize, handler: IrqHandler) -> bool {
+    if irq == 0 || irq >= PLIC_SOURCES {
+        return false;
+    }
+    let mut handlers = IRQ_HANDLERS.lock();
+    if handlers.contains_key(&irq) {
+        return false;
+    }
+    handlers.insert(irq, handler);
+    PLIC.set_priority(irq, IRQ_PRIORITY);
+    PLIC.enable(context(), irq);
+    true
+}
+
+/// Attach a kernel handler to the interrupt source, return false if it is already attached
+pub fn register_irq_handler(irq: usize, handler: fn()) -> bool {
+    attach(irq, IrqHandler::Kernel(handler))
+}
+
+/// Grant the service with `token` the capability to handle the interrupt source
+pub fn attach_service_irq(irq: usize, token: usize) -> bool {
+    attach(
+        irq,
+        IrqHandler::Service(ServiceIrq {
+            owner: token,
+            pending: 0,
+            waiters: Arc::new(SpinLock::new(WaitQueue::default())),
+        }),
+    )
+}
+
+/// Wait until the interrupt source held by the service with `token` fires
+///
+/// Return the number of interrupts since the last wait, or `None` if the service does not hold
+/// the capability.
+pub fn wait_service_irq(irq: usize, token: usize) -> Option<usize> {
+    loop {
+        let mut handlers = IRQ_HANDLERS.lock();
+        let Some(IrqHandler::Service(service)) = handlers.get_mut(&irq) else {
+            return None;
+        };
+        if service.owner != token {
+            return None;
+        }
+        if service.pending > 0 {
+            return Some(core::mem::take(&mut service.pending));
+        }
+        let waiters = service.waiters.clone();
+        block_current_and_run_next(&waiters, handlers);
+    }
+}
+
+/// Unmask the interrupt source held by the service with `token`
+pub fn ack_service_irq(irq: usize, token: usize) -> bool {
+    match IRQ_HANDLERS.lock().get(&irq) {
+        Some(IrqHandler::Service(service)) if service.owner == token => {
+            PLIC.enable(context(), irq);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Claim and dispatch the interrupts pending in the PLIC
+pub fn handle_external_interrupt() {
+    if hart_id() != IRQ_HART {
+        return;
+    }
+    while let Some(irq) = PLIC.claim(context()) {
+        let mut handlers = IRQ_HANDLERS.lock();
+        match handlers.get_mut(&irq) {
+            Some(IrqHandler::Kernel(handler)) => {
+                let handler = *handler;
+                drop(handlers);
+                handler();
+            }
+            Some(IrqHandler::Service(service)) => {
+                PLIC.disable(context(), irq);
+                service.pending += 1;
+                service.waiters.lock().wake_all();
+            }
+            None => log!("[kernel] Unexpected external interrupt {}", irq),
+        }
+        PLIC.complete(context(), irq);
+    }
+}
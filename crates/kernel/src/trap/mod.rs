@@ -4,37 +4,70 @@
 // LICENSE file in the root directory of this source tree.
 
 mod context;
+mod irq;
+mod overflow;
 mod timer;
 
 pub use context::TrapContext;
-pub use timer::{get_time, get_time_ms};
+pub use irq::{ack_service_irq, attach_service_irq, register_irq_handler, wait_service_irq};
+pub use timer::{get_time, get_time_ms, get_time_ns};
 
 use riscv::register::scause::{Exception, Interrupt, Trap};
 use riscv::register::utvec::TrapMode;
-use riscv::register::{scause, sie, stval, stvec};
+use riscv::register::{scause, sepc, sie, sip, sstatus, stval, stvec};
 
-use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
-use crate::sched::proc::{current_pid, current_trap_ctx, current_user_token};
-use crate::sched::{exit_current_and_run_next, suspend_current_and_run_next};
-use crate::syscall::syscall;
+use crate::config::{KERNEL_STACK_NUM, KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT};
+use crate::sbi::set_timer;
+use crate::sched::preempt::preemptible;
+use crate::sched::proc::{current_pid, current_task, current_trap_ctx, current_user_token, hart_id};
+use crate::sched::{
+    exit_current_and_run_next, suspend_current_and_run_next, tick_current_and_run_next,
+};
+use crate::mm::{handle_page_fault, MapPermission, MemoryError};
+use crate::syscall::{out_of_memory, syscall};
 use crate::{log, println};
 use core::arch::{asm, global_asm};
 
+use self::irq::handle_external_interrupt;
+use self::overflow::set_overflow_stack;
 use self::timer::set_next_interrupt;
 
+/// Exit code of a killed process, as if killed by `SIGKILL`
+const KILLED_EXIT_CODE: i32 = -9;
+
 global_asm!(include_str!("trap.S"));
+global_asm!(
+    include_str!("kernel_trap.S"),
+    trampoline = const TRAMPOLINE,
+    stack_size = const KERNEL_STACK_SIZE,
+    stack_stride = const KERNEL_STACK_SIZE + PAGE_SIZE,
+    stacks_size = const KERNEL_STACK_NUM * (KERNEL_STACK_SIZE + PAGE_SIZE),
+);
 
 /// Initialize trap handling
 ///
 /// Set the trap entry to `__alltraps` in `trap.S`
 pub fn init() {
-    set_kernel_trap_entry()
+    set_kernel_trap_entry();
+    irq::init();
+}
+
+/// Initialize trap handling on a secondary hart
+pub fn init_hart() {
+    set_kernel_trap_entry();
+    enable_timer_interrupt();
+    enable_external_interrupt();
+    enable_soft_interrupt();
 }
 
 fn set_kernel_trap_entry() {
+    extern "C" {
+        fn __kerneltrap();
+    }
     unsafe {
-        stvec::write(trap_from_kernel as usize, TrapMode::Direct);
+        stvec::write(__kerneltrap as usize, TrapMode::Direct);
     }
+    set_overflow_stack();
 }
 
 fn set_user_trap_entry() {
@@ -43,9 +76,31 @@ fn set_user_trap_entry() {
     }
 }
 
+/// Handler of the traps taken in the kernel, called by `__kerneltrap` in `kernel_trap.S`
+///
+/// Interrupts are only enabled in the kernel while a thread runs a syscall, so the trap comes
+/// from a thread context and the thread can be preempted here unless preemption is disabled.
 #[no_mangle]
-pub fn trap_from_kernel() -> ! {
-    panic!("a trap from kernel!");
+pub extern "C" fn kernel_trap_handler() {
+    let scause = scause::read();
+    match scause.cause() {
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            set_next_interrupt();
+            if preemptible() && current_task().is_some_and(|thread| thread.lock().pid != 0) {
+                tick_current_and_run_next();
+            }
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => handle_external_interrupt(),
+        Trap::Interrupt(Interrupt::SupervisorSoft) => clear_soft_interrupt(),
+        _ => {
+            panic!(
+                "Unsupported trap from kernel {:?}, stval = {:#x}, sepc = {:#x}!",
+                scause.cause(),
+                stval::read(),
+                sepc::read()
+            );
+        }
+    }
 }
 
 pub fn enable_timer_interrupt() {
@@ -54,6 +109,44 @@ pub fn enable_timer_interrupt() {
     }
 }
 
+pub fn enable_external_interrupt() {
+    unsafe {
+        sie::set_sext();
+    }
+}
+
+/// Enable the inter-processor interrupts, which wake up an idle hart
+pub fn enable_soft_interrupt() {
+    unsafe {
+        sie::set_ssoft();
+    }
+}
+
+/// Clear the pending inter-processor interrupt, which carries no message
+pub fn clear_soft_interrupt() {
+    unsafe {
+        sip::clear_ssoft();
+    }
+}
+
+/// Handle the pending external interrupts while interrupts are disabled in the kernel
+fn poll_external_interrupt() {
+    if sip::read().sext() {
+        handle_external_interrupt();
+    }
+}
+
+/// Sleep until an interrupt is pending, and handle it
+///
+/// Interrupts stay disabled in the kernel, but `wfi` wakes up on any source enabled in `sie`. The
+/// timer is only armed for `wakeup`, as there is no thread on this hart to preempt.
+pub fn wait_for_interrupt(wakeup: Option<usize>) {
+    set_timer(wakeup.unwrap_or(usize::MAX));
+    unsafe { riscv::asm::wfi() };
+    clear_soft_interrupt();
+    poll_external_interrupt();
+}
+
 fn resolve_message() {
     crate::services::reply_services()
 }
@@ -64,14 +157,18 @@ fn resolve_message() {
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    current_task().unwrap().lock().charge_user();
     let scause = scause::read();
     let stval = stval::read();
     match scause.cause() {
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             if current_pid() != 0 {
-                suspend_current_and_run_next();
+                tick_current_and_run_next();
             }
         }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => handle_external_interrupt(),
+        // A wakeup sent to this hart while it was idle, which has been handled already
+        Trap::Interrupt(Interrupt::SupervisorSoft) => clear_soft_interrupt(),
         Trap::Exception(Exception::UserEnvCall) => {
             // log!(
             //     "[kernel] receive syscall {:?}.",
@@ -79,6 +176,8 @@ pub fn trap_handler() -> ! {
             // );
             let mut ctx = current_trap_ctx();
             ctx.pc += 4;
+            // A syscall may take long, so let it be interrupted and preempted
+            unsafe { sstatus::set_sie() };
             let result = syscall(ctx.regs[17], [ctx.regs[10], ctx.regs[11], ctx.regs[12]]) as usize;
             ctx = current_trap_ctx();
             ctx.regs[10] = result;
@@ -87,10 +186,38 @@ pub fn trap_handler() -> ! {
             //     ctx.regs[17], ctx.regs[10]
             // );
         }
-        Trap::Exception(Exception::StoreFault)
-        | Trap::Exception(Exception::StorePageFault)
-        | Trap::Exception(Exception::LoadFault)
-        | Trap::Exception(Exception::LoadPageFault) => {
+        Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::InstructionPageFault) => {
+            let access = match scause.cause() {
+                Trap::Exception(Exception::StorePageFault) => MapPermission::W,
+                Trap::Exception(Exception::LoadPageFault) => MapPermission::R,
+                _ => MapPermission::X,
+            };
+            // Reading the page back from the swap area may take long
+            unsafe { sstatus::set_sie() };
+            match handle_page_fault(current_user_token(), stval, access | MapPermission::U) {
+                Ok(true) => {}
+                Ok(false) => {
+                    println!("[kernel] PageFault in application, kernel killed it.");
+                    exit_current_and_run_next(-2);
+                }
+                Err(MemoryError::StackOverflow) => {
+                    println!(
+                        "[kernel] Stack overflow in application at {:#x}, kernel killed it.",
+                        stval
+                    );
+                    exit_current_and_run_next(-2);
+                }
+                // Let the victim of the OOM killer, which may be this process, exit before retrying
+                Err(MemoryError::OutOfMemory) if out_of_memory() => suspend_current_and_run_next(),
+                Err(err) => {
+                    println!("[kernel] {:?} in application, kernel killed it.", err);
+                    exit_current_and_run_next(KILLED_EXIT_CODE);
+                }
+            }
+        }
+        Trap::Exception(Exception::StoreFault) | Trap::Exception(Exception::LoadFault) => {
             println!("[kernel] PageFault in application, kernel killed it.");
             exit_current_and_run_next(-2);
         }
@@ -111,9 +238,20 @@ pub fn trap_handler() -> ! {
 
 #[no_mangle]
 pub fn trap_return() -> ! {
-    set_user_trap_entry();
+    if current_task().unwrap().lock().killed {
+        log!("[kernel] Process {} is killed", current_pid());
+        unsafe { sstatus::set_sie() };
+        exit_current_and_run_next(KILLED_EXIT_CODE);
+    }
+    unsafe { sstatus::clear_sie() };
+    // Replying to the services may switch away, so do it before leaving the kernel trap entry
     resolve_message();
+    // Nothing can trap into the kernel with the user trap entry until `sret`
+    set_user_trap_entry();
     set_next_interrupt();
+    current_task().unwrap().lock().charge_system();
+    // The thread may run on a different hart from the last time
+    current_trap_ctx().hart_id = hart_id();
     let trap_ctx_ptr = TRAP_CONTEXT;
     let user_satp = current_user_token();
     // log!("[kernel] return to user mode, satp = {:#x}", user_satp,);
@@ -0,0 +1,88 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use core::ptr::addr_of;
+use riscv::register::{scause, sepc, sscratch, sstatus, stval};
+
+use crate::config::{MAX_HARTS, OVERFLOW_STACK_SIZE};
+use crate::println;
+use crate::sched::exit_current_and_run_next;
+use crate::sched::preempt::{holds_mutex, preemptible};
+use crate::sched::proc::{hart_id, try_current_task};
+use crate::stack::guarded_kernel_stack;
+
+use super::KILLED_EXIT_CODE;
+
+/// Deepest backtrace printed
+const MAX_BACKTRACE_DEPTH: usize = 32;
+
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+struct OverflowStack([u8; OVERFLOW_STACK_SIZE]);
+
+/// Stacks of the harts for handling a kernel stack overflow
+static mut OVERFLOW_STACKS: [OverflowStack; MAX_HARTS] = [OverflowStack([0; OVERFLOW_STACK_SIZE]); MAX_HARTS];
+
+/// Keep the overflow stack of the current hart in `sscratch` for `__kerneltrap`
+///
+/// `__restore` takes `sscratch` for the trap context when returning to user mode, so this is
+/// done again each time the hart enters the kernel.
+pub fn set_overflow_stack() {
+    let top = unsafe { addr_of!(OVERFLOW_STACKS[hart_id()]) as usize } + OVERFLOW_STACK_SIZE;
+    // The two words above are kept for `__kerneltrap`
+    unsafe { sscratch::write(top - 2 * 8) };
+}
+
+/// Print the return addresses of the frames from `fp`, as long as they are in `[bottom, top)`
+///
+/// The kernel is built with frame pointers, so the return address and the frame pointer of the
+/// caller are kept right below the frame pointer of each frame.
+fn backtrace(mut fp: usize, bottom: usize, top: usize) {
+    println!("[kernel] Backtrace:");
+    for depth in 0..MAX_BACKTRACE_DEPTH {
+        if fp < bottom + 2 * 8 || fp > top || fp % 8 != 0 {
+            break;
+        }
+        let ra = unsafe { *((fp - 8) as *const usize) };
+        println!("  #{} {:#x}", depth, ra);
+        fp = unsafe { *((fp - 2 * 8) as *const usize) };
+    }
+}
+
+/// Handler of a kernel stack overflow, called by `__kerneltrap` on the overflow stack
+///
+/// `sp` and `fp` are the stack and frame pointers when the trap is taken. The thread cannot go
+/// on, but its process is killed if the thread holds no lock, which is when neither interrupts
+/// nor preemption were disabled and no sleeping lock is held. What the frames on its stack own
+/// is leaked. The system panics if not.
+#[no_mangle]
+pub extern "C" fn kernel_stack_overflow(sp: usize, fp: usize) -> ! {
+    let (top, bottom) = guarded_kernel_stack(sp).unwrap();
+    println!(
+        "[kernel] Kernel stack overflow on hart {}: {:?} at sepc = {:#x}, stval = {:#x}, sp = {:#x} below stack [{:#x}, {:#x})",
+        hart_id(),
+        scause::read().cause(),
+        sepc::read(),
+        stval::read(),
+        sp,
+        bottom,
+        top
+    );
+    backtrace(fp, bottom, top);
+    // The processor and the thread may be locked by the thread itself, which are not waited for
+    let thread = try_current_task();
+    let pid = match thread.as_ref().and_then(|thread| thread.try_lock().map(|thread| thread.pid)) {
+        Some(0) => panic!("Kernel stack overflow in a kernel thread"),
+        Some(pid) => pid,
+        None => panic!("Kernel stack overflowed"),
+    };
+    drop(thread);
+    if !sstatus::read().spie() || !preemptible() || holds_mutex() {
+        panic!("Kernel stack of process {} overflowed with locks held", pid);
+    }
+    println!("[kernel] Kernel stack of process {} overflowed, kernel killed it.", pid);
+    exit_current_and_run_next(KILLED_EXIT_CODE);
+    unreachable!()
+}
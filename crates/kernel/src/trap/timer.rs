@@ -5,11 +5,12 @@
 
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
-use crate::sched::proc::current_pid;
+use crate::sched::{proc::current_task, scheduler::next_release};
 use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100; // Interrupts every 10 ms
 const MSEC_PER_SEC: usize = 1000; // Milliseconds per second
+const NSEC_PER_SEC: usize = 1_000_000_000; // Nanoseconds per second
 
 pub fn get_time() -> usize {
     time::read()
@@ -19,10 +20,31 @@ pub fn get_time_ms() -> usize {
     time::read() / (CLOCK_FREQ / MSEC_PER_SEC)
 }
 
+/// Nanoseconds since boot
+pub fn get_time_ns() -> usize {
+    let time = time::read();
+    time / CLOCK_FREQ * NSEC_PER_SEC + time % CLOCK_FREQ * NSEC_PER_SEC / CLOCK_FREQ
+}
+
+/// Arm the timer for the next tick of the current thread, or disarm it for a service
+///
+/// The timer fires earlier when the current real-time thread uses up its runtime, or another one
+/// starts its next period. There may be no current thread when a tick comes in the kernel while a
+/// thread is switching out.
 pub fn set_next_interrupt() {
-    if current_pid() != 0 {
-        set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
-    } else {
-        set_timer(usize::MAX)
+    let Some(thread) = current_task().filter(|thread| thread.lock().pid != 0) else {
+        set_timer(usize::MAX);
+        return;
+    };
+    let now = get_time();
+    let mut next = now + CLOCK_FREQ / TICKS_PER_SEC;
+    let thread_info = thread.lock();
+    if let Some(entity) = thread_info.sched.deadline.as_ref() {
+        next = next.min(entity.budget_end(now, thread_info.cpu_time()));
+    }
+    drop(thread_info);
+    if let Some(release) = next_release() {
+        next = next.min(release);
     }
+    set_timer(next);
 }
@@ -0,0 +1,27 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+// Supervisor interrupt switch, only usable in the kernel
+
+use core::arch::asm;
+
+/// `sstatus.SIE`
+const SSTATUS_SIE: usize = 1 << 1;
+
+/// Disable supervisor interrupts on the current hart, return whether they were enabled
+#[inline]
+pub fn disable() -> bool {
+    let sstatus: usize;
+    unsafe { asm!("csrrci {}, sstatus, 2", out(reg) sstatus) };
+    sstatus & SSTATUS_SIE != 0
+}
+
+/// Enable supervisor interrupts again if they were enabled before `disable`
+#[inline]
+pub fn restore(enabled: bool) {
+    if enabled {
+        unsafe { asm!("csrsi sstatus, 2") };
+    }
+}
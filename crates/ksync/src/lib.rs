@@ -5,8 +5,15 @@
 
 #![no_std]
 
+extern crate alloc;
+
 mod cell;
+pub mod interrupt;
+mod lock;
 pub mod msg;
 
 pub use cell::UPSafeCell;
-
+pub use lock::{
+    Mutex, MutexGuard, Park, RwLock, RwLockReadGuard, RwLockWriteGuard, SpinLock, SpinLockGuard,
+    TicketLock, TicketLockGuard,
+};
@@ -0,0 +1,20 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+// Kernel locks
+//
+// The spinning locks disable supervisor interrupts while being held, so that a trap handler on
+// the same hart never spins on a lock its interrupted code holds. Their guards enable interrupts
+// again on drop if they were enabled on lock, thus guards must be dropped in reverse order.
+
+mod mutex;
+mod rwlock;
+mod spin;
+mod ticket;
+
+pub use mutex::{Mutex, MutexGuard, Park};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use spin::{SpinLock, SpinLockGuard};
+pub use ticket::{TicketLock, TicketLockGuard};
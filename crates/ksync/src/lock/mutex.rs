@@ -0,0 +1,146 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use alloc::collections::VecDeque;
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+use super::SpinLock;
+use crate::interrupt;
+
+/// Scheduler operations a sleeping lock relies on
+pub trait Park {
+    /// Handle of a thread, which keeps it alive while parked
+    type Thread;
+
+    /// Get the handle of the current thread
+    fn current() -> Self::Thread;
+
+    /// Switch away from the current thread, which is not scheduled again until unparked
+    ///
+    /// The thread may have been unparked already, in which case it is to be scheduled again soon.
+    fn park();
+
+    /// Make a parked thread runnable again
+    fn unpark(thread: Self::Thread);
+
+    /// Note that the current thread has taken a lock, with interrupts disabled
+    fn acquired() {}
+
+    /// Note that the current thread has released a lock
+    fn released() {}
+}
+
+struct MutexState<H> {
+    locked: bool,
+    waiters: VecDeque<H>,
+}
+
+/// A lock that parks the threads waiting for it, for sections that may take long
+///
+/// Only usable from thread context, never from a trap handler or the scheduler itself. The lock
+/// is handed over to the first waiter on unlock, so waiters get it in FIFO order.
+pub struct Mutex<T: ?Sized, P: Park> {
+    state: SpinLock<MutexState<P::Thread>>,
+    _park: PhantomData<P>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send, P: Park> Sync for Mutex<T, P> where P::Thread: Send {}
+unsafe impl<T: ?Sized + Send, P: Park> Send for Mutex<T, P> where P::Thread: Send {}
+
+pub struct MutexGuard<'a, T: ?Sized, P: Park> {
+    lock: &'a Mutex<T, P>,
+}
+
+impl<T, P: Park> Mutex<T, P> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: SpinLock::new(MutexState {
+                locked: false,
+                waiters: VecDeque::new(),
+            }),
+            _park: PhantomData,
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized, P: Park> Mutex<T, P> {
+    pub fn lock(&self) -> MutexGuard<'_, T, P> {
+        // Keep interrupts off until switched away, so that the thread is never queued as a waiter
+        // and preempted at the same time
+        let irq_enabled = interrupt::disable();
+        let mut state = self.state.lock();
+        if !state.locked {
+            state.locked = true;
+        } else {
+            state.waiters.push_back(P::current());
+            drop(state);
+            P::park();
+            // The lock has been handed over by `unlock`
+        }
+        P::acquired();
+        interrupt::restore(irq_enabled);
+        MutexGuard { lock: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T, P>> {
+        let mut state = self.state.lock();
+        if state.locked {
+            return None;
+        }
+        state.locked = true;
+        P::acquired();
+        Some(MutexGuard { lock: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    fn unlock(&self) {
+        P::released();
+        let mut state = self.state.lock();
+        match state.waiters.pop_front() {
+            Some(waiter) => {
+                drop(state);
+                P::unpark(waiter);
+            }
+            None => state.locked = false,
+        }
+    }
+}
+
+impl<T: Default, P: Park> Default for Mutex<T, P> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized, P: Park> Deref for MutexGuard<'_, T, P> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized, P: Park> DerefMut for MutexGuard<'_, T, P> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized, P: Park> Drop for MutexGuard<'_, T, P> {
+    fn drop(&mut self) {
+        self.lock.unlock();
+    }
+}
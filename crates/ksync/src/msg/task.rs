@@ -3,12 +3,28 @@
 // This source code is licensed under the MIT license found in the
 // LICENSE file in the root directory of this source tree.
 
+/// CPU time used by a process in clock cycles, split into user and system mode
+#[derive(Clone, Copy, Default)]
+pub struct CpuTimes {
+    pub user: usize,
+    pub system: usize,
+}
+
+impl core::ops::AddAssign for CpuTimes {
+    fn add_assign(&mut self, other: Self) {
+        self.user += other.user;
+        self.system += other.system;
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum PM2Kernel {
     ForkReply { child_pid: usize },
-    WaitPIDReply { result: isize, exit_code: i32 },
+    WaitPIDReply { result: isize, exit_code: i32, times: CpuTimes },
     Recycle { token: usize },
     Remove { token: usize },
+    /// Process owning the user space asked about, or -1 if it is not a process or must not be killed
+    OomVictimReply { pid: isize },
     Invalid,
 }
 
@@ -24,7 +40,9 @@ pub enum Kernel2PM {
     Fork { pid: usize, token: usize },
     Exec { pid: usize, token: usize },
     WaitPID { pid: usize, child_pid: isize },
-    Exit { pid: usize, exit_code: i32 },
+    Exit { pid: usize, exit_code: i32, times: CpuTimes },
+    /// Ask whether the process owning `token` may be the victim of the OOM killer
+    OomVictim { token: usize },
     Invalid,
 }
 
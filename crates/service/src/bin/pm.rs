@@ -8,7 +8,7 @@
 extern crate service;
 
 use ksync::msg::task::{Kernel2PM, PM2Kernel};
-use service::{log, msg::{recv_msg, reply_msg}, task::{exec, exit, fork, init_task_manager, waitpid}};
+use service::{log, msg::{recv_msg, reply_msg}, task::{exec, exit, fork, init_task_manager, oom_victim, waitpid}};
 
 #[no_mangle]
 pub fn main() -> i32 {
@@ -30,12 +30,17 @@ pub fn main() -> i32 {
             },
             Kernel2PM::WaitPID { pid, child_pid } => {
                 log!("[pm] Process {} waits for child pid {}...", pid, child_pid);
-                let (result, exit_code) = waitpid(pid, child_pid);
-                reply_msg(id, PM2Kernel::WaitPIDReply { result, exit_code });
+                let (result, exit_code, times) = waitpid(pid, child_pid);
+                reply_msg(id, PM2Kernel::WaitPIDReply { result, exit_code, times });
             },
-            Kernel2PM::Exit { pid, exit_code } => {
+            Kernel2PM::Exit { pid, exit_code, times } => {
                 log!("Process {} exits with code {}...", pid, exit_code);
-                exit(pid, exit_code);
+                exit(pid, exit_code, times);
+            },
+            Kernel2PM::OomVictim { token } => {
+                let pid = oom_victim(token);
+                log!("[pm] Out of memory, victim is pid {}...", pid);
+                reply_msg(id, PM2Kernel::OomVictimReply { pid });
             },
             _ => panic!("Invalid message"),
         }
@@ -0,0 +1,22 @@
+// Copyright (c) 2024 Conless Pan
+
+// This source code is licensed under the MIT license found in the
+// LICENSE file in the root directory of this source tree.
+
+use super::syscall;
+
+const SYSCALL_IRQ_ATTACH: usize = 1000;
+const SYSCALL_IRQ_WAIT: usize = 1001;
+const SYSCALL_IRQ_ACK: usize = 1002;
+
+pub fn sys_irq_attach(irq: usize) -> isize {
+    syscall(SYSCALL_IRQ_ATTACH, [irq, 0, 0])
+}
+
+pub fn sys_irq_wait(irq: usize) -> isize {
+    syscall(SYSCALL_IRQ_WAIT, [irq, 0, 0])
+}
+
+pub fn sys_irq_ack(irq: usize) -> isize {
+    syscall(SYSCALL_IRQ_ACK, [irq, 0, 0])
+}
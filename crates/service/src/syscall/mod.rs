@@ -4,11 +4,13 @@
 // LICENSE file in the root directory of this source tree.
 
 mod fs;
+mod irq;
 mod process;
 
 use core::arch::asm;
 
 use fs::sys_write;
+use irq::{sys_irq_ack, sys_irq_attach, sys_irq_wait};
 use process::{sys_exit, sys_yield};
 
 fn syscall(id: usize, args: [usize; 3]) -> isize {
@@ -34,3 +36,14 @@ pub fn exit(exit_code: i32) -> ! {
 pub fn yield_() -> isize {
     sys_yield()
 }
+/// Take the interrupt source for the driver implemented in this service
+pub fn irq_attach(irq: usize) -> isize {
+    sys_irq_attach(irq)
+}
+/// Block until the attached interrupt fires, the source stays masked until `irq_ack`
+pub fn irq_wait(irq: usize) -> isize {
+    sys_irq_wait(irq)
+}
+pub fn irq_ack(irq: usize) -> isize {
+    sys_irq_ack(irq)
+}
@@ -4,7 +4,7 @@
 // LICENSE file in the root directory of this source tree.
 
 use alloc::{sync::Arc, sync::Weak, vec::Vec};
-use ksync::UPSafeCell;
+use ksync::{msg::task::CpuTimes, UPSafeCell};
 
 use crate::{
     // mm::{get_kernel_stack, new_user_space_from_token},
@@ -31,6 +31,8 @@ pub struct TaskStructInner {
     pub mm: MMGuard,
     pub parent: Option<Weak<TaskStruct>>,
     pub children: Vec<Arc<TaskStruct>>,
+    /// CPU time of the task and its reaped children, reported when it exits
+    pub times: CpuTimes,
 }
 
 impl TaskStruct {
@@ -53,6 +55,7 @@ impl TaskStruct {
                     mm,
                     parent: None,
                     children: Vec::new(),
+                    times: CpuTimes::default(),
                 })
             },
         }
@@ -75,6 +78,7 @@ impl TaskStruct {
                     mm,
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
+                    times: CpuTimes::default(),
                 })
             },
         });
@@ -9,7 +9,7 @@ use alloc::{
 };
 use lazy_static::lazy_static;
 
-use ksync::{msg::task::PM2Kernel, UPSafeCell};
+use ksync::{msg::task::{CpuTimes, PM2Kernel}, UPSafeCell};
 
 use crate::msg::send_msg;
 
@@ -51,7 +51,7 @@ impl TaskManager {
         task.exec(new_token)
     }
 
-    pub fn waitpid(&mut self, pid: usize, child_pid: isize) -> (isize, i32) {
+    pub fn waitpid(&mut self, pid: usize, child_pid: isize) -> (isize, i32, CpuTimes) {
         let task = self.tasks.get(&pid).unwrap().clone();
 
         let mut inner = task.inner.borrow_mut();
@@ -60,7 +60,7 @@ impl TaskManager {
             .iter()
             .any(|p| child_pid == -1 || child_pid as usize == p.pid.0)
         {
-            return (-1, 0);
+            return (-1, 0, CpuTimes::default());
         }
 
         let pair = inner.children.iter().enumerate().find_map(|(idx, p)| {
@@ -76,16 +76,30 @@ impl TaskManager {
             let child = inner.children.remove(idx);
             assert_eq!(Arc::strong_count(&child), 1);
             let found_pid = child.pid.0;
-            (found_pid as isize, exit_code)
+            let times = child.inner.borrow_mut().times;
+            (found_pid as isize, exit_code, times)
         } else {
-            (-2, 0)
+            (-2, 0, CpuTimes::default())
         }
     }
 
-    pub fn exit(&mut self, pid: usize, exit_code: i32) {
+    /// Pick the process to kill when out of memory, which is the one owning `token` unless it is
+    /// init or not a process, return -1 if there is none
+    pub fn oom_victim(&self, token: usize) -> isize {
+        self.tasks
+            .values()
+            .find(|task| task.inner.borrow_mut().mm.0 == token)
+            .map_or(-1, |task| match task.pid.0 {
+                1 => -1,
+                pid => pid as isize,
+            })
+    }
+
+    pub fn exit(&mut self, pid: usize, exit_code: i32, times: CpuTimes) {
         let task = self.tasks.get(&pid).unwrap().clone();
         let mut task_inner = task.inner.borrow_mut();
         task_inner.status = TaskStatus::Zombie(exit_code);
+        task_inner.times = times;
 
         {
             let init_task = self.tasks.get(&1).unwrap().clone();
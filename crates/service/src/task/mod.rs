@@ -6,6 +6,7 @@
 extern crate alloc;
 
 use alloc::sync::Arc;
+use ksync::msg::task::CpuTimes;
 use info::task_struct::TaskStruct;
 use manager::TASK_MANAGER;
 
@@ -21,12 +22,16 @@ pub fn exec(pid: usize, new_token: usize)  {
     TASK_MANAGER.borrow_mut().exec(pid, new_token)
 }
 
-pub fn waitpid(pid: usize, child_pid: isize) -> (isize, i32) {
+pub fn waitpid(pid: usize, child_pid: isize) -> (isize, i32, CpuTimes) {
     TASK_MANAGER.borrow_mut().waitpid(pid, child_pid)
 }
 
-pub fn exit(pid: usize, exit_code: i32) {
-    TASK_MANAGER.borrow_mut().exit(pid, exit_code)
+pub fn exit(pid: usize, exit_code: i32, times: CpuTimes) {
+    TASK_MANAGER.borrow_mut().exit(pid, exit_code, times)
+}
+
+pub fn oom_victim(token: usize) -> isize {
+    TASK_MANAGER.borrow_mut().oom_victim(token)
 }
 
 pub fn init_task_manager(init_token: usize) -> usize {
@@ -20,6 +20,48 @@ enum BuildMode {
     Release,
 }
 
+/// Extra options of the emulated machine
+#[derive(Clone, Default)]
+struct QemuOptions {
+    /// Raw disk image attached as a virtio block device
+    disk: Option<String>,
+    /// Raw image with a swap signature attached as a second virtio block device
+    swap: Option<String>,
+    /// Number of harts
+    smp: Option<String>,
+}
+
+impl QemuOptions {
+    fn from_matches(matches: &clap::ArgMatches) -> Self {
+        Self {
+            disk: matches.value_of("disk").map(|disk| disk.to_string()),
+            swap: matches.value_of("swap").map(|swap| swap.to_string()),
+            smp: matches.value_of("smp").map(|smp| smp.to_string()),
+        }
+    }
+
+    /// Append the options to the QEMU command
+    fn apply(&self, command: &mut Command) {
+        if let Some(smp) = &self.smp {
+            command.arg("-smp").arg(smp);
+        }
+        if let Some(disk) = &self.disk {
+            command
+                .arg("-drive")
+                .arg(format!("file={},if=none,format=raw,id=x0", disk))
+                .arg("-device")
+                .arg("virtio-blk-device,drive=x0,bus=virtio-mmio-bus.0");
+        }
+        if let Some(swap) = &self.swap {
+            command
+                .arg("-drive")
+                .arg(format!("file={},if=none,format=raw,id=x1", swap))
+                .arg("-device")
+                .arg("virtio-blk-device,drive=x1,bus=virtio-mmio-bus.1");
+        }
+    }
+}
+
 /// Get the root path of the project
 fn project_root() -> PathBuf {
     Path::new(&env!("CARGO_MANIFEST_DIR"))
@@ -158,7 +200,7 @@ fn objcopy(mode: &BuildMode) -> bool {
 }
 
 /// Run kernel in QEMU
-fn qemu_run(mode: &BuildMode) -> bool {
+fn qemu_run(mode: &BuildMode, options: &QemuOptions) -> bool {
     let kernel_bin = project_root()
         .join("target/riscv64gc-unknown-none-elf")
         .join(if let BuildMode::Release = mode {
@@ -184,6 +226,7 @@ fn qemu_run(mode: &BuildMode) -> bool {
         .arg(bios_bin.to_str().unwrap())
         .arg("-device")
         .arg("loader,file=".to_string() + kernel_bin.to_str().unwrap() + ",addr=0x80200000");
+    options.apply(&mut command);
     println!("[run] Running command: {}", command_to_string(&command));
     let status = command.status();
     if let Err(e) = status {
@@ -229,7 +272,7 @@ fn disasm(mode: &BuildMode) -> bool {
     status.unwrap().success()
 }
 
-fn qemu_debug(mode: &BuildMode) -> bool {
+fn qemu_debug(mode: &BuildMode, options: &QemuOptions) -> bool {
     let kernel_bin = project_root()
         .join("target/riscv64gc-unknown-none-elf")
         .join(if let BuildMode::Release = mode {
@@ -257,6 +300,7 @@ fn qemu_debug(mode: &BuildMode) -> bool {
         .arg("loader,file=".to_string() + kernel_bin.to_str().unwrap() + ",addr=0x80200000")
         .arg("-s")
         .arg("-S");
+    options.apply(&mut command);
     println!("[run] Running command: {}", command_to_string(&command));
     let status = command.status();
     if let Err(e) = status {
@@ -302,6 +346,9 @@ fn main() {
         (@subcommand run =>
             (about: "Run kernel in QEMU")
             (@arg release: --release "Run kernel in release mode")
+            (@arg disk: --disk +takes_value "Attach a raw disk image as a virtio block device")
+            (@arg swap: --swap +takes_value "Attach a raw image made by mkswap as the swap device")
+            (@arg smp: --smp +takes_value "Number of harts, also accepted as -smp like QEMU")
         )
         (@subcommand disasm =>
             (about: "Disassemble kernel")
@@ -310,15 +357,25 @@ fn main() {
         (@subcommand debug =>
             (about: "Run kernel in QEMU with GDB")
             (@arg release: --release "Run kernel in release mode")
+            (@arg disk: --disk +takes_value "Attach a raw disk image as a virtio block device")
+            (@arg swap: --swap +takes_value "Attach a raw image made by mkswap as the swap device")
+            (@arg smp: --smp +takes_value "Number of harts, also accepted as -smp like QEMU")
         )
         (@subcommand gdb =>
             (about: "Run GDB")
             (@arg release: --release "Run GDB in release mode")
         )
     )
-    .get_matches();
+    .get_matches_from(env::args().map(|arg| {
+        // Accept the spelling of QEMU
+        if arg == "-smp" {
+            "--smp".to_string()
+        } else {
+            arg
+        }
+    }));
 
-    type TaskFunc = fn(&BuildMode) -> bool;
+    type TaskFunc = dyn Fn(&BuildMode) -> bool;
     let mut task_queue: Vec<(&str, Box<TaskFunc>)> = vec![];
     let mut mode: BuildMode = BuildMode::Debug;
 
@@ -333,7 +390,8 @@ fn main() {
         if matches.is_present("release") {
             mode = BuildMode::Release;
         }
-        task_queue.push(("qemu", Box::new(qemu_run)));
+        let options = QemuOptions::from_matches(matches);
+        task_queue.push(("qemu", Box::new(move |mode| qemu_run(mode, &options))));
     } else if let Some(matches) = matches.subcommand_matches("disasm") {
         if matches.is_present("release") {
             mode = BuildMode::Release;
@@ -343,7 +401,8 @@ fn main() {
         if matches.is_present("release") {
             mode = BuildMode::Release;
         }
-        task_queue.push(("qemu_debug", Box::new(qemu_debug)));
+        let options = QemuOptions::from_matches(matches);
+        task_queue.push(("qemu_debug", Box::new(move |mode| qemu_debug(mode, &options))));
     } else if let Some(matches) = matches.subcommand_matches("gdb") {
         if matches.is_present("release") {
             mode = BuildMode::Release;